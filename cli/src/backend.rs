@@ -0,0 +1,138 @@
+use bgpfu::{
+    Direction, Error, Explanation, OfflineRpslEvaluator, Report, RouteObject, RpslEvaluator,
+    Serials,
+};
+
+use ip::{Any, Prefix, PrefixSet};
+
+use rpsl::{expr::MpFilterExpr, names::AutNum};
+
+/// The operations shared by the IRRd and RPSL database dump evaluators.
+pub(crate) trait Backend {
+    /// Evaluate `expr`, substituting `peer_as` for `PeerAS`, if given.
+    fn evaluate(
+        &mut self,
+        expr: MpFilterExpr,
+        peer_as: Option<AutNum>,
+    ) -> Result<(PrefixSet<Any>, Report), Error>;
+
+    /// Evaluate and explain `expr`, substituting `peer_as` for `PeerAS`, if given.
+    fn explain(
+        &mut self,
+        expr: MpFilterExpr,
+        peer_as: Option<AutNum>,
+    ) -> Result<Explanation, Error>;
+
+    /// Compile the routing policy of `autnum` for its peering with `peer_as`.
+    fn peer_policy(
+        &mut self,
+        autnum: AutNum,
+        peer_as: AutNum,
+        direction: Direction,
+    ) -> Result<MpFilterExpr, Error>;
+
+    /// Find the route objects for `prefix`, and optionally its less-specific and more-specific
+    /// prefixes.
+    fn origins(
+        &self,
+        prefix: Prefix<Any>,
+        less_specific: bool,
+        more_specific: bool,
+    ) -> Result<Vec<RouteObject>, Error>;
+
+    /// Get the current serials of the IRR databases, if known.
+    fn serials(&self) -> Result<Serials, Error>;
+}
+
+impl Backend for RpslEvaluator {
+    fn evaluate(
+        &mut self,
+        expr: MpFilterExpr,
+        peer_as: Option<AutNum>,
+    ) -> Result<(PrefixSet<Any>, Report), Error> {
+        match peer_as {
+            Some(peer_as) => self.evaluate_for_peer_with_report(expr, peer_as),
+            None => self.evaluate_with_report(expr),
+        }
+    }
+
+    fn explain(
+        &mut self,
+        expr: MpFilterExpr,
+        peer_as: Option<AutNum>,
+    ) -> Result<Explanation, Error> {
+        match peer_as {
+            Some(peer_as) => self.explain_for_peer(expr, peer_as),
+            None => self.explain(expr),
+        }
+    }
+
+    fn peer_policy(
+        &mut self,
+        autnum: AutNum,
+        peer_as: AutNum,
+        direction: Direction,
+    ) -> Result<MpFilterExpr, Error> {
+        self.peer_policy(autnum, peer_as, direction)
+    }
+
+    fn origins(
+        &self,
+        prefix: Prefix<Any>,
+        less_specific: bool,
+        more_specific: bool,
+    ) -> Result<Vec<RouteObject>, Error> {
+        self.origins(prefix, less_specific, more_specific)
+    }
+
+    fn serials(&self) -> Result<Serials, Error> {
+        self.serials()
+    }
+}
+
+impl Backend for OfflineRpslEvaluator {
+    fn evaluate(
+        &mut self,
+        expr: MpFilterExpr,
+        peer_as: Option<AutNum>,
+    ) -> Result<(PrefixSet<Any>, Report), Error> {
+        match peer_as {
+            Some(peer_as) => self.evaluate_for_peer_with_report(expr, peer_as),
+            None => self.evaluate_with_report(expr),
+        }
+    }
+
+    fn explain(
+        &mut self,
+        expr: MpFilterExpr,
+        peer_as: Option<AutNum>,
+    ) -> Result<Explanation, Error> {
+        match peer_as {
+            Some(peer_as) => self.explain_for_peer(expr, peer_as),
+            None => self.explain(expr),
+        }
+    }
+
+    fn peer_policy(
+        &mut self,
+        autnum: AutNum,
+        peer_as: AutNum,
+        direction: Direction,
+    ) -> Result<MpFilterExpr, Error> {
+        self.peer_policy(autnum, peer_as, direction)
+    }
+
+    fn origins(
+        &self,
+        prefix: Prefix<Any>,
+        less_specific: bool,
+        more_specific: bool,
+    ) -> Result<Vec<RouteObject>, Error> {
+        Ok(self.origins(prefix, less_specific, more_specific))
+    }
+
+    fn serials(&self) -> Result<Serials, Error> {
+        // database dumps do not record the serial from which they were exported
+        Ok(Serials::default())
+    }
+}
@@ -1,16 +1,31 @@
-use bgpfu::RpslEvaluator;
+use std::{
+    fmt,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use clap::Parser;
+use anyhow::Context as _;
+
+use bgpfu::{
+    AsPathFilter, Difference, Direction, DiskCache, EvaluatorBuilder, Explanation, Limits, Linter,
+    OfflineRpslEvaluator, Report, Rov, RpslEvaluator, Sanitiser, Snapshot,
+};
+
+use clap::{Args, Parser, Subcommand};
 
 use clap_verbosity_flag::{Verbosity, WarnLevel};
 
-use ip::traits::PrefixSet as _;
+use ip::{concrete::Afi, traits::PrefixSet as _, Any, Prefix, PrefixSet};
 
-use rpsl::expr::MpFilterExpr;
+use rpsl::{
+    expr::{AsSetMember, MpFilterExpr},
+    names::{AsSet, AutNum},
+};
 
 use tracing_log::AsTrace;
 
-use crate::Format;
+use crate::{Backend, Format, RovAction};
 
 /// Entry-point function for the `bgpfu` CLI tool.
 #[allow(clippy::missing_errors_doc)]
@@ -21,16 +36,324 @@ pub fn main() -> anyhow::Result<()> {
         .with_max_level(args.verbosity.log_level_filter().as_trace())
         .try_init()
         .map_err(|err| anyhow::anyhow!(err))?;
-    RpslEvaluator::new(args.host(), args.port())?
-        .evaluate(args.filter())?
-        .ranges()
-        .for_each(|range| println!("{range}"));
+    let rov = args
+        .rov()
+        .map(|path| Rov::load(path).map(|rov| rov.action(args.rov_action().into())))
+        .transpose()?;
+    let sanitiser = args.sanitise().then(|| {
+        Sanitiser::default()
+            .ipv4_max_length(args.ipv4_max_length())
+            .ipv6_max_length(args.ipv6_max_length())
+    });
+    if let Some(Command::MemberOf(lookup)) = args.command() {
+        anyhow::ensure!(
+            args.dumps().is_empty(),
+            "member-of requires an IRRd server, and cannot be used with --dump"
+        );
+        for member_of in connect(&args, None)?.member_of(lookup.member(), lookup.recursive())? {
+            println!("{member_of}");
+        }
+        return Ok(());
+    }
+    if let Some(Command::Diff(diff)) = args.command() {
+        return diff_snapshots(diff);
+    }
+    if let Some(Command::Snapshot(take)) = args.command() {
+        let mut backend = backend(&args, rov)?;
+        let serials = backend.serials()?;
+        let (set, report) = backend.evaluate(take.filter().clone(), args.peer_as())?;
+        warn_report(&report);
+        let snapshot = Snapshot::new(take.filter(), sanitise(sanitiser.as_ref(), set), serials);
+        if let Some(path) = take.output() {
+            snapshot.save(path)?;
+        } else {
+            snapshot.to_json(std::io::stdout().lock())?;
+        }
+        return Ok(());
+    }
+    if let Some(Command::Compare(compare)) = args.command() {
+        anyhow::ensure!(
+            args.dumps().is_empty(),
+            "compare requires IRRd servers, and cannot be used with --dump"
+        );
+        return compare_targets(&args, compare);
+    }
+    if let Some(Command::Lint(lint)) = args.command() {
+        anyhow::ensure!(
+            args.dumps().is_empty(),
+            "lint requires an IRRd server, and cannot be used with --dump"
+        );
+        let report = connect(&args, None)?.lint(lint.as_set(), &lint.linter())?;
+        for problem in report.problems() {
+            println!("{problem}");
+        }
+        anyhow::ensure!(
+            report.is_clean(),
+            "found {} problems with {}",
+            report.problems().len(),
+            report.as_set()
+        );
+        return Ok(());
+    }
+    if let Some(Command::Origins(lookup)) = args.command() {
+        let backend = backend(&args, None)?;
+        for route in backend.origins(
+            lookup.prefix(),
+            lookup.less_specific(),
+            lookup.more_specific(),
+        )? {
+            println!("{:<13} {route}", route.specificity());
+        }
+        return Ok(());
+    }
+    let (format, name) = (args.format(), args.name().to_owned());
+    let (set, report, explanation) = evaluate(args, rov)?;
+    warn_report(&report);
+    if let Some(explanation) = explanation.as_ref().filter(|_| format.is_as_path()) {
+        print_as_path(format, &name, explanation);
+        return Ok(());
+    }
+    let set = sanitise(sanitiser.as_ref(), set);
+    for range in set.ranges() {
+        println!("{range}");
+        if let Some(explanation) = &explanation {
+            let mut derivations = explanation.contributors(&range).peekable();
+            if derivations.peek().is_none() {
+                println!("    (prefix literal)");
+            }
+            derivations.for_each(|derivation| println!("    {derivation}"));
+        }
+    }
+    Ok(())
+}
+
+/// Evaluate the filter expression or routing policy given in `args`, using either an IRRd server
+/// or the given RPSL database dumps.
+fn evaluate(
+    args: Cli,
+    rov: Option<Rov>,
+) -> anyhow::Result<(PrefixSet<Any>, Report, Option<Explanation>)> {
+    let mut backend = backend(&args, rov)?;
+    let explain = args.explain() || args.format().is_as_path();
+    let (expr, peer_as) = if let Some(Command::PeerPolicy(policy)) = args.command() {
+        let expr = backend.peer_policy(policy.autnum(), policy.peer(), policy.direction())?;
+        tracing::info!(
+            "compiled the {} policy of {} for peer {} to '{expr}'",
+            policy.direction(),
+            policy.autnum(),
+            policy.peer()
+        );
+        (expr, Some(policy.peer()))
+    } else {
+        let peer_as = args.peer_as();
+        let expr = args.filter().context("no filter expression given")?;
+        (expr, peer_as)
+    };
+    let evaluated = if explain {
+        let explanation = backend.explain(expr, peer_as)?;
+        (
+            explanation.output().clone(),
+            explanation.report().clone(),
+            Some(explanation),
+        )
+    } else {
+        let (set, report) = backend.evaluate(expr, peer_as)?;
+        (set, report, None)
+    };
+    Ok(evaluated)
+}
+
+/// Construct the backend given in `args`, using either an IRRd server or the given RPSL database
+/// dumps.
+fn backend(args: &Cli, rov: Option<Rov>) -> anyhow::Result<Box<dyn Backend>> {
+    if args.dumps().is_empty() {
+        return Ok(Box::new(connect(args, rov)?));
+    }
+    let mut evaluator = OfflineRpslEvaluator::load(args.dumps())?
+        .strict(args.strict())
+        .limits(args.limits());
+    if let Some(rov) = rov {
+        evaluator = evaluator.rov(rov);
+    }
+    Ok(Box::new(evaluator))
+}
+
+/// Connect to the IRRd server given in `args`.
+fn connect(args: &Cli, rov: Option<Rov>) -> anyhow::Result<RpslEvaluator> {
+    let mut builder = configure(
+        args.fallback_hosts().iter().fold(
+            RpslEvaluator::builder(args.host(), args.port()),
+            |builder, host| builder.fallback(host, args.port()),
+        ),
+        args,
+    )
+    .sources(args.sources())
+    .aggregate(args.aggregate());
+    if let Some(dir) = args.cache_dir() {
+        builder = builder.disk_cache(DiskCache::new(dir, args.cache_ttl()));
+    }
+    if let Some(rov) = rov {
+        builder = builder.rov(rov);
+    }
+    Ok(builder.build()?)
+}
+
+/// Remove bogons and over-specific prefixes from `set` using `sanitiser`, if given.
+fn sanitise(sanitiser: Option<&Sanitiser>, set: PrefixSet<Any>) -> PrefixSet<Any> {
+    let Some(sanitiser) = sanitiser else {
+        return set;
+    };
+    let (set, report) = sanitiser.sanitise(&set);
+    if !report.is_empty() {
+        tracing::warn!(
+            "removed {} bogon and {} over-specific prefix ranges",
+            report.bogons().ranges().count(),
+            report.too_specific().ranges().count()
+        );
+    }
+    set
+}
+
+/// Apply the evaluation options given in `args` that are independent of the IRRd server.
+fn configure(builder: EvaluatorBuilder, args: &Cli) -> EvaluatorBuilder {
+    builder
+        .connections(args.connections())
+        .strict(args.strict())
+        .limits(args.limits())
+}
+
+/// Evaluate the filter expression given in `compare` against each of its targets, and print the
+/// differences between the first target and each of the others, for each address family.
+fn compare_targets(args: &Cli, compare: &Compare) -> anyhow::Result<()> {
+    let targets = compare.targets(args);
+    anyhow::ensure!(
+        targets.len() > 1,
+        "compare requires at least two servers or source sets"
+    );
+    let sets = targets
+        .iter()
+        .map(|target| {
+            tracing::info!("evaluating '{}' against {target}", compare.filter());
+            // the disk cache is not keyed by server, so would hide the differences sought
+            let mut evaluator = configure(RpslEvaluator::builder(&target.host, target.port), args)
+                .sources(&target.sources)
+                .build()?;
+            let (set, report) =
+                Backend::evaluate(&mut evaluator, compare.filter().clone(), args.peer_as())?;
+            warn_report(&report);
+            Ok(set)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let (baseline, baseline_set) = (&targets[0], &sets[0]);
+    for (target, set) in targets.iter().zip(&sets).skip(1) {
+        println!("{target} compared with {baseline}:");
+        print_difference(&Difference::between(baseline_set, set), baseline, target);
+    }
     Ok(())
 }
 
+/// Load the snapshots given in `diff`, and print the differences between them, for each address
+/// family.
+fn diff_snapshots(diff: &Diff) -> anyhow::Result<()> {
+    let (old, new) = (
+        Snapshot::load(diff.old_path())?,
+        Snapshot::load(diff.new_path())?,
+    );
+    if old.expression() != new.expression() {
+        tracing::warn!(
+            "comparing snapshots of different expressions '{}' and '{}'",
+            old.expression(),
+            new.expression()
+        );
+    }
+    let (old_path, new_path) = (diff.old_path().display(), diff.new_path().display());
+    println!("{new_path} compared with {old_path}:");
+    for (source, serial) in new.serials().iter() {
+        let previous = old.serials().get(source);
+        if previous != serial {
+            let show =
+                |serial: Option<u64>| serial.map_or_else(|| "-".to_owned(), |s| s.to_string());
+            println!("  {source} serial: {} -> {}", show(previous), show(serial));
+        }
+    }
+    print_difference(&old.diff(&new), &old_path, &new_path);
+    Ok(())
+}
+
+/// Print the prefix ranges only in `old`, and those only in `new`, for each address family.
+fn print_difference(diff: &Difference, old: &dyn fmt::Display, new: &dyn fmt::Display) {
+    for afi in [Afi::Ipv4, Afi::Ipv6] {
+        let diff = diff.afi(afi);
+        if diff.is_empty() {
+            println!("  {afi}: no differences");
+            continue;
+        }
+        println!(
+            "  {afi}: {} ranges only in {old}, {} ranges only in {new}",
+            diff.removed().ranges().count(),
+            diff.added().ranges().count()
+        );
+        diff.removed()
+            .ranges()
+            .for_each(|range| println!("  - {range}"));
+        diff.added()
+            .ranges()
+            .for_each(|range| println!("  + {range}"));
+    }
+}
+
+/// Print the AS path filter derived from `explanation`, in `format`.
+fn print_as_path(format: Format, name: &str, explanation: &Explanation) {
+    let filter = AsPathFilter::from(explanation);
+    let unattributed = filter.unattributed().ranges().count();
+    if unattributed > 0 {
+        tracing::warn!(
+            "{unattributed} prefix ranges could not be attributed to an origin, and are not matched by the AS path filter"
+        );
+    }
+    if filter.origins().is_empty() {
+        tracing::warn!("no origins were found, so no AS paths are matched");
+    }
+    match format {
+        Format::AsPath => filter
+            .origin_regex()
+            .into_iter()
+            .for_each(|regex| println!("{regex}")),
+        Format::AsPathGroup => {
+            println!("as-path-group {name} {{");
+            for (i, regex) in filter
+                .origin_regexes(AS_PATH_GROUP_TERMS)
+                .into_iter()
+                .enumerate()
+            {
+                println!("    as-path a{i} \"{regex}\";");
+            }
+            println!("}}");
+        }
+        Format::Plain => {}
+    }
+}
+
+/// Maximum number of AS numbers or ranges in each member of a generated as-path-group.
+const AS_PATH_GROUP_TERMS: usize = 64;
+
+/// Summarise the non-fatal errors encountered during evaluation, if any.
+fn warn_report(report: &Report) {
+    if !report.is_empty() {
+        tracing::warn!(
+            "evaluation encountered {} non-fatal errors involving {}",
+            report.len(),
+            report.names().collect::<Vec<_>>().join(", ")
+        );
+    }
+}
+
 /// An IRR query and filter generation toolset.
+///
+/// Options must be given before any subcommand.
 #[derive(Debug, Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true)]
+#[allow(clippy::struct_excessive_bools)]
 struct Cli {
     /// IRRd server hostname or IP address.
     #[arg(short = 'H', long, default_value = "whois.radb.net")]
@@ -40,17 +363,277 @@ struct Cli {
     #[arg(short = 'P', long, default_value_t = 43)]
     port: u16,
 
+    /// Fallback IRRd server hostname or IP address.
+    ///
+    /// May be given multiple times. Servers are tried in the order given if `--host` cannot be
+    /// reached.
+    #[arg(long = "fallback-host", value_name = "HOST")]
+    fallback_hosts: Vec<String>,
+
+    /// Number of concurrent connections to open to the IRRd server.
+    #[arg(short = 'c', long, default_value = "1")]
+    connections: NonZeroUsize,
+
+    /// Comma-separated list of IRR databases to query, in order of preference.
+    ///
+    /// If not specified, the IRRd server's default sources are used.
+    #[arg(short = 's', long, value_name = "SOURCES", value_delimiter = ',')]
+    sources: Vec<String>,
+
+    /// Directory in which to cache IRR responses.
+    ///
+    /// If the IRRd server is unreachable, expired cache entries are used instead.
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Time, in seconds, after which cached IRR responses expire.
+    #[arg(long, value_name = "SECONDS", default_value_t = 86400)]
+    cache_ttl: u64,
+
+    /// Fetch the routes of each as-set as aggregated prefixes, where the IRRd server supports it.
+    ///
+    /// This requires far fewer queries for large as-sets, but the result contains the aggregated
+    /// prefixes rather than the routes themselves, which changes the effect of range operators
+    /// such as `^-`, and errors resolving the members of an as-set are not reported.
+    #[arg(long)]
+    aggregate: bool,
+
+    /// RPSL database dump to resolve names from, instead of querying an IRRd server.
+    ///
+    /// May be given multiple times, and may be `gzip` compressed. Where the same set is defined
+    /// in more than one dump, the first dump given takes precedence.
+    #[arg(short = 'd', long = "dump", value_name = "FILE")]
+    dumps: Vec<PathBuf>,
+
+    /// Validated ROA payloads to perform RPKI route origin validation against.
+    ///
+    /// The file must be in the JSON export format of either rpki-client or Routinator.
+    #[arg(long, value_name = "FILE")]
+    rov: Option<PathBuf>,
+
+    /// Action to take for RPKI-invalid routes when `--rov` is given.
+    #[arg(long, value_enum, value_name = "ACTION", default_value_t = RovAction::Drop)]
+    rov_action: RovAction,
+
+    /// Treat every error encountered during evaluation as fatal.
+    ///
+    /// By default, errors such as a missing as-set are logged, and evaluation continues as if the
+    /// object were empty.
+    #[arg(long)]
+    strict: bool,
+
+    /// Maximum depth of as-set nesting below each evaluated as-set.
+    #[arg(long, value_name = "DEPTH")]
+    max_depth: Option<usize>,
+
+    /// Maximum number of autonomous systems to resolve.
+    #[arg(long, value_name = "N")]
+    max_autnums: Option<usize>,
+
+    /// Maximum number of prefix ranges of each address family to resolve from IRR objects.
+    #[arg(long, value_name = "N")]
+    max_prefixes: Option<usize>,
+
+    /// Maximum time, in seconds, that evaluation may take.
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
+
+    /// Explain which IRR objects contributed each prefix range.
+    ///
+    /// Each range is followed by the chain of sets, aut-nums and route objects through which it
+    /// was reached. This requires additional IRR queries.
+    #[arg(long)]
+    explain: bool,
+
+    /// Remove bogons and over-specific prefixes from the evaluated prefix set.
+    #[arg(long)]
+    sanitise: bool,
+
+    /// Maximum length of IPv4 prefixes when `--sanitise` is given.
+    #[arg(long, value_name = "LENGTH", default_value_t = 24)]
+    ipv4_max_length: u8,
+
+    /// Maximum length of IPv6 prefixes when `--sanitise` is given.
+    #[arg(long, value_name = "LENGTH", default_value_t = 48)]
+    ipv6_max_length: u8,
+
     #[command(flatten)]
     verbosity: Verbosity<WarnLevel>,
 
     /// Output format.
+    ///
+    /// The AS path formats match the origins of the routes contributing to the evaluated prefix
+    /// set, which requires the same additional IRR queries as `--explain`.
     #[arg(short, long, value_enum, default_value_t = Format::Plain)]
     format: Format,
 
+    /// Name of the as-path-group when `--format as-path-group` is given.
+    #[arg(long, value_name = "NAME", default_value = "bgpfu")]
+    name: String,
+
+    /// Peer autonomous system to substitute for `PeerAS` in the filter expression.
+    #[arg(short = 'p', long)]
+    peer_as: Option<AutNum>,
+
+    /// RPSL mp-filter expression to evaluate.
+    #[arg(required = true)]
+    filter: Option<MpFilterExpr>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Alternatives to evaluating a filter expression.
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Evaluate the routing policy that an aut-num publishes for one of its peerings.
+    PeerPolicy(PeerPolicy),
+    /// List the as-sets that contain an aut-num or as-set, along with their sources.
+    MemberOf(MemberOf),
+    /// List the route and route6 objects for a prefix, along with their origins and sources.
+    Origins(Origins),
+    /// Check an as-set, and those nested within it, for common problems.
+    Lint(Lint),
+    /// Evaluate a filter expression against several IRRd servers or source sets, and list the
+    /// differences between the results.
+    Compare(Compare),
+    /// Evaluate a filter expression, and save the result as a snapshot for later comparison.
+    Snapshot(TakeSnapshot),
+    /// List the differences between two snapshots.
+    Diff(Diff),
+}
+
+/// Arguments to the `peer-policy` subcommand.
+#[derive(Debug, Args)]
+struct PeerPolicy {
+    /// Autonomous system whose aut-num object contains the policy.
+    autnum: AutNum,
+
+    /// Peer autonomous system, which is also substituted for `PeerAS` in the policy filters.
+    #[arg(long, value_name = "AUTNUM")]
+    peer: AutNum,
+
+    /// Evaluate the mp-import and import policy. This is the default.
+    #[arg(long, conflicts_with = "export")]
+    import: bool,
+
+    /// Evaluate the mp-export and export policy.
+    #[arg(long)]
+    export: bool,
+}
+
+/// Arguments to the `member-of` subcommand.
+#[derive(Debug, Args)]
+struct MemberOf {
+    /// Autonomous system or as-set to look up.
+    member: AsSetMember,
+
+    /// Also list the as-sets that contain those found, recursively.
+    #[arg(short, long)]
+    recursive: bool,
+}
+
+/// Arguments to the `origins` subcommand.
+#[derive(Debug, Args)]
+struct Origins {
+    /// Prefix to look up.
+    prefix: Prefix<Any>,
+
+    /// Also list the objects for less-specific prefixes covering the prefix.
+    #[arg(short, long)]
+    less_specific: bool,
+
+    /// Also list the objects for more-specific prefixes covered by the prefix.
+    #[arg(short, long)]
+    more_specific: bool,
+}
+
+/// Arguments to the `lint` subcommand.
+#[derive(Debug, Args)]
+struct Lint {
+    /// As-set to check.
+    as_set: AsSet,
+
+    /// Comma-separated list of IRR databases that are authoritative for aut-num objects.
+    ///
+    /// If not specified, the databases of the Regional Internet Registries are used.
+    #[arg(long, value_name = "SOURCES", value_delimiter = ',')]
+    authoritative_sources: Vec<String>,
+
+    /// Number of direct members above which an as-set is reported as unusually large.
+    #[arg(long, value_name = "COUNT", default_value_t = 500)]
+    max_members: usize,
+
+    /// Number of autonomous systems above which the as-set is reported as unusually large.
+    #[arg(long, value_name = "COUNT", default_value_t = 5000)]
+    max_autnums: usize,
+}
+
+/// Arguments to the `compare` subcommand.
+///
+/// Each server given is queried with each set of sources given, and the results are compared
+/// with those of the first. Responses are never cached.
+#[derive(Debug, Args)]
+struct Compare {
+    /// IRRd server to evaluate against, with an optional port number.
+    ///
+    /// May be given multiple times. If not specified, the global `--host` and `--port` are used.
+    #[arg(long = "host", value_name = "HOST[:PORT]")]
+    hosts: Vec<String>,
+
+    /// Comma-separated list of IRR databases to evaluate against.
+    ///
+    /// May be given multiple times. If not specified, the global `--sources` are used.
+    #[arg(long = "sources", value_name = "SOURCES")]
+    source_sets: Vec<String>,
+
     /// RPSL mp-filter expression to evaluate.
     filter: MpFilterExpr,
 }
 
+/// Arguments to the `snapshot` subcommand.
+#[derive(Debug, Args)]
+struct TakeSnapshot {
+    /// File to save the snapshot to. If not specified, it is written to standard output.
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// RPSL mp-filter expression to evaluate.
+    filter: MpFilterExpr,
+}
+
+/// Arguments to the `diff` subcommand.
+#[derive(Debug, Args)]
+struct Diff {
+    /// Earlier snapshot.
+    old: PathBuf,
+
+    /// Later snapshot.
+    new: PathBuf,
+}
+
+/// An IRRd server and set of sources against which an expression is evaluated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Target {
+    host: String,
+    port: u16,
+    sources: Vec<String>,
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)?;
+        } else {
+            write!(f, "{}:{}", self.host, self.port)?;
+        }
+        if !self.sources.is_empty() {
+            write!(f, " ({})", self.sources.join(","))?;
+        }
+        Ok(())
+    }
+}
+
 impl Cli {
     /// Get the IRRd server hostname.
     #[must_use]
@@ -64,10 +647,310 @@ impl Cli {
         self.port
     }
 
+    /// Get the fallback IRRd server hostnames.
+    #[must_use]
+    fn fallback_hosts(&self) -> &[String] {
+        &self.fallback_hosts
+    }
+
+    /// Get the number of IRRd server connections.
+    #[must_use]
+    const fn connections(&self) -> NonZeroUsize {
+        self.connections
+    }
+
+    /// Get the IRR sources to query.
+    #[must_use]
+    fn sources(&self) -> &[String] {
+        &self.sources
+    }
+
+    /// Get the IRR response cache directory, if any.
+    #[must_use]
+    fn cache_dir(&self) -> Option<&Path> {
+        self.cache_dir.as_deref()
+    }
+
+    /// Get the IRR response cache TTL.
+    #[must_use]
+    const fn cache_ttl(&self) -> Duration {
+        Duration::from_secs(self.cache_ttl)
+    }
+
+    /// Get whether to fetch the routes of as-sets as aggregated prefixes.
+    #[must_use]
+    const fn aggregate(&self) -> bool {
+        self.aggregate
+    }
+
+    /// Get the RPSL database dumps to resolve names from.
+    #[must_use]
+    fn dumps(&self) -> &[PathBuf] {
+        &self.dumps
+    }
+
+    /// Get the validated ROA payload file, if any.
+    #[must_use]
+    fn rov(&self) -> Option<&Path> {
+        self.rov.as_deref()
+    }
+
+    /// Get the action to take for RPKI-invalid routes.
+    #[must_use]
+    const fn rov_action(&self) -> RovAction {
+        self.rov_action
+    }
+
+    /// Get whether evaluation errors are fatal.
+    #[must_use]
+    const fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Get the limits on evaluation resources.
+    #[must_use]
+    fn limits(&self) -> Limits {
+        let mut limits = Limits::default();
+        if let Some(depth) = self.max_depth {
+            limits = limits.max_depth(depth);
+        }
+        if let Some(autnums) = self.max_autnums {
+            limits = limits.max_autnums(autnums);
+        }
+        if let Some(prefixes) = self.max_prefixes {
+            limits = limits.max_prefixes(prefixes);
+        }
+        if let Some(timeout) = self.timeout {
+            limits = limits.timeout(Duration::from_secs(timeout));
+        }
+        limits
+    }
+
+    /// Get whether to explain the evaluated prefix set.
+    #[must_use]
+    const fn explain(&self) -> bool {
+        self.explain
+    }
+
+    /// Get whether to sanitise the evaluated prefix set.
+    #[must_use]
+    const fn sanitise(&self) -> bool {
+        self.sanitise
+    }
+
+    /// Get the maximum length of IPv4 prefixes.
+    #[must_use]
+    const fn ipv4_max_length(&self) -> u8 {
+        self.ipv4_max_length
+    }
+
+    /// Get the maximum length of IPv6 prefixes.
+    #[must_use]
+    const fn ipv6_max_length(&self) -> u8 {
+        self.ipv6_max_length
+    }
+
+    /// Get the output format.
+    #[must_use]
+    const fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Get the name of the generated as-path-group.
+    #[must_use]
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the peer autonomous system, if any.
+    #[must_use]
+    const fn peer_as(&self) -> Option<AutNum> {
+        self.peer_as
+    }
+
+    /// Get the subcommand, if any.
+    #[must_use]
+    const fn command(&self) -> Option<&Command> {
+        self.command.as_ref()
+    }
+
     /// Get object to query.
     #[allow(clippy::missing_const_for_fn)]
     #[must_use]
-    fn filter(self) -> MpFilterExpr {
+    fn filter(self) -> Option<MpFilterExpr> {
         self.filter
     }
 }
+
+impl MemberOf {
+    /// Get the autonomous system or as-set to look up.
+    #[must_use]
+    const fn member(&self) -> &AsSetMember {
+        &self.member
+    }
+
+    /// Get whether to look up containing as-sets recursively.
+    #[must_use]
+    const fn recursive(&self) -> bool {
+        self.recursive
+    }
+}
+
+impl Origins {
+    /// Get the prefix to look up.
+    #[must_use]
+    const fn prefix(&self) -> Prefix<Any> {
+        self.prefix
+    }
+
+    /// Get whether to include less-specific prefixes.
+    #[must_use]
+    const fn less_specific(&self) -> bool {
+        self.less_specific
+    }
+
+    /// Get whether to include more-specific prefixes.
+    #[must_use]
+    const fn more_specific(&self) -> bool {
+        self.more_specific
+    }
+}
+
+impl TakeSnapshot {
+    /// Get the file to save the snapshot to.
+    #[must_use]
+    fn output(&self) -> Option<&Path> {
+        self.output.as_deref()
+    }
+
+    /// Get the filter expression to evaluate.
+    #[must_use]
+    const fn filter(&self) -> &MpFilterExpr {
+        &self.filter
+    }
+}
+
+impl Diff {
+    /// Get the path of the earlier snapshot.
+    #[must_use]
+    fn old_path(&self) -> &Path {
+        &self.old
+    }
+
+    /// Get the path of the later snapshot.
+    #[must_use]
+    fn new_path(&self) -> &Path {
+        &self.new
+    }
+}
+
+impl Compare {
+    /// Get the filter expression to evaluate.
+    #[must_use]
+    const fn filter(&self) -> &MpFilterExpr {
+        &self.filter
+    }
+
+    /// Get every combination of the servers and sources to evaluate against, falling back to
+    /// those given in `args`.
+    #[must_use]
+    fn targets(&self, args: &Cli) -> Vec<Target> {
+        let hosts: Vec<_> = if self.hosts.is_empty() {
+            vec![(args.host().to_owned(), args.port())]
+        } else {
+            self.hosts
+                .iter()
+                .map(|host| split_port(host, args.port()))
+                .collect()
+        };
+        let source_sets: Vec<Vec<String>> = if self.source_sets.is_empty() {
+            vec![args.sources().to_vec()]
+        } else {
+            self.source_sets
+                .iter()
+                .map(|sources| {
+                    sources
+                        .split(',')
+                        .map(|source| source.trim().to_ascii_uppercase())
+                        .filter(|source| !source.is_empty())
+                        .collect()
+                })
+                .collect()
+        };
+        hosts
+            .iter()
+            .flat_map(|(host, port)| {
+                source_sets.iter().map(|sources| Target {
+                    host: host.clone(),
+                    port: *port,
+                    sources: sources.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Split an optional port number from `host`, which may be a hostname, an IPv4 address, or an
+/// IPv6 address in brackets if a port number is given.
+fn split_port(host: &str, default_port: u16) -> (String, u16) {
+    if let Some((addr, port)) = host
+        .strip_prefix('[')
+        .and_then(|host| host.split_once("]:"))
+    {
+        if let Ok(port) = port.parse() {
+            return (addr.to_owned(), port);
+        }
+    }
+    match host.split_once(':') {
+        Some((name, port)) if !port.contains(':') => port.parse().map_or_else(
+            |_| (host.to_owned(), default_port),
+            |port| (name.to_owned(), port),
+        ),
+        _ => (host.trim_matches(['[', ']']).to_owned(), default_port),
+    }
+}
+
+impl Lint {
+    /// Get the as-set to check.
+    #[must_use]
+    const fn as_set(&self) -> &AsSet {
+        &self.as_set
+    }
+
+    /// Get the linter configured by the arguments.
+    #[must_use]
+    fn linter(&self) -> Linter {
+        let linter = Linter::default()
+            .max_members(self.max_members)
+            .max_autnums(self.max_autnums);
+        if self.authoritative_sources.is_empty() {
+            linter
+        } else {
+            linter.authoritative_sources(&self.authoritative_sources)
+        }
+    }
+}
+
+impl PeerPolicy {
+    /// Get the autonomous system whose policy is evaluated.
+    #[must_use]
+    const fn autnum(&self) -> AutNum {
+        self.autnum
+    }
+
+    /// Get the peer autonomous system.
+    #[must_use]
+    const fn peer(&self) -> AutNum {
+        self.peer
+    }
+
+    /// Get the direction of the policy to evaluate.
+    #[must_use]
+    const fn direction(&self) -> Direction {
+        if self.export {
+            Direction::Export
+        } else {
+            Direction::Import
+        }
+    }
+}
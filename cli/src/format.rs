@@ -4,4 +4,15 @@ use clap::ValueEnum;
 pub(crate) enum Format {
     /// Plain text output
     Plain,
+    /// AS path regular expression matching the allowed origins
+    AsPath,
+    /// Junos OS as-path-group matching the allowed origins
+    AsPathGroup,
+}
+
+impl Format {
+    /// Returns `true` if the output is an AS path filter rather than a prefix set.
+    pub(crate) const fn is_as_path(self) -> bool {
+        matches!(self, Self::AsPath | Self::AsPathGroup)
+    }
 }
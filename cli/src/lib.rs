@@ -43,12 +43,18 @@
 // docs.rs build config
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 
+mod backend;
+pub(crate) use self::backend::Backend;
+
 mod cli;
 pub use self::cli::main;
 
 mod format;
 pub(crate) use self::format::Format;
 
+mod rov;
+pub(crate) use self::rov::RovAction;
+
 // silence unused dev-dependency warnings
 #[cfg(test)]
 mod deps {
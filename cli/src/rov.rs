@@ -0,0 +1,18 @@
+use clap::ValueEnum;
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub(crate) enum RovAction {
+    /// Remove RPKI-invalid routes from the output
+    Drop,
+    /// Retain RPKI-invalid routes, but log a warning for each
+    Flag,
+}
+
+impl From<RovAction> for bgpfu::RovAction {
+    fn from(action: RovAction) -> Self {
+        match action {
+            RovAction::Drop => Self::Drop,
+            RovAction::Flag => Self::Flag,
+        }
+    }
+}
@@ -1,12 +1,15 @@
 use std::fmt::Display;
-use std::num::NonZeroU64;
+use std::num::{NonZeroU64, NonZeroUsize};
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 use std::{fmt, path::Path};
 
 use anyhow::{anyhow, Context};
 
-use clap::{Args, Parser, Subcommand};
+use bgpfu::{DiskCache, Limits, RovAction as LibRovAction};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 use clap_verbosity_flag::{InfoLevel, Verbosity};
 
@@ -313,6 +316,124 @@ pub(super) struct IrrdOpts {
         value_name = "PORT"
     )]
     port: u16,
+
+    /// Fallback IRRd server hostname or IP address.
+    ///
+    /// May be given multiple times. Servers are tried in the order given if `--irrd-host` cannot
+    /// be reached.
+    #[arg(
+        long = "irrd-fallback-host",
+        id = "irrd-fallback-host",
+        value_name = "HOST"
+    )]
+    fallback_hosts: Vec<String>,
+
+    /// Number of concurrent connections to open to the IRRd server.
+    #[arg(
+        long = "irrd-connections",
+        id = "irrd-connections",
+        default_value = "4",
+        value_name = "N"
+    )]
+    connections: NonZeroUsize,
+
+    /// Comma-separated list of IRR databases to query, in order of preference.
+    ///
+    /// If not specified, the IRRd server's default sources are used.
+    #[arg(
+        long = "irrd-sources",
+        id = "irrd-sources",
+        value_name = "SOURCES",
+        value_delimiter = ','
+    )]
+    sources: Vec<String>,
+
+    /// Directory in which to cache IRR responses.
+    ///
+    /// If the IRRd server is unreachable, expired cache entries are used instead.
+    #[arg(long = "irrd-cache-dir", id = "irrd-cache-dir", value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Time, in seconds, after which cached IRR responses expire.
+    #[arg(
+        long = "irrd-cache-ttl",
+        id = "irrd-cache-ttl",
+        default_value_t = 86400,
+        value_name = "SECONDS"
+    )]
+    cache_ttl: u64,
+
+    /// Validated ROA payloads to perform RPKI route origin validation against.
+    ///
+    /// The file must be in the JSON export format of either rpki-client or Routinator, and is
+    /// re-read before each update.
+    #[arg(long = "rov", id = "rov", value_name = "FILE")]
+    rov: Option<PathBuf>,
+
+    /// Action to take for RPKI-invalid routes when `--rov` is given.
+    #[arg(
+        long = "rov-action",
+        id = "rov-action",
+        value_enum,
+        default_value_t = RovAction::Drop,
+        value_name = "ACTION"
+    )]
+    rov_action: RovAction,
+
+    #[command(flatten, next_help_heading = "Evaluation limits")]
+    limits: LimitOpts,
+}
+
+#[derive(Debug, Args)]
+struct LimitOpts {
+    /// Maximum depth of as-set nesting below each evaluated as-set.
+    #[arg(long, value_name = "DEPTH")]
+    max_depth: Option<usize>,
+
+    /// Maximum number of autonomous systems to resolve for each policy statement.
+    #[arg(long, value_name = "N")]
+    max_autnums: Option<usize>,
+
+    /// Maximum number of prefix ranges of each address family to resolve for each policy
+    /// statement.
+    #[arg(long, value_name = "N")]
+    max_prefixes: Option<usize>,
+
+    /// Maximum time, in seconds, that evaluating the policy statements may take.
+    ///
+    /// Policy statements are evaluated together, so this limits the evaluation of all of them,
+    /// rather than of each individually. If they cannot be evaluated together, each is then
+    /// evaluated separately, subject to this limit.
+    #[arg(long = "evaluation-timeout", value_name = "SECONDS")]
+    timeout: Option<u64>,
+}
+
+impl LimitOpts {
+    fn limits(&self) -> Limits {
+        let mut limits = Limits::default();
+        if let Some(depth) = self.max_depth {
+            limits = limits.max_depth(depth);
+        }
+        if let Some(autnums) = self.max_autnums {
+            limits = limits.max_autnums(autnums);
+        }
+        if let Some(prefixes) = self.max_prefixes {
+            limits = limits.max_prefixes(prefixes);
+        }
+        if let Some(timeout) = self.timeout {
+            let timeout = Duration::from_secs(timeout);
+            limits = limits.timeout(timeout).batch_timeout(timeout);
+        }
+        limits
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum RovAction {
+    /// Remove RPKI-invalid routes from the generated policy
+    Drop,
+    /// Retain RPKI-invalid routes, but log a warning for each
+    Flag,
 }
 
 impl IrrdOpts {
@@ -323,6 +444,39 @@ impl IrrdOpts {
     pub(super) const fn port(&self) -> u16 {
         self.port
     }
+
+    pub(super) fn fallback_hosts(&self) -> &[String] {
+        &self.fallback_hosts
+    }
+
+    pub(super) const fn connections(&self) -> NonZeroUsize {
+        self.connections
+    }
+
+    pub(super) fn sources(&self) -> &[String] {
+        &self.sources
+    }
+
+    pub(super) fn disk_cache(&self) -> Option<DiskCache> {
+        self.cache_dir
+            .as_ref()
+            .map(|dir| DiskCache::new(dir, Duration::from_secs(self.cache_ttl)))
+    }
+
+    pub(super) fn rov(&self) -> Option<&Path> {
+        self.rov.as_deref()
+    }
+
+    pub(super) const fn rov_action(&self) -> LibRovAction {
+        match self.rov_action {
+            RovAction::Drop => LibRovAction::Drop,
+            RovAction::Flag => LibRovAction::Flag,
+        }
+    }
+
+    pub(super) fn limits(&self) -> Limits {
+        self.limits.limits()
+    }
 }
 
 #[derive(Debug, Args)]
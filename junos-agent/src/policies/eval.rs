@@ -1,54 +1,74 @@
-use bgpfu::RpslEvaluator;
-use ip::traits::PrefixSet;
+use async_trait::async_trait;
+use bgpfu::{AsyncRpslEvaluator, BatchResults, Error, Report};
+use ip::{traits::PrefixSet as _, Any, PrefixSet};
 
 use super::{Candidate, Evaluated, Policies};
 
+#[async_trait]
 pub(crate) trait Evaluate {
     type Evaluated;
 
-    fn evaluate(self, evaluator: &mut RpslEvaluator) -> Self::Evaluated;
+    async fn evaluate(self, evaluator: &AsyncRpslEvaluator) -> Self::Evaluated;
 }
 
+#[async_trait]
 impl Evaluate for Policies<Candidate> {
     type Evaluated = Policies<Evaluated>;
 
     #[tracing::instrument(skip(evaluator), level = "trace")]
-    fn evaluate(self, evaluator: &mut RpslEvaluator) -> Policies<Evaluated> {
+    async fn evaluate(self, evaluator: &AsyncRpslEvaluator) -> Policies<Evaluated> {
         tracing::debug!("trying to evaluate {} candidate policies", self.map.len());
+        let exprs = self
+            .map
+            .iter()
+            .map(|(name, candidate)| (name.clone(), candidate.filter_expr.clone()));
+        let mut results = match evaluator.evaluate_batch(exprs).await {
+            Ok(results) => results,
+            Err(err) => {
+                tracing::warn!(
+                    "failed to evaluate candidate policies together, evaluating each separately: {err:#}"
+                );
+                let mut results = BatchResults::with_capacity(self.map.len());
+                for (name, candidate) in &self.map {
+                    let result = evaluator
+                        .evaluate_with_report(candidate.filter_expr.clone())
+                        .await;
+                    _ = results.insert(name.clone(), result);
+                }
+                results
+            }
+        };
         let map = self
             .map
             .into_iter()
             .map(|(name, candidate)| {
-                let evaluated = candidate.evaluate(evaluator);
-                (name, evaluated)
+                let result = results.remove(&name);
+                (name, candidate.evaluated(result))
             })
             .collect();
         Policies { map }
     }
 }
 
-impl Evaluate for Candidate {
-    type Evaluated = Evaluated;
-
-    #[tracing::instrument(skip(self, evaluator), level = "debug")]
-    fn evaluate(self, evaluator: &mut RpslEvaluator) -> Evaluated {
-        tracing::debug!(
-            %self.filter_expr,
-            "trying to evaluate filter expression"
-        );
-        let ranges = evaluator
-            .evaluate(self.filter_expr.clone())
-            .map_err(|err| {
-                tracing::error!(
-                    "failed to evaluate filter expression {}: {err:#}",
-                    self.filter_expr,
-                );
+impl Candidate {
+    /// Construct the [`Evaluated`] policy from the `result` of evaluating the filter expression,
+    /// if it was evaluated.
+    fn evaluated(self, result: Option<Result<(PrefixSet<Any>, Report), Error>>) -> Evaluated {
+        let ranges = result
+            .and_then(|result| {
+                result
+                    .map_err(|err| {
+                        tracing::error!(
+                            "failed to evaluate filter expression {}: {err:#}",
+                            self.filter_expr,
+                        );
+                    })
+                    .ok()
             })
-            .map(|set| {
+            .map(|(set, _)| {
                 let (ipv4, ipv6) = set.as_partitions();
                 (ipv4.ranges().collect(), ipv6.ranges().collect())
-            })
-            .ok();
+            });
         Evaluated {
             filter_expr: self.filter_expr,
             ranges,
@@ -18,7 +18,7 @@ pub(crate) use self::fetch::Fetch;
 mod load;
 pub(crate) use self::load::Load;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct Policies<T> {
     map: HashMap<Name, T>,
 }
@@ -36,6 +36,20 @@ impl Policies<Evaluated> {
             .filter(|item| item.ranges.is_some())
             .count()
     }
+
+    /// Get the policies that are installed once the updates from `self` are committed, or `None`
+    /// if any policy statement failed to evaluate, leaving its installed policy unknown.
+    pub(crate) fn committed(self) -> Option<Policies<Installed>> {
+        let map = self
+            .map
+            .into_iter()
+            .map(|(name, evaluated)| {
+                let (ipv4, ipv6) = evaluated.ranges?;
+                Some((name, Installed { ipv4, ipv6 }))
+            })
+            .collect::<Option<_>>()?;
+        Some(Policies { map })
+    }
 }
 
 impl<T> Default for Policies<T> {
@@ -67,7 +81,7 @@ impl AsRef<str> for Name {
     }
 }
 
-#[derive(PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq)]
 pub(crate) struct Candidate {
     filter_expr: MpFilterExpr,
 }
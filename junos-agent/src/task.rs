@@ -1,12 +1,17 @@
-use std::{cmp::min, num::NonZeroU64, sync::Arc};
+use std::{
+    cmp::min,
+    num::NonZeroU64,
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
 
 use anyhow::Context;
 
-use bgpfu::RpslEvaluator;
+use bgpfu::{AsyncRpslEvaluator, Cache, Rov, Serials};
 
 use tokio::{
     signal::unix::{signal, SignalKind},
-    task::JoinHandle,
+    task::{self, JoinHandle},
     time::{self, Duration},
 };
 
@@ -21,6 +26,46 @@ pub(crate) struct Updater<T> {
     target: T,
     irrd: Arc<IrrdOpts>,
     junos: Arc<JunosOpts>,
+    cache: Cache,
+    last_inputs: LastInputs,
+}
+
+/// The inputs to a successful update, used to detect that a subsequent update would make no
+/// changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Inputs {
+    serials: Serials,
+    candidates: Policies<Candidate>,
+}
+
+/// The [`Inputs`] to the last successful update, and the policies that it installed.
+#[derive(Debug)]
+struct LastUpdate {
+    inputs: Inputs,
+    installed: Policies<Installed>,
+}
+
+/// The [`LastUpdate`], if known.
+#[derive(Debug, Clone, Default)]
+struct LastInputs(Arc<Mutex<Option<LastUpdate>>>);
+
+impl LastInputs {
+    fn lock(&self) -> MutexGuard<'_, Option<LastUpdate>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns `true` if `inputs` are unchanged since the last successful update, and the
+    /// `installed` policies are still those that it installed.
+    ///
+    /// The installed policies may have changed independently of the inputs, for example if the
+    /// ephemeral database was cleared when the device restarted.
+    fn unchanged(&self, inputs: &Inputs, installed: &Policies<Installed>) -> bool {
+        self.lock().as_ref().is_some_and(|last| {
+            inputs.serials.unchanged_since(&last.inputs.serials)
+                && inputs.candidates == last.inputs.candidates
+                && *installed == last.installed
+        })
+    }
 }
 
 impl<T: Target + 'static> Updater<T> {
@@ -30,9 +75,16 @@ impl<T: Target + 'static> Updater<T> {
             target,
             irrd: Arc::new(irrd),
             junos: Arc::new(junos),
+            cache: Cache::new(),
+            last_inputs: LastInputs::default(),
         }
     }
 
+    /// Forget the inputs to the last successful update, so that the next update is not skipped.
+    pub(crate) fn forget_inputs(&self) {
+        *self.last_inputs.lock() = None;
+    }
+
     #[tracing::instrument(skip(self), level = "debug")]
     pub(crate) fn init_loop(self, frequency: NonZeroU64) -> Loop<T> {
         Loop {
@@ -45,6 +97,9 @@ impl<T: Target + 'static> Updater<T> {
     pub(crate) async fn run(self) -> anyhow::Result<()> {
         tracing::info!("starting update");
 
+        // IRR data may have changed since the last run
+        self.cache.clear();
+
         let mut netconf_client = self
             .target
             .connect()
@@ -54,64 +109,123 @@ impl<T: Target + 'static> Updater<T> {
             .await
             .context("failed to open ephemeral database")?;
 
-        let evaluate_candidates = netconf_client
-            .fetch_config::<Policies<Candidate>>()
+        let fetch_installed = netconf_client
+            .fetch_config::<Policies<Installed>>()
             .await
-            .context("failed to request candidate configuration")
+            .context("failed to request installed ephemeral configuration")
             .map(|response| {
                 tokio::spawn(async move {
                     let policies = response
                         .await
-                        .context("failed to fetch candidate policy statements")?;
+                        .context("failed to fetch installed policy statements")?;
                     tracing::info!(
-                        "successfully fetched {} candidate policy statements",
+                        "successfully fetched {} installed policy statements",
                         policies.len()
                     );
-                    let evaluated = tokio::task::block_in_place(|| {
-                        RpslEvaluator::new(self.irrd.host(), self.irrd.port())
-                            .context("failed to connect to IRRd server")
-                            .map(|mut evaluator| policies.evaluate(&mut evaluator))
-                    })?;
-                    tracing::info!(
-                        "successfully evaluated {} of {} policy statements",
-                        evaluated.succeeded(),
-                        evaluated.len()
-                    );
-                    Ok(evaluated)
+                    Ok(policies)
                 })
             })?;
 
-        let fetch_installed = netconf_client
-            .fetch_config::<Policies<Installed>>()
+        let last_inputs = self.last_inputs.clone();
+        let evaluate_candidates = netconf_client
+            .fetch_config::<Policies<Candidate>>()
             .await
-            .context("failed to request installed ephemeral configuration")
+            .context("failed to request candidate configuration")
             .map(|response| {
                 tokio::spawn(async move {
                     let policies = response
                         .await
-                        .context("failed to fetch installed policy statements")?;
+                        .context("failed to fetch candidate policy statements")?;
                     tracing::info!(
-                        "successfully fetched {} installed policy statements",
+                        "successfully fetched {} candidate policy statements",
                         policies.len()
                     );
-                    Ok(policies)
+                    let mut builder = self
+                        .irrd
+                        .fallback_hosts()
+                        .iter()
+                        .fold(
+                            AsyncRpslEvaluator::builder(self.irrd.host(), self.irrd.port()),
+                            |builder, host| builder.fallback(host, self.irrd.port()),
+                        )
+                        .connections(self.irrd.connections())
+                        .sources(self.irrd.sources())
+                        .limits(self.irrd.limits())
+                        .cache(self.cache.clone());
+                    if let Some(disk_cache) = self.irrd.disk_cache() {
+                        builder = builder.disk_cache(disk_cache);
+                    }
+                    if let Some(path) = self.irrd.rov().map(Path::to_path_buf) {
+                        let rov = task::spawn_blocking(move || Rov::load(path))
+                            .await
+                            .context("validated ROA payload loading task failed")?
+                            .context("failed to load validated ROA payloads")?;
+                        builder = builder.rov(rov.action(self.irrd.rov_action()));
+                    }
+                    let evaluator = builder
+                        .build_async()
+                        .await
+                        .context("failed to connect to IRRd server")?;
+                    // validated ROA payloads may change independently of the IRR databases
+                    let inputs = if self.irrd.rov().is_some() {
+                        None
+                    } else {
+                        evaluator
+                            .serials()
+                            .await
+                            .map_err(|err| {
+                                tracing::warn!("failed to query IRR database serials: {err:#}");
+                            })
+                            .ok()
+                            .map(|serials| Inputs {
+                                serials,
+                                candidates: policies.clone(),
+                            })
+                    };
+                    // the update can only be skipped if the installed policies are as expected
+                    let installed = handle_task(fetch_installed).await?;
+                    if inputs
+                        .as_ref()
+                        .is_some_and(|inputs| last_inputs.unchanged(inputs, &installed))
+                    {
+                        return Ok((installed, None));
+                    }
+                    let evaluated = policies.evaluate(&evaluator).await;
+                    tracing::debug!(
+                        "resolver cache: {} hits, {} misses",
+                        self.cache.hits(),
+                        self.cache.misses()
+                    );
+                    tracing::info!(
+                        "successfully evaluated {} of {} policy statements",
+                        evaluated.succeeded(),
+                        evaluated.len()
+                    );
+                    Ok((installed, Some((evaluated, inputs))))
                 })
             })?;
 
-        let (evaluated, installed) = tokio::try_join!(
-            handle_task(evaluate_candidates),
-            handle_task(fetch_installed)
-        )?;
+        let (installed, evaluated) = handle_task(evaluate_candidates).await?;
 
-        let updates = evaluated.compare(&installed);
-
-        netconf_client
-            .load_config(updates)
-            .await
-            .context("failed to load configuration")?
-            .commit_config()
-            .await
-            .context("failed to commit to ephemeral database")?;
+        if let Some((evaluated, inputs)) = evaluated {
+            let updates = evaluated.compare(&installed);
+            netconf_client
+                .load_config(updates)
+                .await
+                .context("failed to load configuration")?
+                .commit_config()
+                .await
+                .context("failed to commit to ephemeral database")?;
+            // policy statements that failed to evaluate were not updated, so a subsequent update
+            // must not be skipped
+            *self.last_inputs.lock() = inputs
+                .zip(evaluated.committed())
+                .map(|(inputs, installed)| LastUpdate { inputs, installed });
+        } else {
+            tracing::info!(
+                "no IRR database, candidate or installed policy has changed since the last update, skipping"
+            );
+        }
 
         netconf_client
             .close_db()
@@ -156,7 +270,8 @@ impl<T: Target + 'static> Loop<T> {
                     break Ok(())
                 }
                 _ = sighup.recv() => {
-                    tracing::info!("got SIGHUP, resetting interval timer");
+                    tracing::info!("got SIGHUP, resetting interval timer and forcing a full update");
+                    self.updater.forget_inputs();
                     interval.reset_immediately();
                 }
                 _ = interval.tick() => {
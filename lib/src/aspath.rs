@@ -0,0 +1,202 @@
+use std::collections::HashSet;
+
+use ip::{Any, PrefixSet};
+
+use rpsl::names::AutNum;
+
+use crate::{
+    expand::AsSetExpansion,
+    explain::{Derivation, Explanation},
+};
+
+/// Constraints on the AS paths of routes accepted by a filter, derived from the origin
+/// autonomous systems of the routes that it contains.
+///
+/// An [`AsPathFilter`] is constructed from an [`Explanation`] of an mp-filter evaluation, in
+/// which case the origins are those of the routes contributing to the evaluated prefix set, or
+/// from an [`AsSetExpansion`], in which case the origins are every autonomous system in the
+/// `as-set`'s cone.
+///
+/// Regular expressions are rendered using the Junos OS AS path syntax, in which each term
+/// matches a whole AS number, and runs of consecutive AS numbers are written as ranges.
+///
+/// # Examples
+///
+/// ``` no_run
+/// use bgpfu::{AsPathFilter, RpslEvaluator};
+///
+/// let explanation = RpslEvaluator::new("whois.radb.net", 43)?.explain("AS-FOO".parse()?)?;
+/// let filter = AsPathFilter::from(&explanation);
+/// if let Some(regex) = filter.origin_regex() {
+///     println!("{regex}");
+/// }
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AsPathFilter {
+    origins: Vec<AutNum>,
+    unattributed: PrefixSet<Any>,
+}
+
+impl AsPathFilter {
+    fn new<I>(origins: I, unattributed: PrefixSet<Any>) -> Self
+    where
+        I: IntoIterator<Item = AutNum>,
+    {
+        let mut origins: Vec<_> = origins
+            .into_iter()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        origins.sort_unstable_by_key(|autnum| asn(*autnum));
+        Self {
+            origins,
+            unattributed,
+        }
+    }
+
+    /// Get the allowed origin autonomous systems, in ascending numerical order.
+    #[must_use]
+    pub fn origins(&self) -> &[AutNum] {
+        &self.origins
+    }
+
+    /// Get the prefix ranges of the evaluated prefix set that could not be attributed to any
+    /// origin.
+    ///
+    /// These originate from prefix literals in the expression or from `route-set` members, and
+    /// would be rejected by a filter constraining only the AS path.
+    #[must_use]
+    pub const fn unattributed(&self) -> &PrefixSet<Any> {
+        &self.unattributed
+    }
+
+    /// Get a regular expression matching AS paths that end in one of the allowed origins, or
+    /// `None` if there are none.
+    #[must_use]
+    pub fn origin_regex(&self) -> Option<String> {
+        self.origin_regexes(usize::MAX).pop()
+    }
+
+    /// Get regular expressions matching AS paths that end in one of the allowed origins, each
+    /// containing at most `max_terms` AS numbers or ranges.
+    ///
+    /// A path ending in one of the allowed origins is matched by exactly one of the expressions,
+    /// so that long lists of origins can be split across the members of an as-path group.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_terms` is zero.
+    #[must_use]
+    pub fn origin_regexes(&self, max_terms: usize) -> Vec<String> {
+        self.terms()
+            .chunks(max_terms)
+            .map(|terms| format!(".* {}", alternation(terms)))
+            .collect()
+    }
+
+    /// Get a regular expression matching AS paths consisting only of the allowed origins, or
+    /// `None` if there are none.
+    ///
+    /// This is stricter than [`AsPathFilter::origin_regex`], and suits a customer cone in which
+    /// every transit autonomous system is also a member.
+    #[must_use]
+    pub fn cone_regex(&self) -> Option<String> {
+        let terms = self.terms();
+        (!terms.is_empty()).then(|| format!("{}+", alternation(&terms)))
+    }
+
+    /// Collapse the origins into runs of consecutive AS numbers.
+    fn terms(&self) -> Vec<String> {
+        let mut runs: Vec<(u32, u32)> = Vec::new();
+        for asn in self.origins.iter().copied().map(asn) {
+            match runs.last_mut() {
+                Some((_, last)) if last.checked_add(1) == Some(asn) => *last = asn,
+                _ => runs.push((asn, asn)),
+            }
+        }
+        runs.into_iter()
+            .map(|(first, last)| {
+                if first == last {
+                    first.to_string()
+                } else {
+                    format!("{first}-{last}")
+                }
+            })
+            .collect()
+    }
+}
+
+impl From<&Explanation> for AsPathFilter {
+    fn from(explanation: &Explanation) -> Self {
+        let origins = explanation
+            .derivations()
+            .iter()
+            .filter_map(Derivation::origin);
+        let unattributed = explanation
+            .ranges()
+            .filter(|(_, derivations)| {
+                derivations
+                    .iter()
+                    .all(|derivation| derivation.origin().is_none())
+            })
+            .map(|(range, _)| range)
+            .collect();
+        Self::new(origins, unattributed)
+    }
+}
+
+impl From<&AsSetExpansion> for AsPathFilter {
+    fn from(expansion: &AsSetExpansion) -> Self {
+        Self::new(
+            expansion.autnums().iter().copied(),
+            PrefixSet::<Any>::default(),
+        )
+    }
+}
+
+/// Get the AS number of `autnum`.
+fn asn(autnum: AutNum) -> u32 {
+    // `AutNum` does not expose its AS number, but always displays as "AS" followed by it
+    autnum
+        .to_string()
+        .trim_start_matches("AS")
+        .parse()
+        .unwrap_or_default()
+}
+
+/// Join `terms` into a single regular expression term.
+fn alternation(terms: &[String]) -> String {
+    match terms {
+        [term] => term.clone(),
+        _ => format!("({})", terms.join("|")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(origins: &[&str]) -> AsPathFilter {
+        AsPathFilter::new(
+            origins.iter().map(|origin| origin.parse().unwrap()),
+            PrefixSet::<Any>::default(),
+        )
+    }
+
+    #[test]
+    fn regexes() {
+        let filter = filter(&["AS65003", "AS64512", "AS65001", "AS65002", "AS65001"]);
+        assert_eq!(filter.origins().len(), 4);
+        assert_eq!(filter.origin_regex().unwrap(), ".* (64512|65001-65003)");
+        assert_eq!(filter.origin_regexes(1), [".* 64512", ".* 65001-65003"]);
+        assert_eq!(filter.cone_regex().unwrap(), "(64512|65001-65003)+");
+    }
+
+    #[test]
+    fn no_origins() {
+        let filter = filter(&[]);
+        assert!(filter.origin_regex().is_none());
+        assert!(filter.cone_regex().is_none());
+    }
+}
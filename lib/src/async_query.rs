@@ -0,0 +1,377 @@
+use std::hash::Hash;
+
+use ip::{Any, Prefix, PrefixSet};
+
+use rpsl::{
+    expr::{eval::Evaluator, AsSetMember, MpFilterExpr},
+    names::{AsSet, AutNum},
+};
+
+use crate::{
+    batch::{self, BatchResults},
+    builder::EvaluatorBuilder,
+    client::Client,
+    error::Error,
+    expand::AsSetExpansion,
+    limits::{Budget, Limits},
+    lint::{LintReport, Linter},
+    member_of::{self, MemberOf},
+    origins::RouteObject,
+    report::Report,
+    serial::Serials,
+    table::Table,
+};
+
+/// An asynchronous evaluator for RPSL `mp-filter` expressions, that resolves RPSL names using the
+/// IRRd query protocol over a `tokio` TCP stream.
+///
+/// Rather than querying for each name as it is encountered, evaluation proceeds in rounds: the
+/// expression is evaluated against the names resolved so far, and any names found to be missing
+/// are then fetched together using a single pipeline of queries. Evaluation is complete once a
+/// round encounters no missing names.
+///
+/// Evaluation methods take `&self`, so that many expressions may be evaluated concurrently using
+/// a single [`AsyncRpslEvaluator`]. See [`EvaluatorBuilder::connections`].
+///
+/// # Examples
+///
+/// ``` no_run
+/// use bgpfu::AsyncRpslEvaluator;
+/// use ip::traits::PrefixSet;
+/// use rpsl::expr::MpFilterExpr;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let filter: MpFilterExpr = "AS-FOO AND { 0.0.0.0/0^8-24, ::/0^16-48 }".parse()?;
+/// AsyncRpslEvaluator::new("whois.radb.net", 43)
+///     .await?
+///     .evaluate(filter)
+///     .await?
+///     .ranges()
+///     .for_each(|range| println!("{range}"));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct AsyncRpslEvaluator {
+    client: Client,
+    strict: bool,
+    limits: Limits,
+}
+
+impl AsyncRpslEvaluator {
+    /// Construct a new [`AsyncRpslEvaluator`], with a single connection to the IRRd server.
+    ///
+    /// Use [`AsyncRpslEvaluator::builder`] for more control over the evaluator configuration.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::Irr`] is returned if the connection to the IRRd server cannot be established.
+    pub async fn new(host: &str, port: u16) -> Result<Self, Error> {
+        Self::builder(host, port).build_async().await
+    }
+
+    /// Construct an [`EvaluatorBuilder`] for the IRRd server at `host:port`.
+    #[must_use]
+    pub fn builder(host: &str, port: u16) -> EvaluatorBuilder {
+        EvaluatorBuilder::new(host, port)
+    }
+
+    #[tracing::instrument(level = "debug")]
+    pub(crate) async fn from_builder(builder: &EvaluatorBuilder) -> Result<Self, Error> {
+        let client = builder.client().await?;
+        Ok(Self {
+            client,
+            strict: builder.is_strict(),
+            limits: builder.evaluation_limits(),
+        })
+    }
+
+    /// Evaluate an RPSL `mp-filter` expression.
+    ///
+    /// # Errors
+    ///
+    /// An `Err` is returned if the connection to the IRRd server fails, or if evaluation
+    /// encounters a fatal error. See [`Evaluator`] for error handling details.
+    #[tracing::instrument(skip(self, expr), fields(%expr), level = "debug")]
+    pub async fn evaluate(&self, expr: MpFilterExpr) -> Result<PrefixSet<Any>, Error> {
+        tracing::info!("evaluating RPSL mp-filter expression '{expr}'");
+        let (output, _) = self.evaluate_with(expr, None).await?;
+        Ok(output)
+    }
+
+    /// Evaluate an RPSL `mp-filter` expression in the context of a peering with `peer_as`.
+    ///
+    /// Any `PeerAS` token appearing in `expr` is substituted with `peer_as`, and resolved in the
+    /// same way as an `aut-num`.
+    ///
+    /// # Errors
+    ///
+    /// See [`AsyncRpslEvaluator::evaluate`].
+    #[tracing::instrument(skip(self, expr), fields(%expr), level = "debug")]
+    pub async fn evaluate_for_peer(
+        &self,
+        expr: MpFilterExpr,
+        peer_as: AutNum,
+    ) -> Result<PrefixSet<Any>, Error> {
+        tracing::info!("evaluating RPSL mp-filter expression '{expr}' for peer {peer_as}");
+        let (output, _) = self.evaluate_with(expr, Some(peer_as)).await?;
+        Ok(output)
+    }
+
+    /// Evaluate an RPSL `mp-filter` expression, returning a [`Report`] of the non-fatal errors
+    /// encountered alongside the output.
+    ///
+    /// # Errors
+    ///
+    /// See [`AsyncRpslEvaluator::evaluate`].
+    #[tracing::instrument(skip(self, expr), fields(%expr), level = "debug")]
+    pub async fn evaluate_with_report(
+        &self,
+        expr: MpFilterExpr,
+    ) -> Result<(PrefixSet<Any>, Report), Error> {
+        tracing::info!("evaluating RPSL mp-filter expression '{expr}'");
+        self.evaluate_with(expr, None).await
+    }
+
+    /// Evaluate an RPSL `mp-filter` expression in the context of a peering with `peer_as`,
+    /// returning a [`Report`] of the non-fatal errors encountered alongside the output.
+    ///
+    /// # Errors
+    ///
+    /// See [`AsyncRpslEvaluator::evaluate`].
+    #[tracing::instrument(skip(self, expr), fields(%expr), level = "debug")]
+    pub async fn evaluate_for_peer_with_report(
+        &self,
+        expr: MpFilterExpr,
+        peer_as: AutNum,
+    ) -> Result<(PrefixSet<Any>, Report), Error> {
+        tracing::info!("evaluating RPSL mp-filter expression '{expr}' for peer {peer_as}");
+        self.evaluate_with(expr, Some(peer_as)).await
+    }
+
+    /// Evaluate many RPSL `mp-filter` expressions together, returning the output and [`Report`]
+    /// of each under the key with which it was given.
+    ///
+    /// Rather than evaluating each expression separately, the names missing from all of the
+    /// expressions are combined in each round, and fetched using a single pipeline of queries,
+    /// so that a name referenced by several expressions is fetched only once.
+    ///
+    /// Each expression is subject to the configured [`Limits`] independently, except for
+    /// [`Limits::timeout`]. Since the names required by every expression are fetched together,
+    /// the time taken is limited for the batch as a whole instead, using
+    /// [`Limits::batch_timeout`]. A non-fatal error encountered while resolving a name that is
+    /// shared by several expressions is reported only for the first of them to resolve it.
+    ///
+    /// # Errors
+    ///
+    /// An `Err` is returned if the connection to the IRRd server fails. The failure of an
+    /// individual expression is returned as its result instead.
+    ///
+    /// # Examples
+    ///
+    /// ``` no_run
+    /// use std::collections::HashMap;
+    ///
+    /// use bgpfu::AsyncRpslEvaluator;
+    /// use rpsl::expr::MpFilterExpr;
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut exprs = HashMap::new();
+    /// for (name, filter) in [("customer-a", "AS-FOO"), ("customer-b", "AS-FOO OR AS-BAR")] {
+    ///     exprs.insert(name, filter.parse::<MpFilterExpr>()?);
+    /// }
+    /// let results = AsyncRpslEvaluator::new("whois.radb.net", 43)
+    ///     .await?
+    ///     .evaluate_batch(exprs)
+    ///     .await?;
+    /// for (name, result) in results {
+    ///     match result {
+    ///         Ok((set, _)) => println!("{name}: {set:?}"),
+    ///         Err(err) => eprintln!("{name}: {err}"),
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[tracing::instrument(skip(self, exprs), level = "debug")]
+    pub async fn evaluate_batch<K, I>(&self, exprs: I) -> Result<BatchResults<K>, Error>
+    where
+        I: IntoIterator<Item = (K, MpFilterExpr)>,
+        K: Eq + Hash,
+    {
+        batch::evaluate(&self.client, exprs, None, self.strict, self.limits).await
+    }
+
+    /// Evaluate many RPSL `mp-filter` expressions together, in the context of a peering with
+    /// `peer_as`.
+    ///
+    /// See [`AsyncRpslEvaluator::evaluate_batch`] and
+    /// [`AsyncRpslEvaluator::evaluate_for_peer`].
+    ///
+    /// # Errors
+    ///
+    /// See [`AsyncRpslEvaluator::evaluate_batch`].
+    #[tracing::instrument(skip(self, exprs), level = "debug")]
+    pub async fn evaluate_batch_for_peer<K, I>(
+        &self,
+        exprs: I,
+        peer_as: AutNum,
+    ) -> Result<BatchResults<K>, Error>
+    where
+        I: IntoIterator<Item = (K, MpFilterExpr)>,
+        K: Eq + Hash,
+    {
+        batch::evaluate(&self.client, exprs, Some(peer_as), self.strict, self.limits).await
+    }
+
+    /// Expand `as_set` recursively into the autonomous systems that it contains.
+    ///
+    /// See [`RpslEvaluator::expand_as_set`][crate::RpslEvaluator::expand_as_set].
+    ///
+    /// # Errors
+    ///
+    /// See [`RpslEvaluator::expand_as_set`][crate::RpslEvaluator::expand_as_set].
+    #[tracing::instrument(skip(self), fields(%as_set), level = "debug")]
+    pub async fn expand_as_set(&self, as_set: &AsSet) -> Result<AsSetExpansion, Error> {
+        tracing::info!("expanding {as_set}");
+        let mut budget = Budget::new(self.limits);
+        let expansion = budget.expansion(as_set);
+        let autnums = budget
+            .run(async {
+                match expansion {
+                    Some(expansion) => Ok(self
+                        .client
+                        .walk(expansion)
+                        .await?
+                        .into_expansion(false)?
+                        .into_autnums()),
+                    None => self.client.as_set_autnums(as_set).await,
+                }
+            })
+            .await?;
+        budget.add_autnums(autnums.iter().copied())?;
+        Ok(AsSetExpansion::new(as_set.clone(), autnums, None))
+    }
+
+    /// Expand `as_set` recursively, along with the tree of its members.
+    ///
+    /// See [`RpslEvaluator::expand_as_set_with_tree`][crate::RpslEvaluator::expand_as_set_with_tree].
+    ///
+    /// # Errors
+    ///
+    /// See [`RpslEvaluator::expand_as_set`][crate::RpslEvaluator::expand_as_set].
+    #[tracing::instrument(skip(self), fields(%as_set), level = "debug")]
+    pub async fn expand_as_set_with_tree(&self, as_set: &AsSet) -> Result<AsSetExpansion, Error> {
+        tracing::info!("expanding {as_set}");
+        let mut budget = Budget::new(self.limits);
+        let expansion = budget
+            .run(self.client.walk(budget.walk(as_set)))
+            .await?
+            .into_expansion(true)?;
+        budget.add_autnums(expansion.autnums().iter().copied())?;
+        Ok(expansion)
+    }
+
+    /// Get the current serials of the IRR databases being queried.
+    ///
+    /// See [`RpslEvaluator::serials`][crate::RpslEvaluator::serials].
+    ///
+    /// # Errors
+    ///
+    /// See [`RpslEvaluator::serials`][crate::RpslEvaluator::serials].
+    #[tracing::instrument(skip(self), level = "debug")]
+    pub async fn serials(&self) -> Result<Serials, Error> {
+        self.client.serials().await
+    }
+
+    /// Find the `as-set`s that contain `member`.
+    ///
+    /// See [`RpslEvaluator::member_of`][crate::RpslEvaluator::member_of].
+    ///
+    /// # Errors
+    ///
+    /// See [`RpslEvaluator::member_of`][crate::RpslEvaluator::member_of].
+    #[tracing::instrument(skip(self), fields(%member), level = "debug")]
+    pub async fn member_of(
+        &self,
+        member: &AsSetMember,
+        recursive: bool,
+    ) -> Result<Vec<MemberOf>, Error> {
+        tracing::info!("looking up the as-sets containing {member}");
+        member_of::lookup(&self.client, member, recursive).await
+    }
+
+    /// Find the `route` and `route6` objects for `prefix`, along with their origins and
+    /// sources.
+    ///
+    /// See [`RpslEvaluator::origins`][crate::RpslEvaluator::origins].
+    ///
+    /// # Errors
+    ///
+    /// See [`RpslEvaluator::origins`][crate::RpslEvaluator::origins].
+    #[tracing::instrument(skip(self), fields(%prefix), level = "debug")]
+    pub async fn origins(
+        &self,
+        prefix: Prefix<Any>,
+        less_specific: bool,
+        more_specific: bool,
+    ) -> Result<Vec<RouteObject>, Error> {
+        tracing::info!("looking up the route objects for {prefix}");
+        self.client
+            .route_objects(prefix, less_specific, more_specific)
+            .await
+    }
+
+    /// Check `as_set` and the `as-set`s nested within it for common problems.
+    ///
+    /// See [`RpslEvaluator::lint`][crate::RpslEvaluator::lint].
+    ///
+    /// # Errors
+    ///
+    /// See [`RpslEvaluator::lint`][crate::RpslEvaluator::lint].
+    #[tracing::instrument(skip(self, linter), fields(%as_set), level = "debug")]
+    pub async fn lint(&self, as_set: &AsSet, linter: &Linter) -> Result<LintReport, Error> {
+        tracing::info!("linting {as_set}");
+        linter.lint(&self.client, as_set).await
+    }
+
+    async fn evaluate_with(
+        &self,
+        expr: MpFilterExpr,
+        peer_as: Option<AutNum>,
+    ) -> Result<(PrefixSet<Any>, Report), Error> {
+        let mut table = Table::new(peer_as, self.strict, self.limits);
+        match table.budget.remaining() {
+            Some(remaining) => {
+                let timeout = table.budget.timeout_err();
+                tokio::time::timeout(remaining, self.evaluate_in(&mut table, expr))
+                    .await
+                    .unwrap_or(Err(timeout))
+            }
+            None => self.evaluate_in(&mut table, expr).await,
+        }
+    }
+
+    async fn evaluate_in(
+        &self,
+        table: &mut Table,
+        expr: MpFilterExpr,
+    ) -> Result<(PrefixSet<Any>, Report), Error> {
+        loop {
+            let output = table.evaluate(expr.clone()).map_err(Error::surface_limit)?;
+            let misses = table.take_misses();
+            if misses.is_empty() {
+                break Ok((output, table.take_report()));
+            }
+            for as_set in &misses.as_sets {
+                if let Some(expansion) = table.budget.expansion(as_set) {
+                    let expansion = self.client.walk(expansion).await?;
+                    table.budget.add_autnums(expansion.into_autnums())?;
+                }
+            }
+            self.client.fetch(misses, table).await?;
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::{collections::HashMap, hash::Hash, mem};
+
+use ip::{Any, PrefixSet};
+
+use rpsl::{
+    expr::{eval::Evaluator, MpFilterExpr},
+    names::AutNum,
+};
+
+use crate::{
+    client::Client,
+    error::Error,
+    limits::{Budget, Limits},
+    report::Report,
+    table::{Names, Table},
+};
+
+/// The results of evaluating a batch of expressions, keyed in the same way as the expressions.
+///
+/// See [`AsyncRpslEvaluator::evaluate_batch`][crate::AsyncRpslEvaluator::evaluate_batch].
+pub type BatchResults<K> = HashMap<K, Result<(PrefixSet<Any>, Report), Error>>;
+
+/// An expression in a batch that has not yet been fully evaluated.
+#[derive(Debug)]
+struct Pending<K> {
+    key: K,
+    expr: MpFilterExpr,
+    table: Table,
+    misses: Names,
+}
+
+impl<K> Pending<K> {
+    /// Attempt to evaluate the expression using the names resolved so far, returning the output
+    /// if no names were found to be missing.
+    fn attempt(&mut self) -> Result<Option<PrefixSet<Any>>, Error> {
+        let output = self
+            .table
+            .evaluate(self.expr.clone())
+            .map_err(Error::surface_limit)?;
+        self.misses = self.table.take_misses();
+        Ok(self.misses.is_empty().then_some(output))
+    }
+}
+
+/// Evaluate each of `exprs`, in the context of a peering with `peer_as` if given, resolving
+/// RPSL names using `client`.
+///
+/// Evaluation proceeds in rounds, as for a single expression, except that the names missing
+/// from every incomplete expression are combined, and those not resolved during an earlier round
+/// are fetched using a single pipeline of queries. A name referenced by several expressions is
+/// therefore fetched only once. Likewise, any `as-set`s that must be walked to enforce the
+/// configured [`Limits`] are walked together.
+///
+/// Each expression is evaluated against its own [`Table`], so that its [`Report`] and the
+/// consumption of its [`Limits`] are independent of the others. Non-fatal errors encountered
+/// while resolving a name are reported by every expression that uses it. The batch as a
+/// whole is subject to [`Limits::batch_timeout`].
+///
+/// # Errors
+///
+/// An `Err` is returned only if the connection to the IRRd server fails. The failure of an
+/// individual expression is returned as its result.
+pub(crate) async fn evaluate<K, I>(
+    client: &Client,
+    exprs: I,
+    peer_as: Option<AutNum>,
+    strict: bool,
+    limits: Limits,
+) -> Result<BatchResults<K>, Error>
+where
+    I: IntoIterator<Item = (K, MpFilterExpr)>,
+    K: Eq + Hash,
+{
+    let budget = Budget::batch(limits);
+    let mut pending: Vec<_> = exprs
+        .into_iter()
+        .map(|(key, expr)| Pending {
+            key,
+            expr,
+            table: Table::new(peer_as, strict, limits.batch_expression()),
+            misses: Names::default(),
+        })
+        .collect();
+    tracing::info!("evaluating a batch of {} expressions", pending.len());
+    let mut results = HashMap::with_capacity(pending.len());
+    let mut store = Table::default();
+    while !pending.is_empty() {
+        let mut waiting = Vec::with_capacity(pending.len());
+        for mut expr in pending {
+            match expr.attempt() {
+                Ok(Some(output)) => {
+                    _ = results.insert(expr.key, Ok((output, expr.table.take_report())));
+                }
+                Ok(None) => waiting.push(expr),
+                Err(err) => _ = results.insert(expr.key, Err(err)),
+            }
+        }
+        let Some(walked) = budget.within(walk(client, &mut waiting)).await else {
+            timeout(&mut results, waiting, &budget);
+            break;
+        };
+        for (expr, result) in mem::take(&mut waiting).into_iter().zip(walked?) {
+            match result {
+                Ok(()) => waiting.push(expr),
+                Err(err) => _ = results.insert(expr.key, Err(err)),
+            }
+        }
+        let mut wanted = Names::default();
+        for expr in &waiting {
+            wanted.extend(&expr.misses);
+        }
+        let absent = store.absent(wanted);
+        if !absent.is_empty() {
+            tracing::debug!(
+                expressions = waiting.len(),
+                "fetching missing names for batch"
+            );
+            let Some(fetched) = budget.within(client.fetch(absent, &mut store)).await else {
+                timeout(&mut results, waiting, &budget);
+                break;
+            };
+            fetched?;
+        }
+        for expr in &mut waiting {
+            expr.table.copy_from(&store, &expr.misses);
+        }
+        pending = waiting;
+    }
+    Ok(results)
+}
+
+/// Walk the newly missing `as-set`s of each of `exprs` that must be walked to enforce its
+/// [`Limits`], returning whether each expression remains within them.
+///
+/// The walks required by every expression are made together, so that each level of nesting is
+/// looked up using a single pipeline of queries.
+async fn walk<K>(
+    client: &Client,
+    exprs: &mut [Pending<K>],
+) -> Result<Vec<Result<(), Error>>, Error> {
+    let mut owners = Vec::new();
+    let mut expansions = Vec::new();
+    for (i, expr) in exprs.iter_mut().enumerate() {
+        for as_set in &expr.misses.as_sets {
+            if let Some(expansion) = expr.table.budget.expansion(as_set) {
+                owners.push(i);
+                expansions.push(expansion);
+            }
+        }
+    }
+    let mut results: Vec<Result<(), Error>> = exprs.iter().map(|_| Ok(())).collect();
+    if expansions.is_empty() {
+        return Ok(results);
+    }
+    tracing::debug!("walking {} as-sets for batch", expansions.len());
+    for (i, walked) in owners.into_iter().zip(client.walk_all(expansions).await?) {
+        if results[i].is_err() {
+            continue;
+        }
+        results[i] = walked
+            .and_then(|expansion| exprs[i].table.budget.add_autnums(expansion.into_autnums()));
+    }
+    Ok(results)
+}
+
+/// Fail each of the `waiting` expressions, because the batch has run out of time.
+fn timeout<K>(results: &mut BatchResults<K>, waiting: Vec<Pending<K>>, budget: &Budget)
+where
+    K: Eq + Hash,
+{
+    results.extend(
+        waiting
+            .into_iter()
+            .map(|expr| (expr.key, Err(budget.timeout_err()))),
+    );
+}
@@ -0,0 +1,227 @@
+use std::num::NonZeroUsize;
+
+use crate::{
+    async_query::AsyncRpslEvaluator, cache::Cache, client::Client, disk_cache::DiskCache,
+    error::Error, irrd, limits::Limits, query::RpslEvaluator, rov::Rov,
+};
+
+/// Builder for [`RpslEvaluator`] and [`AsyncRpslEvaluator`] instances.
+///
+/// # Examples
+///
+/// ``` no_run
+/// use std::num::NonZeroUsize;
+///
+/// use bgpfu::EvaluatorBuilder;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let evaluator = EvaluatorBuilder::new("whois.radb.net", 43)
+///     .connections(NonZeroUsize::new(4).unwrap())
+///     .build_async()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct EvaluatorBuilder {
+    servers: Vec<String>,
+    max_retries: usize,
+    connections: NonZeroUsize,
+    sources: Vec<String>,
+    cache: Option<Cache>,
+    disk_cache: Option<DiskCache>,
+    rov: Option<Rov>,
+    aggregate: bool,
+    strict: bool,
+    limits: Limits,
+}
+
+impl EvaluatorBuilder {
+    /// Construct a new [`EvaluatorBuilder`] for the IRRd server at `host:port`.
+    #[must_use]
+    pub fn new(host: &str, port: u16) -> Self {
+        Self {
+            servers: vec![format!("{host}:{port}")],
+            max_retries: 2,
+            connections: NonZeroUsize::MIN,
+            sources: Vec::new(),
+            cache: None,
+            disk_cache: None,
+            rov: None,
+            aggregate: false,
+            strict: false,
+            limits: Limits::default(),
+        }
+    }
+
+    /// Add a fallback IRRd server at `host:port`.
+    ///
+    /// Whenever a connection needs to be (re-)established, servers are tried in the order in which
+    /// they were added, starting with the server passed to [`EvaluatorBuilder::new`].
+    #[must_use]
+    pub fn fallback(mut self, host: &str, port: u16) -> Self {
+        self.servers.push(format!("{host}:{port}"));
+        self
+    }
+
+    /// Set the maximum number of times to retry queries after a connection failure.
+    ///
+    /// Each retry first re-establishes the connection, failing over to the next available server
+    /// if necessary. Defaults to `2`.
+    #[must_use]
+    pub const fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Set the number of connections to open to the IRRd server.
+    ///
+    /// Queries are divided between the connections in the pool, and independent evaluations
+    /// sharing an [`AsyncRpslEvaluator`] proceed concurrently. Defaults to `1`.
+    #[must_use]
+    pub const fn connections(mut self, connections: NonZeroUsize) -> Self {
+        self.connections = connections;
+        self
+    }
+
+    /// Set the IRR databases to query, in order of preference.
+    ///
+    /// This sets the IRRd `!s` option on each connection, so that objects are only taken from the
+    /// listed `sources`, with the first source in which an object is found taking precedence. By
+    /// default, the server's own default source set is used.
+    ///
+    /// An [`Error::Irr`] is returned when building the evaluator if the server rejects any of
+    /// the `sources`.
+    #[must_use]
+    pub fn sources<I, S>(mut self, sources: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.sources = sources.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Share resolved RPSL names with other evaluators using `cache`.
+    ///
+    /// See [`Cache`] for details.
+    #[must_use]
+    pub fn cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Persist resolved RPSL names using `disk_cache`.
+    ///
+    /// If a disk cache is configured, being unable to reach the IRRd server is not fatal:
+    /// evaluation proceeds using only the cached entries. See [`DiskCache`] for details.
+    #[must_use]
+    pub fn disk_cache(mut self, disk_cache: DiskCache) -> Self {
+        self.disk_cache = Some(disk_cache);
+        self
+    }
+
+    /// Validate resolved routes against a set of validated ROA payloads using `rov`.
+    ///
+    /// Routes are validated as they are resolved, so any configured [`Cache`] or [`DiskCache`]
+    /// holds only the routes that remain after validation. See [`Rov`] for details.
+    #[must_use]
+    pub fn rov(mut self, rov: Rov) -> Self {
+        self.rov = Some(rov);
+        self
+    }
+
+    /// Fetch the routes of each `as-set` using aggregated route queries (`!a4` and `!a6`), where
+    /// supported by the IRRd server.
+    ///
+    /// This replaces a route query for each member of the `as-set` with a pair of queries for the
+    /// whole `as-set`, but the server returns the routes aggregated into the fewest covering
+    /// prefixes, rather than the routes themselves. For example, routes for `192.0.2.0/25` and
+    /// `192.0.2.128/25` are returned as `192.0.2.0/24`. This changes the result of applying a
+    /// range operator to the `as-set`: `AS-FOO^-` then includes both routes, since they are more
+    /// specific than the aggregate, and `AS-FOO^+` includes `192.0.2.0/24`, which is not a route.
+    ///
+    /// Errors resolving the individual members of the `as-set` are also not visible, so are
+    /// missing from the [`Report`] for the evaluation, and are not fatal in
+    /// [`strict`][Self::strict] mode.
+    ///
+    /// Aggregated route queries are not used if [`rov`][Self::rov] is configured. Disabled by
+    /// default.
+    ///
+    /// [`Report`]: crate::Report
+    #[must_use]
+    pub const fn aggregate(mut self, aggregate: bool) -> Self {
+        self.aggregate = aggregate;
+        self
+    }
+
+    /// Make every error encountered during evaluation fatal.
+    ///
+    /// By default, non-fatal errors (e.g. an `as-set` that does not exist) are logged, and
+    /// recorded in the [`Report`] for the evaluation, which then continues as if the name
+    /// involved were empty.
+    ///
+    /// [`Report`]: crate::Report
+    #[must_use]
+    pub const fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub(crate) const fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Limit the resources consumed by each evaluation.
+    ///
+    /// See [`Limits`] for details.
+    #[must_use]
+    pub const fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub(crate) const fn evaluation_limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Construct an [`RpslEvaluator`] using the current configuration.
+    ///
+    /// # Errors
+    ///
+    /// See [`RpslEvaluator::new`].
+    pub fn build(self) -> Result<RpslEvaluator, Error> {
+        RpslEvaluator::from_builder(&self)
+    }
+
+    /// Construct an [`AsyncRpslEvaluator`] using the current configuration.
+    ///
+    /// # Errors
+    ///
+    /// See [`AsyncRpslEvaluator::new`].
+    pub async fn build_async(self) -> Result<AsyncRpslEvaluator, Error> {
+        AsyncRpslEvaluator::from_builder(&self).await
+    }
+
+    pub(crate) async fn client(&self) -> Result<Client, Error> {
+        let client = Client::new(
+            self.servers.clone(),
+            self.sources.clone(),
+            self.connections,
+            self.max_retries,
+        )
+        .with_cache(self.cache.clone())
+        .with_disk_cache(self.disk_cache.clone())
+        .with_rov(self.rov.clone())
+        .with_aggregation(self.aggregate);
+        match client.connect_all().await {
+            Ok(()) => Ok(client),
+            Err(err @ Error::Irr(irrd::Error::Io(_))) if self.disk_cache.is_some() => {
+                tracing::warn!("continuing using only the on-disk cache: {err:#}");
+                Ok(client)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+};
+
+use ip::{Any, PrefixSet};
+
+use rpsl::names::{AsSet, AutNum, RouteSet};
+
+use crate::{
+    rov::RovAction,
+    table::{Entry, Names, Table},
+};
+
+/// An in-memory cache of resolved RPSL names, that may be shared between evaluators.
+///
+/// [`Cache`] is cheap to clone, and clones share the same underlying storage. Once a name has
+/// been resolved by any evaluator configured with a [`Cache`], subsequent resolutions of the same
+/// name are served from memory until the cache is explicitly [cleared][Cache::clear].
+///
+/// Only names that were resolved without error are cached. A name for which any non-fatal error
+/// was encountered, such as an `as-set` that does not exist or a member whose routes could not be
+/// fetched, is resolved again by the next evaluation that requires it, so that the error is
+/// reported (or, in strict mode, raised) every time. An autonomous system with no routes of one
+/// address family is not an error, so is cached as normal, as are the members of each `as-set`.
+///
+/// Evaluators sharing a [`Cache`] may query different IRRd servers or IRR sources, or validate
+/// routes using different [`Rov`][crate::Rov] configurations. Names are cached separately for
+/// each such configuration, so that an evaluator is only ever served the results of its own
+/// queries.
+///
+/// # Examples
+///
+/// ``` no_run
+/// use bgpfu::{AsyncRpslEvaluator, Cache};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let cache = Cache::new();
+/// let evaluator = AsyncRpslEvaluator::builder("whois.radb.net", 43)
+///     .cache(cache.clone())
+///     .build_async()
+///     .await?;
+/// for filter in ["AS-FOO", "AS-FOO OR AS-BAR"] {
+///     _ = evaluator.evaluate(filter.parse()?).await?;
+/// }
+/// println!("{} hits, {} misses", cache.hits(), cache.misses());
+/// cache.clear();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Cache {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    scopes: Mutex<HashMap<Scope, Tables>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// The parts of an evaluator's configuration that determine the output of resolving a name.
+///
+/// Cached names are only shared between evaluators with the same [`Scope`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub(crate) struct Scope {
+    pub(crate) servers: Vec<String>,
+    pub(crate) sources: Vec<String>,
+    pub(crate) rov: Option<(usize, RovAction)>,
+    pub(crate) aggregate: bool,
+}
+
+#[derive(Debug, Default)]
+struct Tables {
+    as_sets: HashMap<AsSet, PrefixSet<Any>>,
+    route_sets: HashMap<RouteSet, PrefixSet<Any>>,
+    autnums: HashMap<AutNum, PrefixSet<Any>>,
+}
+
+impl Tables {
+    fn len(&self) -> usize {
+        self.as_sets.len() + self.route_sets.len() + self.autnums.len()
+    }
+}
+
+impl Cache {
+    /// Construct a new, empty [`Cache`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the number of lookups that were served from the cache since it was last cleared.
+    #[must_use]
+    pub fn hits(&self) -> u64 {
+        self.inner.hits.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of lookups that were not found in the cache since it was last cleared.
+    #[must_use]
+    pub fn misses(&self) -> u64 {
+        self.inner.misses.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of RPSL names currently held in the cache.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.scopes().values().map(Tables::len).sum()
+    }
+
+    /// Returns `true` if the cache holds no RPSL names.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove all entries from the cache, and reset the hit and miss counters.
+    pub fn clear(&self) {
+        self.scopes().clear();
+        self.inner.hits.store(0, Ordering::Relaxed);
+        self.inner.misses.store(0, Ordering::Relaxed);
+    }
+
+    fn scopes(&self) -> MutexGuard<'_, HashMap<Scope, Tables>> {
+        // the tables are never left in an inconsistent state, so a poisoned lock is safe to use
+        self.inner
+            .scopes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn record(&self, hit: bool) {
+        let counter = if hit {
+            &self.inner.hits
+        } else {
+            &self.inner.misses
+        };
+        _ = counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Move any names in `names` that are cached for `scope` into `table`, returning the
+    /// remaining names.
+    pub(crate) fn lookup(&self, scope: &Scope, mut names: Names, table: &mut Table) -> Names {
+        let scopes = self.scopes();
+        let empty = Tables::default();
+        let tables = scopes.get(scope).unwrap_or(&empty);
+        names
+            .as_sets
+            .retain(|as_set| self.lookup_one(&tables.as_sets, &mut table.as_sets, as_set));
+        names.route_sets.retain(|route_set| {
+            self.lookup_one(&tables.route_sets, &mut table.route_sets, route_set)
+        });
+        names
+            .autnums
+            .retain(|autnum| self.lookup_one(&tables.autnums, &mut table.autnums, autnum));
+        drop(scopes);
+        names
+    }
+
+    /// Look up a single name, returning `true` if it was not found.
+    fn lookup_one<K>(
+        &self,
+        cached: &HashMap<K, PrefixSet<Any>>,
+        entries: &mut HashMap<K, Entry<PrefixSet<Any>>>,
+        key: &K,
+    ) -> bool
+    where
+        K: Clone + Eq + Hash,
+    {
+        let hit = cached.get(key).map(|output| {
+            _ = entries.insert(
+                key.clone(),
+                Entry {
+                    output: output.clone(),
+                    errors: Vec::new(),
+                },
+            );
+        });
+        self.record(hit.is_some());
+        hit.is_none()
+    }
+
+    /// Insert the entries in `table` for each of `names` into the cache for `scope`.
+    ///
+    /// Entries recording any errors are not cached.
+    pub(crate) fn store(&self, scope: &Scope, names: &Names, table: &Table) {
+        let mut scopes = self.scopes();
+        let tables = scopes.entry(scope.clone()).or_default();
+        store(&mut tables.as_sets, &names.as_sets, &table.as_sets);
+        store(&mut tables.route_sets, &names.route_sets, &table.route_sets);
+        store(&mut tables.autnums, &names.autnums, &table.autnums);
+        drop(scopes);
+    }
+}
+
+fn store<K>(
+    cached: &mut HashMap<K, PrefixSet<Any>>,
+    keys: &HashSet<K>,
+    entries: &HashMap<K, Entry<PrefixSet<Any>>>,
+) where
+    K: Clone + Eq + Hash,
+{
+    for key in keys {
+        match entries.get(key) {
+            Some(entry) if entry.errors.is_empty() => {
+                _ = cached
+                    .entry(key.clone())
+                    .or_insert_with(|| entry.output.clone());
+            }
+            Some(_) => tracing::debug!("not caching an entry that recorded errors"),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{iter, sync::Arc};
+
+    use super::*;
+    use crate::irrd;
+
+    #[test]
+    fn hits_and_misses() {
+        let cache = Cache::new();
+        let autnum: AutNum = "AS65000".parse().unwrap();
+        let names = || Names {
+            autnums: iter::once(autnum).collect(),
+            ..Names::default()
+        };
+
+        let scope = Scope::default();
+        let mut table = Table::default();
+        let remaining = cache.lookup(&scope, names(), &mut table);
+        assert!(remaining.autnums.contains(&autnum));
+        assert_eq!((cache.hits(), cache.misses()), (0, 1));
+
+        _ = table.autnums.insert(
+            autnum,
+            Entry {
+                output: iter::once("192.0.2.0/24".parse::<ip::Prefix<Any>>().unwrap()).collect(),
+                errors: Vec::new(),
+            },
+        );
+        cache.store(&scope, &names(), &table);
+        assert_eq!(cache.len(), 1);
+
+        let mut table = Table::default();
+        let remaining = cache.lookup(&scope, names(), &mut table);
+        assert!(remaining.is_empty());
+        assert!(table.autnums.contains_key(&autnum));
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!((cache.hits(), cache.misses()), (0, 0));
+    }
+
+    #[test]
+    fn errors_are_not_cached() {
+        let cache = Cache::new();
+        let scope = Scope::default();
+        let as_set: AsSet = "AS-FOO".parse().unwrap();
+        let names = || Names {
+            as_sets: iter::once(as_set.clone()).collect(),
+            ..Names::default()
+        };
+
+        let mut table = Table::default();
+        _ = table.as_sets.insert(
+            as_set.clone(),
+            Entry {
+                output: PrefixSet::<Any>::default(),
+                errors: vec![Arc::new(
+                    irrd::Error::ResponseErr(
+                        irrd::Query::AsSetMembersRecursive(as_set.clone()),
+                        irrd::ResponseError::KeyNotFound,
+                    )
+                    .into(),
+                )],
+            },
+        );
+        cache.store(&scope, &names(), &table);
+        assert!(cache.is_empty());
+
+        let mut table = Table::default();
+        let remaining = cache.lookup(&scope, names(), &mut table);
+        assert!(remaining.as_sets.contains(&as_set));
+        assert!(!table.as_sets.contains_key(&as_set));
+    }
+
+    #[test]
+    fn scopes_are_separate() {
+        let cache = Cache::new();
+        let autnum: AutNum = "AS65000".parse().unwrap();
+        let names = || Names {
+            autnums: iter::once(autnum).collect(),
+            ..Names::default()
+        };
+        let radb = Scope {
+            sources: vec!["RADB".to_owned()],
+            ..Scope::default()
+        };
+
+        let mut table = Table::default();
+        _ = table.autnums.insert(
+            autnum,
+            Entry {
+                output: iter::once("192.0.2.0/24".parse::<ip::Prefix<Any>>().unwrap()).collect(),
+                errors: Vec::new(),
+            },
+        );
+        cache.store(&radb, &names(), &table);
+
+        let mut table = Table::default();
+        let remaining = cache.lookup(&Scope::default(), names(), &mut table);
+        assert!(remaining.autnums.contains(&autnum));
+        let remaining = cache.lookup(&radb, names(), &mut table);
+        assert!(remaining.is_empty());
+    }
+}
@@ -0,0 +1,1277 @@
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    num::NonZeroUsize,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures::future::try_join_all;
+
+use ip::{traits::PrefixSet as _, Any, Prefix, PrefixSet};
+
+use rpsl::{
+    attr::{AttributeType, RpslAttribute},
+    expr::{AsSetMember, MpFilterExpr},
+    names::{AsSet, AutNum},
+    obj::{RpslObject, RpslObjectClass as _},
+};
+
+use crate::{
+    cache::{Cache, Scope},
+    disk_cache::DiskCache,
+    error::Error,
+    expand::Expansion,
+    irrd::{self, Connection, Query, Response, ResponseError, RouteMatch, RpslObjectClass},
+    offline::{attributes, read_paragraphs},
+    origins::{Origins, RouteObject},
+    policy::Policy,
+    rov::Rov,
+    serial::Serials,
+    table::{Entry, Names, Table},
+};
+
+use tokio::sync::Mutex;
+
+/// The delay before retrying after failing to connect to any server, multiplied by the number of
+/// attempts made so far.
+const RECONNECT_BACKOFF: Duration = Duration::from_millis(250);
+
+/// A client for resolving RPSL names to their contents using the IRRd query protocol.
+///
+/// The queries required to resolve a collection of names are pipelined, rather than waiting for
+/// each response in turn, and are spread across a pool of connections to the server.
+///
+/// Connections are (re-)established on demand, trying each configured server in turn. Since all
+/// queries are read-only, queries that were in flight on a failed connection are retried.
+///
+/// If enabled using [`Client::with_aggregation`], and the server supports aggregated route
+/// queries, as detected when connecting, the routes of each `as-set` are fetched using a pair of
+/// `!a` queries, rather than a route query for each of its members.
+#[derive(Debug)]
+pub(crate) struct Client {
+    servers: Vec<String>,
+    sources: Vec<String>,
+    max_retries: usize,
+    pool: Vec<Mutex<Option<Connection>>>,
+    next: AtomicUsize,
+    aggregation: AtomicBool,
+    aggregate: bool,
+    cache: Option<Cache>,
+    disk_cache: Option<DiskCache>,
+    rov: Option<Rov>,
+}
+
+impl Client {
+    /// Construct a new [`Client`] for a pool of `connections` connections to the first available
+    /// IRRd server in `servers`, querying `sources` (or the server default, if empty).
+    ///
+    /// No connections are established until they are first required. See
+    /// [`Client::connect_all`].
+    pub(crate) fn new(
+        servers: Vec<String>,
+        sources: Vec<String>,
+        connections: NonZeroUsize,
+        max_retries: usize,
+    ) -> Self {
+        Self {
+            servers,
+            sources,
+            max_retries,
+            pool: (0..connections.get()).map(|_| Mutex::new(None)).collect(),
+            next: AtomicUsize::new(0),
+            aggregation: AtomicBool::new(false),
+            aggregate: false,
+            cache: None,
+            disk_cache: None,
+            rov: None,
+        }
+    }
+
+    /// Serve resolved names from, and store newly resolved names in, `cache`.
+    pub(crate) fn with_cache(self, cache: Option<Cache>) -> Self {
+        Self { cache, ..self }
+    }
+
+    /// Serve resolved names from, and store newly resolved names in, `disk_cache`.
+    pub(crate) fn with_disk_cache(self, disk_cache: Option<DiskCache>) -> Self {
+        Self { disk_cache, ..self }
+    }
+
+    /// Fetch the routes of each `as-set` using aggregated route queries, if `aggregate` is `true`
+    /// and the server supports them.
+    ///
+    /// See [`EvaluatorBuilder::aggregate`][crate::EvaluatorBuilder::aggregate] for how this
+    /// changes the resolved routes.
+    pub(crate) fn with_aggregation(self, aggregate: bool) -> Self {
+        Self { aggregate, ..self }
+    }
+
+    /// Validate the routes originated by each resolved `aut-num` using `rov`.
+    pub(crate) fn with_rov(self, rov: Option<Rov>) -> Self {
+        Self { rov, ..self }
+    }
+
+    /// Establish every connection in the pool.
+    pub(crate) async fn connect_all(&self) -> Result<(), Error> {
+        _ = try_join_all(self.pool.iter().map(|slot| async move {
+            let mut slot = slot.lock().await;
+            if slot.is_none() {
+                *slot = Some(self.connect_any().await?);
+            }
+            drop(slot);
+            Ok::<_, Error>(())
+        }))
+        .await?;
+        Ok(())
+    }
+
+    /// Connect to the first available server.
+    async fn connect_any(&self) -> Result<Connection, Error> {
+        self.connect_with_sources(&self.sources).await
+    }
+
+    /// Connect to the first available server, querying `sources` rather than the configured
+    /// sources.
+    async fn connect_with_sources(&self, sources: &[String]) -> Result<Connection, Error> {
+        let mut last_err = None;
+        for addr in &self.servers {
+            match Connection::connect(addr.as_str(), sources).await {
+                Ok(conn) => {
+                    self.aggregation
+                        .store(conn.supports_aggregation(), Ordering::Relaxed);
+                    return Ok(conn);
+                }
+                Err(err) => {
+                    tracing::warn!("failed to connect to IRRd server {addr}: {err:#}");
+                    last_err = Some(err);
+                }
+            }
+        }
+        Err(last_err.map_or(Error::AcquireConnection, Error::from))
+    }
+
+    /// Get the [`Scope`] within which resolved names are cached.
+    fn scope(&self) -> Scope {
+        Scope {
+            servers: self.servers.clone(),
+            sources: self.sources.clone(),
+            rov: self.rov.as_ref().map(Rov::id),
+            aggregate: self.aggregates(),
+        }
+    }
+
+    /// Determine whether the routes of `as-set`s are fetched using aggregated route queries,
+    /// where the server supports them.
+    ///
+    /// Route origin validation requires the routes of each member, so aggregated route queries
+    /// are not used when it is enabled.
+    const fn aggregates(&self) -> bool {
+        self.aggregate && self.rov.is_none()
+    }
+
+    /// Move any names that are already cached into `table`, returning the remaining names.
+    fn lookup_cached(&self, names: Names, table: &mut Table) -> Names {
+        match &self.cache {
+            Some(cache) => cache.lookup(&self.scope(), names, table),
+            None => names,
+        }
+    }
+
+    /// Execute `queries`, divided evenly between the connections in the pool.
+    ///
+    /// The responses are returned in the same order as `queries`.
+    async fn execute(&self, mut queries: Vec<Query>) -> Result<Vec<(Query, Response)>, Error> {
+        if queries.is_empty() {
+            return Ok(Vec::new());
+        }
+        let chunk_size = queries.len().div_ceil(self.pool.len());
+        let mut chunks = Vec::with_capacity(self.pool.len());
+        while !queries.is_empty() {
+            let rest = queries.split_off(chunk_size.min(queries.len()));
+            chunks.push(mem::replace(&mut queries, rest));
+        }
+        // rotate the starting connection, so that concurrent callers with only a few queries
+        // each do not all contend for the first connection in the pool
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let responses =
+            try_join_all(chunks.into_iter().enumerate().map(|(i, chunk)| {
+                self.execute_on(&self.pool[(start + i) % self.pool.len()], chunk)
+            }))
+            .await?;
+        Ok(responses.into_iter().flatten().collect())
+    }
+
+    /// Execute `queries` using the connection in `slot`, reconnecting and retrying if the
+    /// connection fails.
+    ///
+    /// Failing to re-establish the connection counts as a failed attempt, and is retried in the
+    /// same way.
+    async fn execute_on(
+        &self,
+        slot: &Mutex<Option<Connection>>,
+        queries: Vec<Query>,
+    ) -> Result<Vec<(Query, Response)>, Error> {
+        let mut slot = slot.lock().await;
+        let mut attempt = 0;
+        loop {
+            // the connection is only returned to the pool once the responses to all queries have
+            // been read, so that a connection is never re-used in an unknown state
+            let conn = match slot.take() {
+                Some(conn) => Ok(conn),
+                None => self.connect_any().await,
+            };
+            let reconnect_failed = conn.is_err();
+            let err = match conn {
+                Ok(mut conn) => match conn.execute(queries.clone()).await {
+                    Ok(responses) => {
+                        *slot = Some(conn);
+                        drop(slot);
+                        break Ok(responses);
+                    }
+                    Err(err) => err.into(),
+                },
+                Err(err) => err,
+            };
+            if attempt >= self.max_retries {
+                break Err(err);
+            }
+            attempt += 1;
+            tracing::warn!(
+                "connection to IRRd server failed, retrying ({attempt}/{}): {err:#}",
+                self.max_retries
+            );
+            if reconnect_failed {
+                // every server has just been tried, so give them a moment to recover
+                let backoff =
+                    RECONNECT_BACKOFF.saturating_mul(attempt.try_into().unwrap_or(u32::MAX));
+                tokio::time::sleep(backoff).await;
+            }
+        }
+    }
+
+    /// Fetch the contents of each of `names`, and insert the results into `table`.
+    ///
+    /// Names are served from the configured caches where possible. If the IRRd server cannot be
+    /// reached, expired on-disk cache entries are used instead.
+    ///
+    /// # Errors
+    ///
+    /// An `Err` is returned only if the connection fails, and the names could not be served from
+    /// the on-disk cache. Errors resolving individual names are recorded in the corresponding
+    /// table [`Entry`].
+    #[tracing::instrument(skip_all, level = "debug")]
+    pub(crate) async fn fetch(&self, names: Names, table: &mut Table) -> Result<(), Error> {
+        let scope = self.scope();
+        let mut names = self.lookup_cached(names, table);
+        let uncached = names.clone();
+        if let Some(disk_cache) = &self.disk_cache {
+            names = disk_cache.lookup(&scope, names, table, false).await;
+        }
+        match self.fetch_remote(names.clone(), table).await {
+            Ok(()) => {
+                if let Some(disk_cache) = &self.disk_cache {
+                    disk_cache.store(&scope, &names, table).await;
+                }
+                if let Some(cache) = &self.cache {
+                    cache.store(&scope, &uncached, table);
+                }
+            }
+            Err(err) => {
+                let Some(disk_cache) = &self.disk_cache else {
+                    return Err(err);
+                };
+                tracing::warn!("falling back to expired on-disk cache entries: {err:#}");
+                if !disk_cache
+                    .lookup(&scope, names, table, true)
+                    .await
+                    .is_empty()
+                {
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetch the contents of each of `names` from the IRRd server, and insert the results into
+    /// `table`.
+    ///
+    /// The routes of each `as-set` are fetched using aggregated route queries if enabled, and the
+    /// server supports them. Otherwise, or if the server rejects them, the members of the
+    /// `as-set` are expanded by the server, after which the routes originated by each member not
+    /// already in `table` are fetched in a further round of queries.
+    ///
+    /// # Errors
+    ///
+    /// An `Err` is returned only if the connection fails.
+    async fn fetch_remote(&self, names: Names, table: &mut Table) -> Result<(), Error> {
+        let Names {
+            filter_sets,
+            as_sets,
+            route_sets,
+            autnums,
+        } = names;
+        tracing::debug!(
+            filter_sets = filter_sets.len(),
+            as_sets = as_sets.len(),
+            route_sets = route_sets.len(),
+            autnums = autnums.len(),
+            "fetching RPSL names"
+        );
+        let aggregate = self.aggregates() && self.aggregation.load(Ordering::Relaxed);
+        let queries = filter_sets
+            .iter()
+            .map(|filter_set| Query::RpslObject(RpslObjectClass::FilterSet, filter_set.to_string()))
+            .chain(
+                as_sets
+                    .iter()
+                    .flat_map(|as_set| as_set_queries(as_set, aggregate)),
+            )
+            .chain(
+                route_sets
+                    .iter()
+                    .cloned()
+                    .map(Query::RouteSetMembersRecursive),
+            )
+            .chain(autnums.iter().flat_map(|autnum| routes_queries(*autnum)))
+            .collect();
+        let mut responses = self.execute(queries).await?.into_iter();
+        table.filter_sets.extend(
+            filter_sets
+                .into_iter()
+                .zip(responses.by_ref().map(filter_set_entry)),
+        );
+        let mut members: Vec<(AsSet, Entry<HashSet<AutNum>>)> = Vec::new();
+        let mut unaggregated = Vec::new();
+        for as_set in as_sets {
+            if aggregate {
+                match aggregated_entry(responses.by_ref().take(2).collect()) {
+                    Some(entry) => _ = table.as_sets.insert(as_set, entry),
+                    None => unaggregated.push(as_set),
+                }
+            } else {
+                members.push((as_set, Entry::from_responses(responses.by_ref().take(1))));
+            }
+        }
+        table
+            .route_sets
+            .extend(route_sets.into_iter().map(|route_set| {
+                (
+                    route_set,
+                    Entry::from_responses::<_, Prefix<Any>>(responses.by_ref().take(1)),
+                )
+            }));
+        table.autnums.extend(autnums.into_iter().map(|autnum| {
+            (
+                autnum,
+                self.autnum_entry(autnum, responses.by_ref().take(2)),
+            )
+        }));
+        if !unaggregated.is_empty() {
+            tracing::debug!(
+                "falling back to member expansion for {} as-sets",
+                unaggregated.len()
+            );
+            let queries = unaggregated
+                .iter()
+                .cloned()
+                .map(Query::AsSetMembersRecursive)
+                .collect();
+            let mut responses = self.execute(queries).await?.into_iter();
+            members.extend(
+                unaggregated
+                    .into_iter()
+                    .map(|as_set| (as_set, Entry::from_responses(responses.by_ref().take(1)))),
+            );
+        }
+        self.fetch_members(members, table).await
+    }
+
+    /// Fetch the routes originated by the members of each `as-set` in `members`, and insert the
+    /// union of the results for each `as-set` into `table`.
+    async fn fetch_members(
+        &self,
+        members: Vec<(AsSet, Entry<HashSet<AutNum>>)>,
+        table: &mut Table,
+    ) -> Result<(), Error> {
+        let missing = Names {
+            autnums: members
+                .iter()
+                .flat_map(|(_, entry)| entry.output.iter().copied())
+                .filter(|autnum| !table.autnums.contains_key(autnum))
+                .collect(),
+            ..Names::default()
+        };
+        let missing = self.lookup_cached(missing, table).autnums;
+        if !missing.is_empty() {
+            tracing::debug!("fetching routes for {} as-set members", missing.len());
+            let queries = missing.iter().copied().flat_map(routes_queries).collect();
+            let mut responses = self.execute(queries).await?.into_iter();
+            table.autnums.extend(missing.iter().map(|&autnum| {
+                (
+                    autnum,
+                    self.autnum_entry(autnum, responses.by_ref().take(2)),
+                )
+            }));
+            // the members are cached too, since they are likely to be shared with other `as-set`s
+            if let Some(cache) = &self.cache {
+                let members = Names {
+                    autnums: missing,
+                    ..Names::default()
+                };
+                cache.store(&self.scope(), &members, table);
+            }
+        }
+        for (as_set, Entry { output, mut errors }) in members {
+            let mut prefixes = Vec::new();
+            for autnum in &output {
+                let Some(entry) = table.autnums.get(autnum) else {
+                    continue;
+                };
+                prefixes.extend(entry.output.prefixes());
+                // member errors remain on the member's entry, so that it is not cached as if
+                // it were complete, and are reported against every `as-set` that includes it
+                errors.extend(entry.errors.iter().cloned());
+            }
+            _ = table.as_sets.insert(
+                as_set,
+                Entry {
+                    output: prefixes.into_iter().collect(),
+                    errors,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Construct the table [`Entry`] for the routes originated by `autnum` from the responses
+    /// to its route queries, applying route origin validation if configured.
+    fn autnum_entry<I>(&self, autnum: AutNum, responses: I) -> Entry<PrefixSet<Any>>
+    where
+        I: IntoIterator<Item = (Query, Response)>,
+    {
+        let mut entry =
+            Entry::from_responses::<_, Prefix<Any>>(responses.into_iter().map(routes_response));
+        if let Some(rov) = &self.rov {
+            entry.output = rov.apply(autnum, &entry.output);
+        }
+        entry
+    }
+
+    /// Fetch the direct members of each of `as_sets`, without expanding nested `as-set`s.
+    ///
+    /// The members of any `as-set` that cannot be fetched are `None`. These lookups bypass the
+    /// configured caches.
+    pub(crate) async fn as_set_members(
+        &self,
+        as_sets: &[AsSet],
+    ) -> Result<Vec<Option<Vec<AsSetMember>>>, Error> {
+        let queries = as_sets.iter().cloned().map(Query::AsSetMembers).collect();
+        Ok(self
+            .execute(queries)
+            .await?
+            .into_iter()
+            .map(|(_, response)| match response {
+                Ok(data) => Some(
+                    data.split_whitespace()
+                        .filter_map(|member| member.parse().ok())
+                        .collect(),
+                ),
+                Err(err) => {
+                    tracing::debug!("{err:#}");
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Walk the members of an `as-set` one level of nesting at a time.
+    ///
+    /// These lookups bypass the configured caches.
+    pub(crate) async fn walk(&self, mut expansion: Expansion) -> Result<Expansion, Error> {
+        while !expansion.pending().is_empty() {
+            expansion.advance(self.as_set_members(expansion.pending()).await?)?;
+        }
+        Ok(expansion)
+    }
+
+    /// Walk the members of several `as-set`s together, one level of nesting at a time.
+    ///
+    /// At each level, the `as-set`s pending in every unfinished walk are looked up using a single
+    /// pipeline of queries. A walk that fails, such as by exceeding its depth limit, is returned
+    /// as an `Err` without affecting the others.
+    ///
+    /// These lookups bypass the configured caches.
+    pub(crate) async fn walk_all(
+        &self,
+        expansions: Vec<Expansion>,
+    ) -> Result<Vec<Result<Expansion, Error>>, Error> {
+        let mut walks: Vec<Result<_, Error>> = expansions.into_iter().map(Ok).collect();
+        loop {
+            let pending: Vec<AsSet> = walks
+                .iter()
+                .flatten()
+                .flat_map(Expansion::pending)
+                .cloned()
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect();
+            if pending.is_empty() {
+                break Ok(walks);
+            }
+            let members: HashMap<_, _> = pending
+                .iter()
+                .cloned()
+                .zip(self.as_set_members(&pending).await?)
+                .collect();
+            for walk in &mut walks {
+                let Ok(expansion) = walk else {
+                    continue;
+                };
+                if expansion.pending().is_empty() {
+                    continue;
+                }
+                let level: Vec<_> = expansion
+                    .pending()
+                    .iter()
+                    .map(|as_set| members.get(as_set).cloned().flatten())
+                    .collect();
+                if let Err(err) = expansion.advance(level) {
+                    *walk = Err(err);
+                }
+            }
+        }
+    }
+
+    /// Fetch every `aut-num` contained in `as_set`, expanding nested `as-set`s on the server.
+    ///
+    /// This lookup bypasses the configured caches.
+    pub(crate) async fn as_set_autnums(&self, as_set: &AsSet) -> Result<HashSet<AutNum>, Error> {
+        let query = Query::AsSetMembersRecursive(as_set.clone());
+        let responses = self.execute(vec![query]).await?;
+        match responses.into_iter().next() {
+            Some((query, Ok(data))) => data
+                .split_whitespace()
+                .map(|item| {
+                    item.parse().map_err(|err| {
+                        Error::from(irrd::Error::ParseItem {
+                            query: query.clone(),
+                            item: item.to_owned(),
+                            source: Box::new(err),
+                        })
+                    })
+                })
+                .collect(),
+            Some((_, Err(irrd::Error::ResponseErr(_, ResponseError::KeyNotFound)))) | None => {
+                Err(Error::AsSetNotFound(as_set.clone()))
+            }
+            Some((_, Err(err))) => Err(err.into()),
+        }
+    }
+
+    /// Fetch the routing policy attributes of the `aut-num` object for `autnum`.
+    ///
+    /// This lookup bypasses the configured caches.
+    pub(crate) async fn autnum_policy(&self, autnum: AutNum) -> Result<Policy, Error> {
+        let query = Query::RpslObject(RpslObjectClass::AutNum, autnum.to_string());
+        let responses = self.execute(vec![query]).await?;
+        match responses.into_iter().next() {
+            Some((_, Ok(data))) => Ok(Policy::from_attrs(attributes(&data))),
+            Some((_, Err(irrd::Error::ResponseErr(_, ResponseError::KeyNotFound)))) | None => {
+                Err(Error::AutNumNotFound(autnum))
+            }
+            Some((_, Err(err))) => Err(err.into()),
+        }
+    }
+
+    /// Fetch the `source` of the `route` or `route6` object for each `(prefix, origin)` pair in
+    /// `routes`.
+    ///
+    /// The source of any object that cannot be fetched is `None`. These lookups bypass the
+    /// configured caches.
+    pub(crate) async fn route_sources(
+        &self,
+        routes: &[(Prefix<Any>, AutNum)],
+    ) -> Result<Vec<Option<String>>, Error> {
+        let queries = routes
+            .iter()
+            .map(|(prefix, origin)| {
+                let class = match prefix {
+                    Prefix::<Any>::Ipv4(_) => RpslObjectClass::Route,
+                    Prefix::<Any>::Ipv6(_) => RpslObjectClass::Route6,
+                };
+                Query::RpslObject(class, format!("{prefix}{origin}"))
+            })
+            .collect();
+        Ok(self
+            .execute(queries)
+            .await?
+            .into_iter()
+            .map(|(_, response)| response.ok().and_then(|data| object_source(&data)))
+            .collect())
+    }
+
+    /// Fetch the current serials of the configured sources, or of every source known to the
+    /// server if none are configured.
+    ///
+    /// This lookup bypasses the configured caches.
+    pub(crate) async fn serials(&self) -> Result<Serials, Error> {
+        let query = Query::DatabaseSerials(self.sources.clone());
+        match self.execute(vec![query]).await?.into_iter().next() {
+            Some((_, Ok(data))) => Ok(Serials::parse(&data)),
+            Some((_, Err(err))) => Err(err.into()),
+            None => Ok(Serials::default()),
+        }
+    }
+
+    /// Fetch the `as-set`s whose `members` attribute lists `member`, along with the `source` of
+    /// each.
+    ///
+    /// The inverse query is sent on a new connection to the first available server. This lookup
+    /// bypasses the configured caches.
+    pub(crate) async fn as_sets_containing(
+        &self,
+        member: &str,
+    ) -> Result<Vec<(AsSet, Option<String>)>, Error> {
+        let query = Query::AsSetsContaining(member.to_owned(), self.sources.clone());
+        let mut last_err = None;
+        for addr in &self.servers {
+            match irrd::execute_once(addr.as_str(), query.clone()).await {
+                Ok((_, Ok(data))) => return Ok(as_set_objects(&data)),
+                Ok((_, Err(irrd::Error::ResponseErr(_, ResponseError::KeyNotFound)))) => {
+                    return Ok(Vec::new())
+                }
+                Ok((_, Err(err))) => return Err(err.into()),
+                Err(err) => {
+                    tracing::warn!("failed to query IRRd server {addr}: {err:#}");
+                    last_err = Some(err);
+                }
+            }
+        }
+        Err(last_err.map_or(Error::AcquireConnection, Error::from))
+    }
+
+    /// Fetch the `as-set`s that contain `autnum` by reference, along with the `source` of each.
+    ///
+    /// These are the `as-set`s named in the `member-of` attribute of the `aut-num` object for
+    /// `autnum`, whose `mbrs-by-ref` attribute lists either `ANY` or one of the maintainers of
+    /// the `aut-num` object. These lookups bypass the configured caches.
+    pub(crate) async fn as_sets_by_reference(
+        &self,
+        autnum: AutNum,
+    ) -> Result<Vec<(AsSet, Option<String>)>, Error> {
+        let query = Query::RpslObject(RpslObjectClass::AutNum, autnum.to_string());
+        let attrs = match self.execute(vec![query]).await?.into_iter().next() {
+            Some((_, Ok(data))) => attributes(&data),
+            Some((_, Err(irrd::Error::ResponseErr(_, ResponseError::KeyNotFound)))) | None => {
+                return Ok(Vec::new())
+            }
+            Some((_, Err(err))) => return Err(err.into()),
+        };
+        let maintainers = list_values(&attrs, "mnt-by");
+        let as_sets: Vec<AsSet> = list_values(&attrs, "member-of")
+            .into_iter()
+            .filter_map(|name| name.parse().ok())
+            .collect();
+        let queries = as_sets
+            .iter()
+            .map(|as_set| Query::RpslObject(RpslObjectClass::AsSet, as_set.to_string()))
+            .collect();
+        Ok(as_sets
+            .into_iter()
+            .zip(self.execute(queries).await?)
+            .filter_map(|(as_set, (_, response))| {
+                let data = response.map_err(|err| tracing::debug!("{err:#}")).ok()?;
+                let admitted = list_values(&attributes(&data), "mbrs-by-ref")
+                    .iter()
+                    .any(|mntner| mntner == "ANY" || maintainers.contains(mntner));
+                if !admitted {
+                    tracing::debug!("{as_set} does not admit {autnum} by reference");
+                }
+                admitted.then(|| (as_set, object_source(&data)))
+            })
+            .collect())
+    }
+
+    /// Fetch the `route` and `route6` objects for `prefix`, and optionally for its less-specific
+    /// and more-specific prefixes, in every configured source.
+    ///
+    /// These lookups bypass the configured caches.
+    pub(crate) async fn route_objects(
+        &self,
+        prefix: Prefix<Any>,
+        less_specific: bool,
+        more_specific: bool,
+    ) -> Result<Vec<RouteObject>, Error> {
+        let mut queries = vec![Query::Routes(
+            prefix,
+            if less_specific {
+                RouteMatch::LessSpecific
+            } else {
+                RouteMatch::Exact
+            },
+        )];
+        if more_specific {
+            queries.push(Query::Routes(prefix, RouteMatch::MoreSpecific));
+        }
+        let mut origins = Origins::new(prefix, less_specific, more_specific);
+        for (_, response) in self.execute(queries).await? {
+            match response {
+                Ok(data) => origins.insert_objects(&data),
+                Err(irrd::Error::ResponseErr(_, ResponseError::KeyNotFound)) => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(origins.finish())
+    }
+
+    /// Fetch the object of `class` with each of `keys`, or `None` for any that do not exist.
+    ///
+    /// These lookups bypass the configured caches.
+    pub(crate) async fn objects(
+        &self,
+        class: RpslObjectClass,
+        keys: &[String],
+    ) -> Result<Vec<Option<String>>, Error> {
+        let queries = keys
+            .iter()
+            .map(|key| Query::RpslObject(class, key.clone()))
+            .collect();
+        self.execute(queries)
+            .await?
+            .into_iter()
+            .map(object_response)
+            .collect()
+    }
+
+    /// Fetch the object of `class` with each of `keys` from `sources`, rather than the
+    /// configured sources, or `None` for any that do not exist there.
+    ///
+    /// The queries are sent on a new connection, which is closed afterwards. These lookups
+    /// bypass the configured caches.
+    pub(crate) async fn objects_in(
+        &self,
+        sources: &[String],
+        class: RpslObjectClass,
+        keys: &[String],
+    ) -> Result<Vec<Option<String>>, Error> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let queries = keys
+            .iter()
+            .map(|key| Query::RpslObject(class, key.clone()))
+            .collect();
+        self.connect_with_sources(sources)
+            .await?
+            .execute(queries)
+            .await?
+            .into_iter()
+            .map(object_response)
+            .collect()
+    }
+
+    /// Fetch the names of every source known to the server.
+    ///
+    /// This lookup bypasses the configured caches.
+    pub(crate) async fn known_sources(&self) -> Result<Vec<String>, Error> {
+        let query = Query::DatabaseSerials(Vec::new());
+        match self.execute(vec![query]).await?.into_iter().next() {
+            Some((_, Ok(data))) => Ok(Serials::parse(&data)
+                .iter()
+                .map(|(source, _)| source.to_owned())
+                .collect()),
+            Some((_, Err(err))) => Err(err.into()),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Determine whether any `route` or `route6` objects exist for each of `autnums`.
+    ///
+    /// These lookups bypass the configured caches.
+    pub(crate) async fn originates_routes(&self, autnums: &[AutNum]) -> Result<Vec<bool>, Error> {
+        let queries = autnums.iter().copied().flat_map(routes_queries).collect();
+        let mut responses = self.execute(queries).await?.into_iter();
+        autnums
+            .iter()
+            .map(|_| {
+                let mut found = false;
+                for (_, response) in responses.by_ref().take(2) {
+                    match response {
+                        Ok(data) => found |= !data.trim().is_empty(),
+                        Err(irrd::Error::ResponseErr(_, ResponseError::KeyNotFound)) => {}
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+                Ok(found)
+            })
+            .collect()
+    }
+
+    /// Resolve a single collection of `names` into a new [`Table`].
+    pub(crate) async fn resolve(&self, names: Names) -> Result<Table, Error> {
+        let mut table = Table::default();
+        self.fetch(names, &mut table).await?;
+        Ok(table)
+    }
+}
+
+const fn routes_queries(autnum: AutNum) -> [Query; 2] {
+    [Query::Ipv4Routes(autnum), Query::Ipv6Routes(autnum)]
+}
+
+/// Treat a "key not found" response to a route query as an empty result.
+///
+/// The server responds in this way when there are no routes of the queried address family, which
+/// is the case for most autonomous systems that originate only IPv4 routes, so it is not an error.
+fn routes_response((query, response): (Query, Response)) -> (Query, Response) {
+    match response {
+        Err(irrd::Error::ResponseErr(_, ResponseError::KeyNotFound)) => (query, Ok(String::new())),
+        response => (query, response),
+    }
+}
+
+/// Get the queries used to resolve `as_set`, either directly to its aggregated routes, or to
+/// its members.
+fn as_set_queries(as_set: &AsSet, aggregate: bool) -> Vec<Query> {
+    if aggregate {
+        vec![
+            Query::AggregatedIpv4Routes(as_set.clone()),
+            Query::AggregatedIpv6Routes(as_set.clone()),
+        ]
+    } else {
+        vec![Query::AsSetMembersRecursive(as_set.clone())]
+    }
+}
+
+/// Construct the table [`Entry`] for an `as-set` from the responses to its aggregated route
+/// queries, or `None` if the server rejected them.
+fn aggregated_entry(responses: Vec<(Query, Response)>) -> Option<Entry<PrefixSet<Any>>> {
+    let rejected = responses.iter().any(|(_, response)| {
+        matches!(
+            response,
+            Err(irrd::Error::ResponseErr(_, ResponseError::Other(_)))
+        )
+    });
+    if rejected {
+        return None;
+    }
+    let not_found = responses.iter().all(|(_, response)| {
+        matches!(
+            response,
+            Err(irrd::Error::ResponseErr(_, ResponseError::KeyNotFound))
+        )
+    });
+    // a missing `as-set` is reported once, rather than once per address family
+    if not_found {
+        return Some(Entry::from_responses::<_, Prefix<Any>>(
+            responses.into_iter().take(1),
+        ));
+    }
+    Some(Entry::from_responses::<_, Prefix<Any>>(
+        responses.into_iter().map(routes_response),
+    ))
+}
+
+impl<T> Entry<T> {
+    /// Construct an [`Entry`] by parsing the whitespace separated items in each of `responses`.
+    fn from_responses<I, U>(responses: I) -> Self
+    where
+        I: IntoIterator<Item = (Query, Response)>,
+        T: FromIterator<U>,
+        U: FromStr,
+        U::Err: std::error::Error + Send + Sync + 'static,
+    {
+        let mut errors = Vec::new();
+        let output = responses
+            .into_iter()
+            .flat_map(|(query, response)| match response {
+                Ok(data) => data
+                    .split_whitespace()
+                    .map(|item| {
+                        item.parse().map_err(|err| irrd::Error::ParseItem {
+                            query: query.clone(),
+                            item: item.to_owned(),
+                            source: Box::new(err),
+                        })
+                    })
+                    .collect(),
+                Err(err) => vec![Err(err)],
+            })
+            .filter_map(|result| result.map_err(|err| errors.push(Arc::new(err.into()))).ok())
+            .collect();
+        Self { output, errors }
+    }
+}
+
+/// Get the text of an RPSL object from the response to an object query, or `None` if it does not
+/// exist.
+fn object_response((_, response): (Query, Response)) -> Result<Option<String>, Error> {
+    match response {
+        Ok(data) => Ok(Some(data)),
+        Err(irrd::Error::ResponseErr(_, ResponseError::KeyNotFound)) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Find the value of the `source` attribute in the text of an RPSL object.
+pub(crate) fn object_source(data: &str) -> Option<String> {
+    data.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("source")
+            .then(|| value.trim().to_owned())
+    })
+}
+
+/// Get the name and `source` of each `as-set` object in the text of a RIPE-style response.
+fn as_set_objects(data: &str) -> Vec<(AsSet, Option<String>)> {
+    let mut as_sets = Vec::new();
+    // reading from a byte slice cannot fail
+    _ = read_paragraphs(data.as_bytes(), |paragraph| {
+        let attrs = attributes(paragraph);
+        if let Some(Ok(as_set)) = attrs
+            .first()
+            .filter(|(class, _)| class == "as-set")
+            .map(|(_, name)| name.parse())
+        {
+            as_sets.push((as_set, object_source(paragraph)));
+        }
+    });
+    as_sets
+}
+
+/// Get the upper-cased items of every occurrence of the list attribute `name`.
+pub(crate) fn list_values(attrs: &[(String, String)], name: &str) -> Vec<String> {
+    attrs
+        .iter()
+        .filter(|(attr, _)| attr == name)
+        .flat_map(|(_, value)| value.split([',', ' ']))
+        .filter(|item| !item.is_empty())
+        .map(str::to_ascii_uppercase)
+        .collect()
+}
+
+/// Construct a table [`Entry`] from the response to a `filter-set` object query.
+fn filter_set_entry((_, response): (Query, Response)) -> Entry<Option<MpFilterExpr>> {
+    let result = response.map_err(Error::from).and_then(|data| {
+        let obj: RpslObject = data.trim_end().parse()?;
+        if let RpslObject::FilterSet(ref filter_set_obj) = obj {
+            filter_set_obj
+                .attrs()
+                .into_iter()
+                .find_map(|attr| {
+                    if let RpslAttribute::MpFilter(expr) = attr {
+                        Some(expr.clone())
+                    } else {
+                        None
+                    }
+                })
+                .ok_or_else(|| Error::FindAttribute(AttributeType::MpFilter, obj))
+        } else {
+            Err(Error::RpslObjectClass(obj))
+        }
+    });
+    match result {
+        Ok(expr) => Entry {
+            output: Some(expr),
+            errors: Vec::new(),
+        },
+        Err(err) => Entry {
+            output: None,
+            errors: vec![Arc::new(err)],
+        },
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::EvaluatorBuilder;
+
+    /// Serve the IRRd query protocol on `listener`, answering each query in `responses` with the
+    /// corresponding data (or error, if it starts with `F`), and any other query as not found.
+    pub(crate) async fn serve(
+        listener: TcpListener,
+        responses: HashMap<&'static str, &'static str>,
+    ) {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let responses = responses.clone();
+            drop(tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                while let Ok(Some(query)) = lines.next_line().await {
+                    let response = match query.as_str() {
+                        "!!" => continue,
+                        "!v" => "A24\n# IRRd -- version 4.4.2\nC\n".to_owned(),
+                        query if query.starts_with("!n") => "C\n".to_owned(),
+                        query => match responses.get(query) {
+                            Some(&"") => "C\n".to_owned(),
+                            Some(err) if err.starts_with('F') => format!("{err}\n"),
+                            Some(data) => format!("A{}\n{data}\nC\n", data.len() + 1),
+                            None => "D\n".to_owned(),
+                        },
+                    };
+                    writer.write_all(response.as_bytes()).await.unwrap();
+                }
+            }));
+        }
+    }
+
+    #[tokio::test]
+    async fn retry_failed_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            // drop the first connection before completing the handshake
+            drop(listener.accept().await.unwrap());
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream
+                .write_all(b"C\nA24\n# IRRd -- version 4.4.2\nC\nA13\n192.0.2.0/24\nC\n")
+                .await
+                .unwrap();
+            let mut sent = Vec::new();
+            _ = stream.read_to_end(&mut sent).await.unwrap();
+        });
+        let client = Client::new(vec![addr], Vec::new(), NonZeroUsize::MIN, 1);
+        let responses = client
+            .execute(vec![Query::Ipv4Routes("AS65000".parse().unwrap())])
+            .await
+            .unwrap();
+        assert_eq!(responses[0].1.as_ref().unwrap(), "192.0.2.0/24\n");
+        drop(client);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn aggregated_routes() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(serve(
+            listener,
+            [
+                ("!iAS-FOO,1", "AS65001"),
+                ("!gAS65001", "192.0.2.0/25 192.0.2.128/25"),
+                ("!6AS65001", ""),
+                ("!a4AS-FOO", "192.0.2.0/24"),
+                ("!a6AS-FOO", ""),
+            ]
+            .into_iter()
+            .collect(),
+        ));
+        let evaluate = |aggregate: bool, expr: &'static str| async move {
+            EvaluatorBuilder::new("127.0.0.1", port)
+                .aggregate(aggregate)
+                .build_async()
+                .await
+                .unwrap()
+                .evaluate(expr.parse().unwrap())
+                .await
+                .unwrap()
+        };
+        for (expr, routes, aggregated) in [
+            ("AS-FOO", "{192.0.2.0/25, 192.0.2.128/25}", "{192.0.2.0/24}"),
+            (
+                "AS-FOO^-",
+                "{192.0.2.0/25^-, 192.0.2.128/25^-}",
+                "{192.0.2.0/24^-}",
+            ),
+            (
+                "AS-FOO^+",
+                "{192.0.2.0/25^+, 192.0.2.128/25^+}",
+                "{192.0.2.0/24^+}",
+            ),
+            (
+                "AS-FOO^24-25",
+                "{192.0.2.0/25, 192.0.2.128/25}",
+                "{192.0.2.0/24^24-25}",
+            ),
+        ] {
+            assert_eq!(
+                evaluate(false, expr).await,
+                evaluate(false, routes).await,
+                "{expr}"
+            );
+            assert_eq!(
+                evaluate(true, expr).await,
+                evaluate(false, aggregated).await,
+                "{expr} aggregated"
+            );
+        }
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn stale_if_error() {
+        let dir = std::env::temp_dir().join(format!("bgpfu-client-stale-{}", std::process::id()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(serve(
+            listener,
+            [("!iAS-FOO,1", "AS65001"), ("!gAS65001", "192.0.2.0/24")]
+                .into_iter()
+                .collect(),
+        ));
+        // every entry is expired as soon as it is stored, so is only used if the server is down
+        let evaluate = || async {
+            EvaluatorBuilder::new("127.0.0.1", port)
+                .disk_cache(DiskCache::new(&dir, Duration::ZERO))
+                .build_async()
+                .await
+                .unwrap()
+                .evaluate_with_report("AS-FOO".parse().unwrap())
+                .await
+                .unwrap()
+        };
+        let (set, report) = evaluate().await;
+        assert_eq!(set.prefixes().count(), 1);
+        assert!(report.is_empty());
+        server.abort();
+        assert!(server.await.unwrap_err().is_cancelled());
+        assert_eq!(evaluate().await.0, set);
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn servers_are_separate() {
+        let dir = std::env::temp_dir().join(format!("bgpfu-client-servers-{}", std::process::id()));
+        let cache = Cache::new();
+        for route in ["192.0.2.0/24", "198.51.100.0/24"] {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let server = tokio::spawn(serve(
+                listener,
+                std::iter::once(("!gAS65001", route)).collect(),
+            ));
+            // each server's routes are cached separately, in memory and on disk
+            let evaluator = EvaluatorBuilder::new("127.0.0.1", port)
+                .cache(cache.clone())
+                .disk_cache(DiskCache::new(&dir, Duration::from_secs(3600)))
+                .build_async()
+                .await
+                .unwrap();
+            let set = evaluator
+                .evaluate("AS65001".parse().unwrap())
+                .await
+                .unwrap();
+            assert_eq!(
+                set.prefixes()
+                    .map(|prefix| prefix.to_string())
+                    .collect::<Vec<_>>(),
+                [route]
+            );
+            assert_eq!(cache.hits(), 0);
+            server.abort();
+        }
+        assert_eq!(cache.len(), 2);
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn member_errors() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(serve(
+            listener,
+            [
+                ("!iAS-FOO,1", "AS65001"),
+                ("!iAS-BAR,1", "AS65001"),
+                ("!gAS65001", "F internal error"),
+            ]
+            .into_iter()
+            .collect(),
+        ));
+        let client = Client::new(vec![addr], Vec::new(), NonZeroUsize::MIN, 0);
+        let table = client
+            .resolve(Names {
+                as_sets: ["AS-FOO", "AS-BAR"]
+                    .into_iter()
+                    .map(|as_set| as_set.parse().unwrap())
+                    .collect(),
+                ..Names::default()
+            })
+            .await
+            .unwrap();
+        // the member's error is kept on its own entry, and copied to each `as-set`
+        assert_eq!(table.autnums[&"AS65001".parse().unwrap()].errors.len(), 1);
+        for entry in table.as_sets.values() {
+            assert_eq!(entry.errors.len(), 1);
+        }
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn cache_hits() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(serve(
+            listener,
+            [("!iAS-FOO,1", "AS65001"), ("!gAS65001", "192.0.2.0/24")]
+                .into_iter()
+                .collect(),
+        ));
+        let cache = Cache::new();
+        let evaluator = EvaluatorBuilder::new("127.0.0.1", port)
+            .cache(cache.clone())
+            .build_async()
+            .await
+            .unwrap();
+        let set = evaluator.evaluate("AS-FOO".parse().unwrap()).await.unwrap();
+        assert_eq!(set.prefixes().count(), 1);
+        // both the `as-set` and its IPv4-only member are cached
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.hits(), 0);
+        for expr in ["AS-FOO", "AS65001"] {
+            let (cached, report) = evaluator
+                .evaluate_with_report(expr.parse().unwrap())
+                .await
+                .unwrap();
+            assert_eq!(cached, set);
+            assert!(report.is_empty());
+        }
+        assert_eq!(cache.hits(), 2);
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn routes_not_found() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // neither the member nor the `as-set` has any IPv6 routes
+        let server = tokio::spawn(serve(
+            listener,
+            [
+                ("!iAS-FOO,1", "AS65001"),
+                ("!gAS65001", "192.0.2.0/24"),
+                ("!a4AS-FOO", "192.0.2.0/24"),
+            ]
+            .into_iter()
+            .collect(),
+        ));
+        for aggregate in [false, true] {
+            let (set, report) = EvaluatorBuilder::new("127.0.0.1", port)
+                .strict(true)
+                .aggregate(aggregate)
+                .build_async()
+                .await
+                .unwrap()
+                .evaluate_with_report("AS-FOO".parse().unwrap())
+                .await
+                .unwrap();
+            assert_eq!(set.prefixes().count(), 1);
+            assert!(report.is_empty());
+        }
+        server.abort();
+    }
+}
@@ -0,0 +1,112 @@
+use ip::{any, concrete::Afi, traits::PrefixSet as _, Any, PrefixSet};
+
+/// The differences between two evaluated prefix sets.
+///
+/// # Examples
+///
+/// ```
+/// use bgpfu::Difference;
+/// use ip::{concrete::Afi, traits::PrefixSet as _, Any, Prefix, PrefixSet};
+///
+/// let old: PrefixSet<Any> = ["192.0.2.0/24", "2001:db8::/32"]
+///     .into_iter()
+///     .map(|prefix| prefix.parse::<Prefix<Any>>())
+///     .collect::<Result<_, _>>()?;
+/// let new: PrefixSet<Any> = ["192.0.2.0/24", "198.51.100.0/24"]
+///     .into_iter()
+///     .map(|prefix| prefix.parse::<Prefix<Any>>())
+///     .collect::<Result<_, _>>()?;
+///
+/// let diff = Difference::between(&old, &new);
+/// assert_eq!(diff.added().ranges().count(), 1);
+/// assert!(diff.afi(Afi::Ipv4).removed().ranges().next().is_none());
+/// assert_eq!(diff.afi(Afi::Ipv6).removed().ranges().count(), 1);
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    added: PrefixSet<Any>,
+    removed: PrefixSet<Any>,
+}
+
+impl Difference {
+    /// Find the prefixes that are in `new` but not `old`, and those that are in `old` but not
+    /// `new`.
+    #[must_use]
+    pub fn between(old: &PrefixSet<Any>, new: &PrefixSet<Any>) -> Self {
+        Self {
+            added: new.clone() - old.clone(),
+            removed: old.clone() - new.clone(),
+        }
+    }
+
+    /// Get the prefixes that are only in the new set.
+    #[must_use]
+    pub const fn added(&self) -> &PrefixSet<Any> {
+        &self.added
+    }
+
+    /// Get the prefixes that are only in the old set.
+    #[must_use]
+    pub const fn removed(&self) -> &PrefixSet<Any> {
+        &self.removed
+    }
+
+    /// Returns `true` if the sets contain the same prefixes.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.added.prefixes().next().is_none() && self.removed.prefixes().next().is_none()
+    }
+
+    /// Get the differences between the prefixes of address family `afi` only.
+    #[must_use]
+    pub fn afi(&self, afi: Afi) -> Self {
+        Self {
+            added: of_afi(&self.added, afi),
+            removed: of_afi(&self.removed, afi),
+        }
+    }
+}
+
+/// Get the prefix ranges of `set` in address family `afi`.
+fn of_afi(set: &PrefixSet<Any>, afi: Afi) -> PrefixSet<Any> {
+    set.ranges()
+        .filter(|range| match range {
+            any::PrefixRange::Ipv4(_) => afi == Afi::Ipv4,
+            any::PrefixRange::Ipv6(_) => afi == Afi::Ipv6,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ip::{traits::PrefixRange as _, Prefix};
+
+    use super::*;
+
+    fn set(ranges: &[&str]) -> PrefixSet<Any> {
+        ranges
+            .iter()
+            .map(|range| range.parse::<Prefix<Any>>().unwrap().into())
+            .map(any::PrefixRange::or_longer)
+            .collect()
+    }
+
+    #[test]
+    fn overlapping_ranges() {
+        let old = set(&["192.0.2.0/24", "2001:db8::/32"]);
+        let new = set(&["192.0.2.0/25", "2001:db8::/32", "2001:db8::/31"]);
+        let diff = Difference::between(&old, &new);
+        assert!(!diff.is_empty());
+        let ipv4 = diff.afi(Afi::Ipv4);
+        assert!(ipv4.added().ranges().next().is_none());
+        assert!(ipv4.removed().contains("192.0.2.0/24".parse().unwrap()));
+        assert!(ipv4.removed().contains("192.0.2.128/26".parse().unwrap()));
+        assert!(!ipv4.removed().contains("192.0.2.0/26".parse().unwrap()));
+        let ipv6 = diff.afi(Afi::Ipv6);
+        assert!(ipv6.removed().ranges().next().is_none());
+        assert!(ipv6.added().contains("2001:db9::/32".parse().unwrap()));
+        assert!(!ipv6.added().contains("2001:db8::/48".parse().unwrap()));
+        assert!(Difference::between(&new, &new).is_empty());
+    }
+}
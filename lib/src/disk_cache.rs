@@ -0,0 +1,410 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{Display, Write as _},
+    hash::Hash,
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use ip::{traits::PrefixSet as _, Any, Prefix, PrefixSet};
+
+use tokio::fs;
+
+use crate::{
+    cache::Scope,
+    rov::RovAction,
+    table::{Entry, Names, Table},
+};
+
+/// A persistent, on-disk cache of resolved RPSL names.
+///
+/// Each resolved `as-set`, `route-set` and `aut-num` is stored as a list of prefixes, and each
+/// `filter-set` as its `mp-filter` expression, in a file beneath the cache directory. Entries
+/// younger than the configured TTL are used in place of querying the IRRd server.
+///
+/// Entries are stored separately for each IRRd server (along with its fallbacks) and set of IRR
+/// sources queried, and for evaluators that drop RPKI-invalid routes. Names for which any
+/// non-fatal error was encountered are not stored.
+///
+/// If the IRRd server cannot be reached, expired entries are used instead, and a warning is
+/// logged, so that a transient outage does not prevent filters from being generated.
+///
+/// # Examples
+///
+/// ``` no_run
+/// use std::time::Duration;
+///
+/// use bgpfu::{DiskCache, RpslEvaluator};
+///
+/// let evaluator = RpslEvaluator::builder("whois.radb.net", 43)
+///     .disk_cache(DiskCache::new("/var/cache/bgpfu", Duration::from_secs(3600)))
+///     .build()?;
+/// # Ok::<_, bgpfu::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+const AS_SETS: &str = "as-set";
+const ROUTE_SETS: &str = "route-set";
+const AUTNUMS: &str = "aut-num";
+const FILTER_SETS: &str = "filter-set";
+
+impl DiskCache {
+    /// Construct a new [`DiskCache`] in `dir`, with entries expiring after `ttl`.
+    ///
+    /// The directory is created when the first entry is written, if it does not already exist.
+    pub fn new<P: Into<PathBuf>>(dir: P, ttl: Duration) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl,
+        }
+    }
+
+    /// Get the cache directory.
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Get the time after which cache entries expire.
+    #[must_use]
+    pub const fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    fn path<K: Display>(&self, scope: &Scope, kind: &str, key: &K) -> PathBuf {
+        let mut dir = if scope.sources.is_empty() {
+            "default".to_owned()
+        } else {
+            scope.sources.join(",")
+        };
+        // the VRPs change over time, so entries are not separated by the VRPs used
+        if matches!(scope.rov, Some((_, RovAction::Drop))) {
+            dir.push_str("+rov");
+        }
+        if scope.aggregate {
+            dir.push_str("+aggregate");
+        }
+        self.dir
+            .join(scope.servers.join(","))
+            .join(dir)
+            .join(kind)
+            .join(key.to_string())
+    }
+
+    /// Move any names in `names` that are cached for `scope` into `table`, returning the
+    /// remaining names.
+    ///
+    /// Expired entries are only used if `allow_expired` is `true`.
+    pub(crate) async fn lookup(
+        &self,
+        scope: &Scope,
+        names: Names,
+        table: &mut Table,
+        allow_expired: bool,
+    ) -> Names {
+        let Names {
+            filter_sets,
+            as_sets,
+            route_sets,
+            autnums,
+        } = names;
+        Names {
+            filter_sets: self
+                .lookup_kind(
+                    scope,
+                    FILTER_SETS,
+                    filter_sets,
+                    &mut table.filter_sets,
+                    allow_expired,
+                    |data| data.parse().ok().map(Some),
+                )
+                .await,
+            as_sets: self
+                .lookup_kind(
+                    scope,
+                    AS_SETS,
+                    as_sets,
+                    &mut table.as_sets,
+                    allow_expired,
+                    parse_prefixes,
+                )
+                .await,
+            route_sets: self
+                .lookup_kind(
+                    scope,
+                    ROUTE_SETS,
+                    route_sets,
+                    &mut table.route_sets,
+                    allow_expired,
+                    parse_prefixes,
+                )
+                .await,
+            autnums: self
+                .lookup_kind(
+                    scope,
+                    AUTNUMS,
+                    autnums,
+                    &mut table.autnums,
+                    allow_expired,
+                    parse_prefixes,
+                )
+                .await,
+        }
+    }
+
+    async fn lookup_kind<K, T, F>(
+        &self,
+        scope: &Scope,
+        kind: &str,
+        keys: HashSet<K>,
+        entries: &mut HashMap<K, Entry<T>>,
+        allow_expired: bool,
+        parse: F,
+    ) -> HashSet<K>
+    where
+        K: Display + Eq + Hash + Send + Sync,
+        T: Send,
+        F: Fn(&str) -> Option<T> + Send,
+    {
+        let mut remaining = HashSet::new();
+        for key in keys {
+            let name = format!("{kind} {key}");
+            let output = self
+                .read(self.path(scope, kind, &key), &name, allow_expired)
+                .await
+                .and_then(|data| {
+                    let output = parse(&data);
+                    if output.is_none() {
+                        tracing::warn!("ignoring malformed on-disk cache entry for {name}");
+                    }
+                    output
+                });
+            if let Some(output) = output {
+                _ = entries.insert(
+                    key,
+                    Entry {
+                        output,
+                        errors: Vec::new(),
+                    },
+                );
+            } else {
+                _ = remaining.insert(key);
+            }
+        }
+        remaining
+    }
+
+    async fn read(&self, path: PathBuf, name: &str, allow_expired: bool) -> Option<String> {
+        let age = fs::metadata(&path)
+            .await
+            .and_then(|metadata| metadata.modified())
+            .ok()?
+            .elapsed()
+            .unwrap_or_default();
+        if age > self.ttl {
+            if !allow_expired {
+                tracing::debug!("on-disk cache entry for {name} has expired");
+                return None;
+            }
+            tracing::warn!(
+                "using expired on-disk cache entry for {name}, last updated {}s ago",
+                age.as_secs()
+            );
+        }
+        match fs::read_to_string(&path).await {
+            Ok(data) => Some(data),
+            Err(err) => {
+                tracing::warn!(
+                    "failed to read on-disk cache entry {}: {err}",
+                    path.display()
+                );
+                None
+            }
+        }
+    }
+
+    /// Write the entries in `table` for each of `names` to the cache for `scope`.
+    ///
+    /// Entries recording any errors are not written. Failures are logged, but are otherwise
+    /// ignored.
+    pub(crate) async fn store(&self, scope: &Scope, names: &Names, table: &Table) {
+        for filter_set in &names.filter_sets {
+            if let Some(Entry {
+                output: Some(expr),
+                errors,
+            }) = table.filter_sets.get(filter_set)
+            {
+                if errors.is_empty() {
+                    self.write(self.path(scope, FILTER_SETS, filter_set), expr.to_string())
+                        .await;
+                }
+            }
+        }
+        self.store_prefixes(scope, AS_SETS, &names.as_sets, &table.as_sets)
+            .await;
+        self.store_prefixes(scope, ROUTE_SETS, &names.route_sets, &table.route_sets)
+            .await;
+        self.store_prefixes(scope, AUTNUMS, &names.autnums, &table.autnums)
+            .await;
+    }
+
+    async fn store_prefixes<K>(
+        &self,
+        scope: &Scope,
+        kind: &str,
+        keys: &HashSet<K>,
+        entries: &HashMap<K, Entry<PrefixSet<Any>>>,
+    ) where
+        K: Display + Eq + Hash + Sync,
+    {
+        for key in keys {
+            let Some(entry) = entries.get(key) else {
+                continue;
+            };
+            if entry.errors.is_empty() {
+                let data = entry
+                    .output
+                    .prefixes()
+                    .fold(String::new(), |mut data, prefix| {
+                        _ = writeln!(data, "{prefix}");
+                        data
+                    });
+                self.write(self.path(scope, kind, key), data).await;
+            } else {
+                tracing::debug!("not caching {kind} {key}, which recorded errors");
+            }
+        }
+    }
+
+    async fn write(&self, path: PathBuf, data: String) {
+        if let Err(err) = write_atomic(&path, data).await {
+            tracing::warn!(
+                "failed to write on-disk cache entry {}: {err}",
+                path.display()
+            );
+        }
+    }
+}
+
+/// Write `data` to a temporary file alongside `path`, and then move it into place, so that
+/// concurrent readers never see a partially written entry.
+async fn write_atomic(path: &Path, data: String) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".tmp.{}", std::process::id()));
+    fs::write(&tmp, data).await?;
+    fs::rename(&tmp, path).await
+}
+
+fn parse_prefixes(data: &str) -> Option<PrefixSet<Any>> {
+    data.split_whitespace()
+        .map(str::parse::<Prefix<Any>>)
+        .collect::<Result<_, _>>()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{iter, sync::Arc};
+
+    use super::*;
+    use crate::irrd;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("bgpfu-disk-cache-{name}-{}", std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn store_and_expire() {
+        let dir = temp_dir("expire");
+        let scope = Scope::default();
+        let autnum: rpsl::names::AutNum = "AS65000".parse().unwrap();
+        let names = Names {
+            autnums: iter::once(autnum).collect(),
+            ..Names::default()
+        };
+        let mut table = Table::default();
+        _ = table.autnums.insert(
+            autnum,
+            Entry {
+                output: parse_prefixes("192.0.2.0/24\n2001:db8::/32\n").unwrap(),
+                errors: Vec::new(),
+            },
+        );
+
+        let cache = DiskCache::new(&dir, Duration::from_secs(3600));
+        cache.store(&scope, &names, &table).await;
+        let mut table = Table::default();
+        assert!(cache
+            .lookup(&scope, names.clone(), &mut table, false)
+            .await
+            .is_empty());
+        assert_eq!(table.autnums[&autnum].output.prefixes().count(), 2);
+
+        let radb = Scope {
+            sources: vec!["RADB".to_owned()],
+            ..Scope::default()
+        };
+        let mut table = Table::default();
+        assert!(!cache
+            .lookup(&radb, names.clone(), &mut table, true)
+            .await
+            .is_empty());
+
+        let cache = DiskCache::new(&dir, Duration::ZERO);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let mut table = Table::default();
+        assert!(!cache
+            .lookup(&scope, names.clone(), &mut table, false)
+            .await
+            .is_empty());
+        assert!(cache
+            .lookup(&scope, names, &mut table, true)
+            .await
+            .is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn errors_are_not_stored() {
+        let dir = temp_dir("errors");
+        let scope = Scope::default();
+        let as_set: rpsl::names::AsSet = "AS-FOO".parse().unwrap();
+        let names = Names {
+            as_sets: iter::once(as_set.clone()).collect(),
+            ..Names::default()
+        };
+        let mut table = Table::default();
+        _ = table.as_sets.insert(
+            as_set.clone(),
+            Entry {
+                output: parse_prefixes("192.0.2.0/24\n").unwrap(),
+                errors: vec![Arc::new(
+                    irrd::Error::ResponseErr(
+                        irrd::Query::Ipv4Routes("AS65000".parse().unwrap()),
+                        irrd::ResponseError::Other("connection reset".to_owned()),
+                    )
+                    .into(),
+                )],
+            },
+        );
+
+        let cache = DiskCache::new(&dir, Duration::from_secs(3600));
+        cache.store(&scope, &names, &table).await;
+        let mut table = Table::default();
+        assert!(cache
+            .lookup(&scope, names, &mut table, true)
+            .await
+            .as_sets
+            .contains(&as_set));
+        assert!(!dir.exists());
+    }
+}
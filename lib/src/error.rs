@@ -1,11 +1,21 @@
-use rpsl::{attr::AttributeType, error::ParseError, expr::eval::EvaluationError, obj::RpslObject};
+use std::{io, path::PathBuf, sync::Arc, time::Duration};
+
+use ip::concrete::Afi;
+
+use rpsl::{
+    attr::AttributeType,
+    error::ParseError,
+    expr::eval::EvaluationError,
+    names::{AsSet, AutNum},
+    obj::RpslObject,
+};
 
 /// Error condition variants.
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     /// IRR query protocol errors.
     #[error(transparent)]
-    Irr(#[from] irrc::Error),
+    Irr(#[from] crate::irrd::Error),
     /// RPSL expression evaluation errors.
     #[error(transparent)]
     Evaluation(#[from] EvaluationError),
@@ -18,7 +28,111 @@ pub enum Error {
     /// The required attribute was not found in the RPSL object.
     #[error("no {0} attribute found in RPSL object {1}")]
     FindAttribute(AttributeType, RpslObject),
+    /// A `PeerAS` token was encountered outside of a peer-specific evaluation.
+    #[error("cannot resolve 'PeerAS' without a peer autonomous system")]
+    PeerAs,
     /// An unexpected RPSL object type was received.
     #[error("unexpected RPSL object {0}")]
     RpslObjectClass(RpslObject),
+    /// The named RPSL object was not found in the loaded database dumps.
+    #[error("no {0} object named {1} found in the loaded RPSL database dumps")]
+    ObjectNotFound(&'static str, String),
+    /// An RPSL database dump couldn't be read.
+    #[error("failed to read RPSL database dump {}", .0.display())]
+    ReadDump(PathBuf, #[source] io::Error),
+    /// A validated ROA payload set couldn't be read.
+    #[error("failed to read validated ROA payloads from {}", .0.display())]
+    ReadVrps(PathBuf, #[source] io::Error),
+    /// A local IRR database mirror couldn't be read or written.
+    #[error("failed to read or write IRR database mirror {}", .0.display())]
+    Mirror(PathBuf, #[source] io::Error),
+    /// Updates to a local IRR database mirror couldn't be retrieved from an NRTM server.
+    #[error("failed to retrieve NRTM updates from {0}")]
+    Nrtm(String, #[source] io::Error),
+    /// A validated ROA payload set couldn't be parsed.
+    #[error("failed to parse validated ROA payloads")]
+    ParseVrps(#[source] serde_json::Error),
+    /// A validated ROA payload has an invalid prefix or origin.
+    #[error("invalid validated ROA payload for prefix {0}")]
+    InvalidVrp(String),
+    /// An evaluation snapshot couldn't be read or written.
+    #[error("failed to read or write evaluation snapshot {}", .0.display())]
+    Snapshot(PathBuf, #[source] io::Error),
+    /// An evaluation snapshot couldn't be parsed or serialised.
+    #[error("failed to parse or serialise evaluation snapshot")]
+    ParseSnapshot(#[source] serde_json::Error),
+    /// An evaluation snapshot is in an unsupported version of the snapshot format.
+    #[error("unsupported evaluation snapshot format version {0}")]
+    SnapshotVersion(u64),
+    /// An evaluation snapshot contains an invalid prefix range.
+    #[error("invalid prefix range {0} in evaluation snapshot")]
+    InvalidSnapshot(String),
+    /// The `tokio` runtime used to drive queries couldn't be constructed.
+    #[error("failed to construct the query runtime")]
+    Runtime(#[source] io::Error),
+    /// The `as-set` to be expanded was not found.
+    #[error("no as-set object named {0} found")]
+    AsSetNotFound(AsSet),
+    /// The `aut-num` whose routing policy is to be compiled was not found.
+    #[error("no aut-num object named {0} found")]
+    AutNumNotFound(AutNum),
+    /// A routing policy expression couldn't be compiled.
+    #[error("cannot compile policy '{0}': {1}")]
+    Policy(String, String),
+    /// An `as-set` was nested more deeply than the configured limit.
+    #[error("as-set {0} is nested more than {1} levels deep")]
+    DepthLimit(AsSet, usize),
+    /// An evaluation resolved more autonomous systems than the configured limit.
+    #[error("evaluation resolved more than {0} autonomous systems")]
+    AutNumLimit(usize),
+    /// An evaluation resolved more prefix ranges of an address family than the configured limit.
+    #[error("evaluation resolved more than {1} {0} prefix ranges")]
+    PrefixLimit(Afi, usize),
+    /// An evaluation took longer than the configured limit.
+    #[error("evaluation did not complete within {0:?}")]
+    Timeout(Duration),
+    /// An error encountered while resolving an RPSL name that is shared by several evaluations.
+    #[error(transparent)]
+    Shared(Arc<Self>),
+}
+
+impl From<Arc<Self>> for Error {
+    fn from(err: Arc<Self>) -> Self {
+        Arc::try_unwrap(err).unwrap_or_else(Self::Shared)
+    }
+}
+
+impl Error {
+    /// Returns `true` if the error resulted from exceeding one of the configured [`Limits`].
+    ///
+    /// [`Limits`]: crate::Limits
+    #[must_use]
+    pub const fn is_limit(&self) -> bool {
+        matches!(
+            self,
+            Self::DepthLimit(..) | Self::AutNumLimit(_) | Self::PrefixLimit(..) | Self::Timeout(_)
+        )
+    }
+
+    /// Unwrap a limit error that was raised while resolving an RPSL name, so that it is returned
+    /// to the caller as itself, rather than as a resolution failure.
+    pub(crate) fn surface_limit(self) -> Self {
+        let Self::Evaluation(EvaluationError::Resolution { item, source }) = self else {
+            return self;
+        };
+        match source.downcast::<Self>() {
+            Ok(err) => {
+                let err = err.surface_limit();
+                if err.is_limit() {
+                    err
+                } else {
+                    Self::Evaluation(EvaluationError::Resolution {
+                        item,
+                        source: Box::new(err),
+                    })
+                }
+            }
+            Err(source) => Self::Evaluation(EvaluationError::Resolution { item, source }),
+        }
+    }
 }
@@ -0,0 +1,257 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, mem,
+};
+
+use rpsl::{
+    expr::AsSetMember,
+    names::{AsSet, AutNum},
+};
+
+use crate::error::Error;
+
+/// The recursive expansion of an `as-set` into the autonomous systems that it contains.
+///
+/// # Examples
+///
+/// ``` no_run
+/// use bgpfu::RpslEvaluator;
+///
+/// let mut evaluator = RpslEvaluator::new("whois.radb.net", 43)?;
+/// let expansion = evaluator.expand_as_set_with_tree(&"AS-FOO".parse()?)?;
+/// println!("{} autonomous systems", expansion.autnums().len());
+/// if let Some(tree) = expansion.tree() {
+///     print!("{tree}");
+/// }
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsSetExpansion {
+    as_set: AsSet,
+    autnums: HashSet<AutNum>,
+    tree: Option<MemberTree>,
+}
+
+impl AsSetExpansion {
+    pub(crate) const fn new(
+        as_set: AsSet,
+        autnums: HashSet<AutNum>,
+        tree: Option<MemberTree>,
+    ) -> Self {
+        Self {
+            as_set,
+            autnums,
+            tree,
+        }
+    }
+
+    /// Get the `as-set` that was expanded.
+    #[must_use]
+    pub const fn as_set(&self) -> &AsSet {
+        &self.as_set
+    }
+
+    /// Get every autonomous system contained in the `as-set`, directly or via nested `as-set`s.
+    #[must_use]
+    pub const fn autnums(&self) -> &HashSet<AutNum> {
+        &self.autnums
+    }
+
+    /// Get the tree of `as-set` members, if it was requested.
+    #[must_use]
+    pub const fn tree(&self) -> Option<&MemberTree> {
+        self.tree.as_ref()
+    }
+
+    /// Convert the expansion into the set of autonomous systems contained in the `as-set`.
+    #[must_use]
+    pub fn into_autnums(self) -> HashSet<AutNum> {
+        self.autnums
+    }
+}
+
+/// A node in the tree of members of an `as-set`.
+///
+/// The [`Display`][fmt::Display] implementation renders the tree one member per line, with each
+/// level of nesting indented by two spaces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemberTree {
+    /// An `aut-num` member.
+    AutNum(AutNum),
+    /// An `as-set` and its members, in the order in which they appear in the object, or `None` if
+    /// the `as-set` could not be found.
+    AsSet(AsSet, Option<Vec<Self>>),
+    /// An `as-set` whose members appear elsewhere in the tree.
+    Repeated(AsSet),
+}
+
+impl MemberTree {
+    fn write(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        write!(f, "{:indent$}", "")?;
+        match self {
+            Self::AutNum(autnum) => writeln!(f, "{autnum}"),
+            Self::AsSet(as_set, Some(members)) => {
+                writeln!(f, "{as_set}")?;
+                members
+                    .iter()
+                    .try_for_each(|member| member.write(f, indent + 2))
+            }
+            Self::AsSet(as_set, None) => writeln!(f, "{as_set} (not found)"),
+            Self::Repeated(as_set) => writeln!(f, "{as_set} (repeated)"),
+        }
+    }
+}
+
+impl fmt::Display for MemberTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+/// A breadth-first walk of the members of an `as-set`, one level of nesting at a time.
+///
+/// The direct members of the `as-set`s in [`Expansion::pending`] are looked up by the caller,
+/// and passed to [`Expansion::advance`], until no `as-set`s remain pending.
+#[derive(Debug)]
+pub(crate) struct Expansion {
+    root: AsSet,
+    max_depth: Option<usize>,
+    depth: usize,
+    pending: Vec<AsSet>,
+    members: HashMap<AsSet, Option<Vec<AsSetMember>>>,
+    autnums: HashSet<AutNum>,
+}
+
+impl Expansion {
+    /// Begin walking the members of `root`, failing if `as-set`s are nested more than
+    /// `max_depth` levels deep.
+    pub(crate) fn new(root: AsSet, max_depth: Option<usize>) -> Self {
+        Self {
+            pending: vec![root.clone()],
+            root,
+            max_depth,
+            depth: 0,
+            members: HashMap::new(),
+            autnums: HashSet::new(),
+        }
+    }
+
+    /// Get the `as-set`s whose members are required to continue the walk.
+    pub(crate) fn pending(&self) -> &[AsSet] {
+        &self.pending
+    }
+
+    /// Continue the walk using the direct `members` of each pending `as-set`, in the same order
+    /// as [`Expansion::pending`].
+    ///
+    /// Members of `as-set`s that could not be found are `None`.
+    pub(crate) fn advance<I>(&mut self, members: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = Option<Vec<AsSetMember>>>,
+    {
+        let mut next = Vec::new();
+        for (as_set, members) in mem::take(&mut self.pending).into_iter().zip(members) {
+            for member in members.iter().flatten() {
+                match member {
+                    AsSetMember::AutNum(autnum) => _ = self.autnums.insert(*autnum),
+                    AsSetMember::AsSet(as_set) => {
+                        if as_set != &self.root
+                            && !self.members.contains_key(as_set)
+                            && !next.contains(as_set)
+                        {
+                            next.push(as_set.clone());
+                        }
+                    }
+                }
+            }
+            _ = self.members.insert(as_set, members);
+        }
+        if !next.is_empty() {
+            self.depth += 1;
+            if let Some(max) = self.max_depth.filter(|max| self.depth > *max) {
+                return Err(Error::DepthLimit(self.root.clone(), max));
+            }
+        }
+        self.pending = next;
+        Ok(())
+    }
+
+    /// Finish the walk, returning every `aut-num` reached.
+    pub(crate) fn into_autnums(self) -> HashSet<AutNum> {
+        self.autnums
+    }
+
+    /// Finish the walk, returning the [`AsSetExpansion`] of the root `as-set`, and including the
+    /// tree of its members if `tree` is `true`.
+    ///
+    /// An [`Error::AsSetNotFound`] is returned if the root `as-set` could not be found.
+    pub(crate) fn into_expansion(mut self, tree: bool) -> Result<AsSetExpansion, Error> {
+        if !matches!(self.members.get(&self.root), Some(Some(_))) {
+            return Err(Error::AsSetNotFound(self.root));
+        }
+        let tree = tree.then(|| {
+            let mut visited = HashSet::new();
+            build_tree(&self.root, &mut self.members, &mut visited)
+        });
+        Ok(AsSetExpansion::new(self.root, self.autnums, tree))
+    }
+}
+
+/// Construct the [`MemberTree`] of `as_set`, depth first, from the direct `members` of each
+/// `as-set` reached during a walk.
+fn build_tree(
+    as_set: &AsSet,
+    members: &mut HashMap<AsSet, Option<Vec<AsSetMember>>>,
+    visited: &mut HashSet<AsSet>,
+) -> MemberTree {
+    if !visited.insert(as_set.clone()) {
+        return MemberTree::Repeated(as_set.clone());
+    }
+    let children = members.remove(as_set).flatten().map(|direct| {
+        direct
+            .into_iter()
+            .map(|member| match member {
+                AsSetMember::AutNum(autnum) => MemberTree::AutNum(autnum),
+                AsSetMember::AsSet(nested) => build_tree(&nested, members, visited),
+            })
+            .collect()
+    });
+    MemberTree::AsSet(as_set.clone(), children)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn members(members: &[&str]) -> Option<Vec<AsSetMember>> {
+        members.iter().map(|member| member.parse().ok()).collect()
+    }
+
+    #[test]
+    fn nested_tree() {
+        let mut expansion = Expansion::new("AS-FOO".parse().unwrap(), None);
+        expansion
+            .advance([members(&["AS65001", "AS-BAR", "AS-MISSING"])])
+            .unwrap();
+        expansion
+            .advance([members(&["AS65002", "AS-FOO"]), None])
+            .unwrap();
+        assert!(expansion.pending().is_empty());
+        let expansion = expansion.into_expansion(true).unwrap();
+        assert_eq!(expansion.autnums().len(), 2);
+        assert_eq!(
+            expansion.tree().unwrap().to_string(),
+            "AS-FOO\n  AS65001\n  AS-BAR\n    AS65002\n    AS-FOO (repeated)\n  AS-MISSING (not found)\n"
+        );
+    }
+
+    #[test]
+    fn missing_root() {
+        let mut expansion = Expansion::new("AS-FOO".parse().unwrap(), None);
+        expansion.advance([None]).unwrap();
+        assert!(matches!(
+            expansion.into_expansion(false),
+            Err(Error::AsSetNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,397 @@
+use std::{collections::HashSet, fmt, mem};
+
+use ip::{
+    any::PrefixRange,
+    traits::{Prefix as _, PrefixRange as _, PrefixSet as _},
+    Any, Prefix, PrefixSet,
+};
+
+use rpsl::{
+    expr::{
+        eval::{Evaluate, Evaluator, Resolver},
+        AsSetMember, MpFilterExpr,
+    },
+    names::{AsSet, AutNum, FilterSet, RouteSet},
+    primitive::PeerAs,
+};
+
+use crate::{error::Error, report::Report};
+
+/// The output of an evaluation, along with the derivation of each route and `route-set` member
+/// that contributed to it.
+///
+/// A [`Derivation`] contributes to an output prefix range if the range could have been produced
+/// from it by applying range operators, so that routes remain associated with the ranges derived
+/// from them.
+///
+/// # Examples
+///
+/// ``` no_run
+/// use bgpfu::RpslEvaluator;
+///
+/// let explanation = RpslEvaluator::new("whois.radb.net", 43)?.explain("AS-FOO".parse()?)?;
+/// for (range, derivations) in explanation.ranges() {
+///     println!("{range}");
+///     for derivation in derivations {
+///         println!("    {derivation}");
+///     }
+/// }
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Explanation {
+    output: PrefixSet<Any>,
+    derivations: Vec<Derivation>,
+    report: Report,
+}
+
+/// The chain of RPSL names through which a route or `route-set` member was reached.
+///
+/// The chain starts from a name appearing in the evaluated expression, or in a `filter-set`
+/// referenced by it. Routes end with the `aut-num` that originates them.
+///
+/// `route-set` members are resolved by the IRRd server in a single step, so their derivations
+/// consist only of the `route-set` named in the expression.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Derivation {
+    path: Vec<Step>,
+    range: PrefixRange,
+    source: Option<String>,
+}
+
+/// A single RPSL name in a [`Derivation`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Step {
+    /// An `as-set`.
+    AsSet(AsSet),
+    /// A `route-set`.
+    RouteSet(RouteSet),
+    /// An `aut-num`.
+    AutNum(AutNum),
+}
+
+impl Explanation {
+    /// Get the evaluated prefix set.
+    #[must_use]
+    pub const fn output(&self) -> &PrefixSet<Any> {
+        &self.output
+    }
+
+    /// Get the [`Report`] of the non-fatal errors encountered during the evaluation.
+    #[must_use]
+    pub const fn report(&self) -> &Report {
+        &self.report
+    }
+
+    pub(crate) fn with_report(self, report: Report) -> Self {
+        Self { report, ..self }
+    }
+
+    /// Get the derivations of everything that contributed to the output.
+    #[must_use]
+    pub fn derivations(&self) -> &[Derivation] {
+        &self.derivations
+    }
+
+    /// Get the derivations that contributed to `range`.
+    pub fn contributors(&self, range: &PrefixRange) -> impl Iterator<Item = &Derivation> + '_ {
+        let range = range.clone();
+        self.derivations
+            .iter()
+            .filter(move |derivation| contributes(&derivation.range, &range))
+    }
+
+    /// Iterate over the prefix ranges in the output, along with the derivations that
+    /// contributed to each.
+    ///
+    /// Ranges without any derivations originate from prefix literals in the expression.
+    pub fn ranges(&self) -> impl Iterator<Item = (PrefixRange, Vec<&Derivation>)> + '_ {
+        self.output.ranges().map(|range| {
+            let derivations = self.contributors(&range).collect();
+            (range, derivations)
+        })
+    }
+}
+
+impl Derivation {
+    /// Get the chain of RPSL names, starting from the outermost.
+    #[must_use]
+    pub fn path(&self) -> &[Step] {
+        &self.path
+    }
+
+    /// Get the contributed prefix range. For a route, this contains only the route prefix.
+    #[must_use]
+    pub const fn range(&self) -> &PrefixRange {
+        &self.range
+    }
+
+    /// Get the origin of the route, or `None` for a `route-set` member.
+    #[must_use]
+    pub fn origin(&self) -> Option<AutNum> {
+        match self.path.last() {
+            Some(Step::AutNum(autnum)) => Some(*autnum),
+            _ => None,
+        }
+    }
+
+    /// Get the IRR database containing the `route` or `route6` object, if known.
+    #[must_use]
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+}
+
+impl fmt::Display for Derivation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.path {
+            write!(f, "{step} -> ")?;
+        }
+        if self.origin().is_none() {
+            return write!(f, "{}", self.range);
+        }
+        match self.range.prefix() {
+            prefix @ Prefix::<Any>::Ipv4(_) => write!(f, "route {prefix}")?,
+            prefix @ Prefix::<Any>::Ipv6(_) => write!(f, "route6 {prefix}")?,
+        }
+        if let Some(source) = &self.source {
+            write!(f, " ({source})")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AsSet(as_set) => as_set.fmt(f),
+            Self::RouteSet(route_set) => route_set.fmt(f),
+            Self::AutNum(autnum) => autnum.fmt(f),
+        }
+    }
+}
+
+/// Returns `true` if the prefix `range` could have been produced from the `contributed` range
+/// by applying range operators, i.e. if their prefixes overlap, and `contributed` is no more
+/// specific than the longest prefixes in `range`.
+fn contributes(contributed: &PrefixRange, range: &PrefixRange) -> bool {
+    let overlaps = match (contributed.prefix(), range.prefix()) {
+        (Prefix::<Any>::Ipv4(a), Prefix::<Any>::Ipv4(b)) => a.contains(&b) || b.contains(&a),
+        (Prefix::<Any>::Ipv6(a), Prefix::<Any>::Ipv6(b)) => a.contains(&b) || b.contains(&a),
+        _ => false,
+    };
+    overlaps && contributed.lower() <= range.upper()
+}
+
+/// The lookups needed to explain an evaluation, beyond those needed to perform it.
+pub(crate) trait Lookup {
+    /// Get the direct members of each of `as_sets`, or `None` for any that cannot be found.
+    fn as_set_members(&mut self, as_sets: &[AsSet])
+        -> Result<Vec<Option<Vec<AsSetMember>>>, Error>;
+
+    /// Get the routes originated by each of `autnums`.
+    fn originated_routes(&mut self, autnums: &[AutNum]) -> Result<Vec<PrefixSet<Any>>, Error>;
+
+    /// Get the IRR database containing the `route` or `route6` object for each `(prefix,
+    /// origin)` pair in `routes`, if known.
+    fn route_sources(
+        &mut self,
+        routes: &[(Prefix<Any>, AutNum)],
+    ) -> Result<Vec<Option<String>>, Error>;
+}
+
+/// An [`Evaluator`] that delegates name resolution to an inner evaluator, recording the
+/// derivation of everything that it resolves.
+#[derive(Debug)]
+pub(crate) struct Explainer<'e, E> {
+    inner: &'e mut E,
+    peer_as: Option<AutNum>,
+    derivations: Vec<Derivation>,
+}
+
+impl<'e, E: Lookup> Explainer<'e, E> {
+    /// Construct a new [`Explainer`] wrapping `inner`, which substitutes `peer_as` for
+    /// `PeerAS`, if any.
+    pub(crate) fn new(inner: &'e mut E, peer_as: Option<AutNum>) -> Self {
+        Self {
+            inner,
+            peer_as,
+            derivations: Vec::new(),
+        }
+    }
+
+    /// Evaluate `expr`, and explain the output.
+    pub(crate) fn explain<'a>(mut self, expr: MpFilterExpr) -> Result<Explanation, Error>
+    where
+        E: Evaluator<'a>,
+        MpFilterExpr: Evaluate<'a, Self, Output = PrefixSet<Any>>,
+    {
+        let output = <Self as Evaluator<'a>>::evaluate(&mut self, expr)?;
+        let mut seen = HashSet::new();
+        let mut derivations: Vec<_> = mem::take(&mut self.derivations)
+            .into_iter()
+            .filter(|derivation| {
+                output
+                    .ranges()
+                    .any(|range| contributes(&derivation.range, &range))
+                    && seen.insert(derivation.clone())
+            })
+            .collect();
+        let (indices, routes): (Vec<_>, Vec<_>) = derivations
+            .iter()
+            .enumerate()
+            .filter_map(|(i, derivation)| {
+                let origin = derivation.origin()?;
+                Some((i, (derivation.range.prefix(), origin)))
+            })
+            .unzip();
+        tracing::debug!("looking up the sources of {} routes", routes.len());
+        let sources = self.inner.route_sources(&routes)?;
+        for (i, source) in indices.into_iter().zip(sources) {
+            derivations[i].source = source;
+        }
+        Ok(Explanation {
+            output,
+            derivations,
+            report: Report::default(),
+        })
+    }
+
+    /// Find the shortest chain of `as-set`s through which each `aut-num` is a member of
+    /// `as_set`.
+    fn walk(&mut self, as_set: &AsSet) -> Result<Vec<(AutNum, Vec<Step>)>, Error> {
+        let mut paths = vec![(as_set.clone(), vec![Step::AsSet(as_set.clone())])];
+        let mut visited: HashSet<_> = std::iter::once(as_set.clone()).collect();
+        let mut autnums = Vec::new();
+        let mut found = HashSet::new();
+        while !paths.is_empty() {
+            let as_sets: Vec<_> = paths.iter().map(|(as_set, _)| as_set.clone()).collect();
+            let members = self.inner.as_set_members(&as_sets)?;
+            let mut next = Vec::new();
+            for ((_, path), members) in paths.into_iter().zip(members) {
+                for member in members.into_iter().flatten() {
+                    match member {
+                        AsSetMember::AutNum(autnum) => {
+                            if found.insert(autnum) {
+                                autnums.push((autnum, path.clone()));
+                            }
+                        }
+                        AsSetMember::AsSet(as_set) => {
+                            if visited.insert(as_set.clone()) {
+                                let mut path = path.clone();
+                                path.push(Step::AsSet(as_set.clone()));
+                                next.push((as_set, path));
+                            }
+                        }
+                    }
+                }
+            }
+            paths = next;
+        }
+        Ok(autnums)
+    }
+
+    /// Record the derivation of each of the `routes` originated by `autnum`, reached via `path`.
+    fn record_routes(&mut self, mut path: Vec<Step>, autnum: AutNum, routes: &PrefixSet<Any>) {
+        path.push(Step::AutNum(autnum));
+        self.derivations
+            .extend(routes.prefixes().map(|prefix| Derivation {
+                path: path.clone(),
+                range: prefix.into(),
+                source: None,
+            }));
+    }
+}
+
+impl<'a, E: Evaluator<'a>> Evaluator<'a> for Explainer<'_, E> {
+    type Output<T> = <T as Evaluate<'a, Self>>::Output
+    where
+        T: Evaluate<'a, Self>;
+
+    type Error = Error;
+
+    fn finalise<T>(&mut self, output: T::Output) -> Result<Self::Output<T>, Self::Error>
+    where
+        T: Evaluate<'a, Self>,
+    {
+        Ok(output)
+    }
+
+    fn sink_error(&mut self, err: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+        self.inner.sink_error(err)
+    }
+}
+
+impl<'a, E> Resolver<'a, FilterSet, MpFilterExpr> for Explainer<'_, E>
+where
+    E: Resolver<'a, FilterSet, MpFilterExpr, IError = Error>,
+{
+    type IError = Error;
+
+    fn resolve(&mut self, filter_set: &FilterSet) -> Result<MpFilterExpr, Self::IError> {
+        self.inner.resolve(filter_set)
+    }
+}
+
+impl<'a, E> Resolver<'a, AsSet, PrefixSet<Any>> for Explainer<'_, E>
+where
+    E: Lookup + Resolver<'a, AsSet, PrefixSet<Any>, IError = Error>,
+{
+    type IError = Error;
+
+    fn resolve(&mut self, as_set: &AsSet) -> Result<PrefixSet<Any>, Self::IError> {
+        let output = self.inner.resolve(as_set)?;
+        let (autnums, paths): (Vec<_>, Vec<_>) = self.walk(as_set)?.into_iter().unzip();
+        let routes = self.inner.originated_routes(&autnums)?;
+        for ((autnum, path), routes) in autnums.into_iter().zip(paths).zip(routes) {
+            self.record_routes(path, autnum, &routes);
+        }
+        Ok(output)
+    }
+}
+
+impl<'a, E> Resolver<'a, RouteSet, PrefixSet<Any>> for Explainer<'_, E>
+where
+    E: Lookup + Resolver<'a, RouteSet, PrefixSet<Any>, IError = Error>,
+{
+    type IError = Error;
+
+    fn resolve(&mut self, route_set: &RouteSet) -> Result<PrefixSet<Any>, Self::IError> {
+        let output = self.inner.resolve(route_set)?;
+        self.derivations
+            .extend(output.ranges().map(|range| Derivation {
+                path: vec![Step::RouteSet(route_set.clone())],
+                range,
+                source: None,
+            }));
+        Ok(output)
+    }
+}
+
+impl<'a, E> Resolver<'a, AutNum, PrefixSet<Any>> for Explainer<'_, E>
+where
+    E: Lookup + Resolver<'a, AutNum, PrefixSet<Any>, IError = Error>,
+{
+    type IError = Error;
+
+    fn resolve(&mut self, autnum: &AutNum) -> Result<PrefixSet<Any>, Self::IError> {
+        let output = self.inner.resolve(autnum)?;
+        self.record_routes(Vec::new(), *autnum, &output);
+        Ok(output)
+    }
+}
+
+impl<'a, E> Resolver<'a, PeerAs, PrefixSet<Any>> for Explainer<'_, E>
+where
+    E: Lookup + Resolver<'a, PeerAs, PrefixSet<Any>, IError = Error>,
+{
+    type IError = Error;
+
+    fn resolve(&mut self, peer_as: &PeerAs) -> Result<PrefixSet<Any>, Self::IError> {
+        let output = self.inner.resolve(peer_as)?;
+        if let Some(autnum) = self.peer_as {
+            self.record_routes(Vec::new(), autnum, &output);
+        }
+        Ok(output)
+    }
+}
@@ -0,0 +1,369 @@
+use std::fmt::Display;
+
+use tokio::{
+    io::{
+        AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt,
+        BufReader, BufWriter, ReadHalf, WriteHalf,
+    },
+    net::{TcpStream, ToSocketAddrs},
+};
+
+use super::{Error, Query, ResponseError};
+
+/// The outcome of an individual query.
+///
+/// An `Ok` value contains the (possibly empty) response data. An `Err` value indicates that the
+/// server returned an error response to the query, which is usually non-fatal.
+pub(crate) type Response = Result<String, Error>;
+
+const CLIENT_ID: &str = concat!(env!("CARGO_PKG_NAME"), "-", env!("CARGO_PKG_VERSION"));
+
+/// A connection to an IRRd server, in multiple-command mode.
+#[derive(Debug)]
+pub(crate) struct Connection<S = TcpStream> {
+    reader: BufReader<ReadHalf<S>>,
+    writer: BufWriter<WriteHalf<S>>,
+    aggregation: bool,
+}
+
+impl Connection {
+    /// Connect to the IRRd server at `addr`.
+    ///
+    /// If `sources` is not empty, subsequent queries are restricted to the listed IRR databases,
+    /// in the order given.
+    #[tracing::instrument(level = "debug")]
+    pub(crate) async fn connect<A>(addr: A, sources: &[String]) -> Result<Self, Error>
+    where
+        A: ToSocketAddrs + Display + std::fmt::Debug + Send + Sync,
+    {
+        tracing::info!("trying to connect to {addr}");
+        let stream = TcpStream::connect(&addr).await?;
+        tracing::debug!("disabling Nagle's algorithm");
+        stream.set_nodelay(true)?;
+        let mut conn = Self::new(stream);
+        tracing::debug!("requesting multiple command mode");
+        conn.writer.write_all(b"!!\n").await?;
+        let mut queries = vec![Query::SetClientId(CLIENT_ID.to_owned())];
+        if !sources.is_empty() {
+            tracing::debug!("selecting IRR sources {}", sources.join(","));
+            queries.push(Query::SetSources(sources.to_vec()));
+        }
+        queries.push(Query::Version);
+        let mut responses = conn.execute(queries).await?;
+        let version = responses.pop().and_then(|(_, response)| response.ok());
+        for (_, response) in responses {
+            _ = response?;
+        }
+        conn.aggregation = version.as_deref().is_some_and(supports_aggregation);
+        tracing::debug!(
+            version = version.as_deref().map(str::trim),
+            aggregation = conn.aggregation,
+            "detected server capabilities"
+        );
+        tracing::info!("connected to {addr}");
+        Ok(conn)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite> Connection<S> {
+    fn new(stream: S) -> Self {
+        let (read, write) = tokio::io::split(stream);
+        Self {
+            reader: BufReader::new(read),
+            writer: BufWriter::new(write),
+            aggregation: false,
+        }
+    }
+
+    /// Returns `true` if the server supports the aggregated route queries
+    /// [`Query::AggregatedIpv4Routes`] and [`Query::AggregatedIpv6Routes`].
+    pub(crate) const fn supports_aggregation(&self) -> bool {
+        self.aggregation
+    }
+
+    /// Execute a sequence of `queries`, using pipelining.
+    ///
+    /// Queries are written to the server concurrently with reading the responses, and the
+    /// responses are returned in the same order as `queries`.
+    ///
+    /// An outer `Err` is returned if the connection fails, in which case it should not be
+    /// re-used.
+    #[tracing::instrument(skip_all, fields(queries = queries.len()), level = "debug")]
+    pub(crate) async fn execute(
+        &mut self,
+        queries: Vec<Query>,
+    ) -> Result<Vec<(Query, Response)>, Error> {
+        let Self { reader, writer, .. } = self;
+        let send = async {
+            for query in &queries {
+                tracing::trace!(?query, "sending query");
+                writer.write_all(query.cmd().as_bytes()).await?;
+            }
+            writer.flush().await.map_err(Error::from)
+        };
+        let recv = async {
+            let mut responses = Vec::with_capacity(queries.len());
+            for query in &queries {
+                responses.push(read_response(reader, query).await?);
+            }
+            Ok(responses)
+        };
+        let ((), responses) = tokio::try_join!(send, recv)?;
+        Ok(queries.into_iter().zip(responses).collect())
+    }
+}
+
+/// Execute a single unframed `query` on a new connection to the server at `addr`.
+///
+/// This is used for RIPE-style queries, such as [`Query::AsSetsContaining`], which cannot be
+/// pipelined in multiple-command mode.
+#[tracing::instrument(level = "debug")]
+pub(crate) async fn execute_once<A>(addr: A, query: Query) -> Result<(Query, Response), Error>
+where
+    A: ToSocketAddrs + Display + std::fmt::Debug + Send + Sync,
+{
+    tracing::debug!("connecting to {addr} for a single query");
+    let stream = TcpStream::connect(&addr).await?;
+    let response = execute_unframed(stream, &query).await?;
+    Ok((query, response))
+}
+
+/// Send `query` on `stream`, and read the response until the server closes the connection.
+async fn execute_unframed<S>(mut stream: S, query: &Query) -> Result<Response, Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    tracing::trace!(?query, "sending unframed query");
+    stream.write_all(query.cmd().as_bytes()).await?;
+    stream.flush().await?;
+    let mut data = Vec::new();
+    _ = stream.read_to_end(&mut data).await?;
+    let data = String::from_utf8_lossy(&data).into_owned();
+    // RIPE-style servers report errors in comment lines, either as `%ERROR:101: no entries
+    // found` (RIPE) or `%% ERROR: ...` (IRRd)
+    let err = data.lines().find_map(|line| {
+        let line = line.strip_prefix('%')?.trim_start_matches('%').trim_start();
+        line.strip_prefix("ERROR")
+            .map(|msg| msg.trim_start_matches(':').trim())
+    });
+    Ok(match err {
+        Some(msg) if msg.starts_with("101") => Err(Error::ResponseErr(
+            query.clone(),
+            ResponseError::KeyNotFound,
+        )),
+        Some(msg) => Err(Error::ResponseErr(
+            query.clone(),
+            ResponseError::Other(msg.to_owned()),
+        )),
+        None => Ok(data),
+    })
+}
+
+/// Determine whether a server reporting `version` supports aggregated route queries, which were
+/// introduced in IRRd version 4.
+fn supports_aggregation(version: &str) -> bool {
+    version
+        .split_once("version")
+        .and_then(|(_, rest)| rest.trim().split('.').next()?.parse::<u32>().ok())
+        .is_some_and(|major| major >= 4)
+}
+
+#[tracing::instrument(skip(reader), level = "trace")]
+async fn read_response<R>(reader: &mut R, query: &Query) -> Result<Response, Error>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut status = String::new();
+    if reader.read_line(&mut status).await? == 0 {
+        return Err(Error::Io(std::io::ErrorKind::UnexpectedEof.into()));
+    }
+    let malformed = |msg: &str| Error::Malformed(query.clone(), msg.to_owned());
+    let status = status.trim_end();
+    match status.split_at(status.chars().next().map_or(0, char::len_utf8)) {
+        ("A", len) => {
+            let len = len
+                .parse()
+                .map_err(|_| malformed("invalid response length"))?;
+            tracing::trace!("expecting response length {len} bytes");
+            let mut data = vec![0; len];
+            _ = reader.read_exact(&mut data).await?;
+            let mut end = String::new();
+            _ = reader.read_line(&mut end).await?;
+            if end != "C\n" {
+                return Err(malformed("missing end of response marker"));
+            }
+            String::from_utf8(data)
+                .map(Ok)
+                .map_err(|_| malformed("invalid UTF-8 in response data"))
+        }
+        ("C", "") => Ok(Ok(String::new())),
+        ("D", "") => Ok(Err(Error::ResponseErr(
+            query.clone(),
+            ResponseError::KeyNotFound,
+        ))),
+        ("E", "") => Ok(Err(Error::ResponseErr(
+            query.clone(),
+            ResponseError::KeyNotUnique,
+        ))),
+        ("F", msg) => Ok(Err(Error::ResponseErr(
+            query.clone(),
+            ResponseError::Other(msg.trim().to_owned()),
+        ))),
+        _ => Err(malformed("unrecognised response status")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::duplex;
+
+    use rpsl::names::AsSet;
+
+    use super::*;
+    use crate::irrd::RpslObjectClass;
+
+    async fn exchange(queries: Vec<Query>, responses: &str) -> (Vec<(Query, Response)>, String) {
+        let (client, mut server) = duplex(1 << 16);
+        server.write_all(responses.as_bytes()).await.unwrap();
+        let mut conn = Connection::new(client);
+        let results = conn.execute(queries).await.unwrap();
+        drop(conn);
+        let mut sent = String::new();
+        _ = server.read_to_string(&mut sent).await.unwrap();
+        (results, sent)
+    }
+
+    #[tokio::test]
+    async fn pipelined_responses() {
+        let autnum = "AS65000".parse().unwrap();
+        let (results, sent) = exchange(
+            vec![
+                Query::Ipv4Routes(autnum),
+                Query::Ipv6Routes(autnum),
+                Query::SetSources(vec!["RIPE".to_owned(), "RADB".to_owned()]),
+                Query::AsSetMembersRecursive("AS-FOO".parse().unwrap()),
+                Query::RpslObject(RpslObjectClass::FilterSet, "FLTR-FOO".to_owned()),
+            ],
+            "A29\n192.0.2.0/24 198.51.100.0/24\nC\nD\nC\nF unknown command\nE\n",
+        )
+        .await;
+        assert_eq!(
+            sent,
+            "!gAS65000\n!6AS65000\n!sRIPE,RADB\n!iAS-FOO,1\n!mfilter-set,FLTR-FOO\n"
+        );
+        let results: Vec<_> = results.into_iter().map(|(_, resp)| resp).collect();
+        assert_eq!(
+            results[0].as_ref().unwrap(),
+            "192.0.2.0/24 198.51.100.0/24\n"
+        );
+        assert!(matches!(
+            results[1],
+            Err(Error::ResponseErr(
+                Query::Ipv6Routes(_),
+                ResponseError::KeyNotFound
+            ))
+        ));
+        assert_eq!(results[2].as_ref().unwrap(), "");
+        assert!(matches!(
+            &results[3],
+            Err(Error::ResponseErr(_, ResponseError::Other(msg))) if msg == "unknown command"
+        ));
+        assert!(matches!(
+            results[4],
+            Err(Error::ResponseErr(_, ResponseError::KeyNotUnique))
+        ));
+    }
+
+    #[tokio::test]
+    async fn aggregated_routes() {
+        let as_set: AsSet = "AS-FOO".parse().unwrap();
+        let (results, sent) = exchange(
+            vec![
+                Query::Version,
+                Query::AggregatedIpv4Routes(as_set.clone()),
+                Query::AggregatedIpv6Routes(as_set),
+            ],
+            "A24\n# IRRd -- version 4.4.2\nC\nA13\n192.0.2.0/23\nC\nC\n",
+        )
+        .await;
+        assert_eq!(sent, "!v\n!a4AS-FOO\n!a6AS-FOO\n");
+        assert!(supports_aggregation(results[0].1.as_ref().unwrap()));
+        assert_eq!(results[1].1.as_ref().unwrap(), "192.0.2.0/23\n");
+        assert!(!supports_aggregation("# IRRd -- version 3.0.8"));
+        assert!(!supports_aggregation("unknown"));
+    }
+
+    #[tokio::test]
+    async fn unframed_responses() {
+        let query = Query::AsSetsContaining("AS65000".to_owned(), vec!["RADB".to_owned()]);
+        let exchange = |response: &'static str| {
+            let query = query.clone();
+            async move {
+                let (client, mut server) = duplex(1 << 10);
+                server.write_all(response.as_bytes()).await.unwrap();
+                server.shutdown().await.unwrap();
+                let response = execute_unframed(client, &query).await.unwrap();
+                drop(server);
+                response
+            }
+        };
+        assert_eq!(query.cmd(), "-r -s RADB -T as-set -i members AS65000\n");
+        let data = exchange("as-set: AS-FOO\nsource: RADB\n\n").await.unwrap();
+        assert_eq!(data, "as-set: AS-FOO\nsource: RADB\n\n");
+        assert!(matches!(
+            exchange("%ERROR:101: no entries found\n\n").await,
+            Err(Error::ResponseErr(_, ResponseError::KeyNotFound))
+        ));
+        assert!(matches!(
+            exchange("%% ERROR: Invalid source\n").await,
+            Err(Error::ResponseErr(_, ResponseError::Other(msg))) if msg == "Invalid source"
+        ));
+    }
+
+    #[tokio::test]
+    async fn connect_handshake() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream
+                .write_all(b"C\nC\nA24\n# IRRd -- version 4.4.2\nC\n")
+                .await
+                .unwrap();
+            let mut sent = [0; 64];
+            let mut len = 0;
+            while !sent[..len].ends_with(b"!v\n") {
+                len += stream.read(&mut sent[len..]).await.unwrap();
+            }
+            String::from_utf8(sent[..len].to_vec()).unwrap()
+        });
+        let conn = Connection::connect(addr, &["RIPE".to_owned(), "RADB".to_owned()])
+            .await
+            .unwrap();
+        assert!(conn.supports_aggregation());
+        assert_eq!(
+            server.await.unwrap(),
+            format!("!!\n!n{CLIENT_ID}\n!sRIPE,RADB\n!v\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_end_of_response() {
+        let (client, mut server) = duplex(1 << 10);
+        server.write_all(b"A4\nfoo\nX\n").await.unwrap();
+        let result = Connection::new(client)
+            .execute(vec![Query::Ipv4Routes("AS65000".parse().unwrap())])
+            .await;
+        assert!(matches!(result, Err(Error::Malformed(..))));
+    }
+
+    #[tokio::test]
+    async fn premature_eof() {
+        let (client, server) = duplex(1 << 10);
+        drop(server);
+        let result = Connection::new(client)
+            .execute(vec![Query::Ipv4Routes("AS65000".parse().unwrap())])
+            .await;
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+}
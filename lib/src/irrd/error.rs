@@ -0,0 +1,42 @@
+use std::io;
+
+use super::Query;
+
+/// Error responses returned by an IRRd server.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ResponseError {
+    /// The query was valid, but the primary key queried for did not exist.
+    #[error("the query was valid, but the primary key queried for did not exist")]
+    KeyNotFound,
+    /// The query was valid, but there are multiple copies of the key in one database.
+    #[error("the query was valid, but there are multiple copies of the key in one database")]
+    KeyNotUnique,
+    /// The query was invalid.
+    #[error("the query was invalid: {0}")]
+    Other(String),
+}
+
+/// IRRd query protocol errors.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The server returned an error response to a query.
+    #[error("error response for query {0:?}: {1}")]
+    ResponseErr(Query, #[source] ResponseError),
+    /// An I/O error occurred on the connection to the server.
+    #[error("an I/O error occurred")]
+    Io(#[from] io::Error),
+    /// The server sent a response that could not be decoded.
+    #[error("malformed response for query {0:?}: {1}")]
+    Malformed(Query, String),
+    /// An item contained in a response could not be parsed.
+    #[error("failed to parse item '{item}' in response for query {query:?}: {source}")]
+    ParseItem {
+        /// The query to which the response was received.
+        query: Query,
+        /// The raw item that failed to parse.
+        item: String,
+        /// The underlying parser error.
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+}
@@ -0,0 +1,8 @@
+mod connection;
+pub(crate) use self::connection::{execute_once, Connection, Response};
+
+mod error;
+pub use self::error::{Error, ResponseError};
+
+mod query;
+pub use self::query::{Query, RouteMatch, RpslObjectClass};
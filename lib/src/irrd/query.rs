@@ -0,0 +1,139 @@
+use std::fmt;
+
+use ip::{Any, Prefix};
+
+use rpsl::names::{AsSet, AutNum, RouteSet};
+
+/// IRRd query variants.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Query {
+    /// Identifies the client to the server.
+    SetClientId(String),
+    /// Restricts subsequent queries to the listed IRR databases, searched in the order given.
+    SetSources(Vec<String>),
+    /// Returns the direct members of an `as-set`, without expanding `as-set` members.
+    AsSetMembers(AsSet),
+    /// Returns all members of an `as-set`, recursively expanding `as-set` members as necessary.
+    AsSetMembersRecursive(AsSet),
+    /// Returns all members of a `route-set`, recursively expanding members as necessary.
+    RouteSetMembersRecursive(RouteSet),
+    /// Returns all IPv4 prefixes corresponding to a `route` object having `origin:` set to the
+    /// provided AS.
+    Ipv4Routes(AutNum),
+    /// Returns all IPv6 prefixes corresponding to a `route6` object having `origin:` set to the
+    /// provided AS.
+    Ipv6Routes(AutNum),
+    /// Returns an RPSL object exactly matching the provided key, of the specified RPSL object
+    /// class.
+    RpslObject(RpslObjectClass, String),
+    /// Returns the aggregated IPv4 prefixes of the `route` objects originated by all members of
+    /// an `as-set`, recursively expanding `as-set` members as necessary.
+    AggregatedIpv4Routes(AsSet),
+    /// Returns the aggregated IPv6 prefixes of the `route6` objects originated by all members of
+    /// an `as-set`, recursively expanding `as-set` members as necessary.
+    AggregatedIpv6Routes(AsSet),
+    /// Returns the version of the server software.
+    Version,
+    /// Returns the current serial range of each of the listed IRR databases, or of every
+    /// database known to the server if the list is empty.
+    DatabaseSerials(Vec<String>),
+    /// Returns the `as-set` objects that list the provided `aut-num` or `as-set` in their
+    /// `members` attribute, searching the listed IRR databases, or the server defaults if the
+    /// list is empty.
+    ///
+    /// This is a RIPE-style inverse query, the response to which is not framed. It must
+    /// therefore be sent on its own connection, which the server closes once the response is
+    /// complete.
+    AsSetsContaining(String, Vec<String>),
+    /// Returns the `route` or `route6` objects for the provided prefix, or for those prefixes
+    /// related to it as specified.
+    Routes(Prefix<Any>, RouteMatch),
+}
+
+impl Query {
+    /// Get the RPSL name that the query is for, if any.
+    pub(crate) fn name(&self) -> Option<String> {
+        match self {
+            Self::SetClientId(_)
+            | Self::SetSources(_)
+            | Self::Version
+            | Self::DatabaseSerials(_) => None,
+            Self::AsSetMembers(q)
+            | Self::AsSetMembersRecursive(q)
+            | Self::AggregatedIpv4Routes(q)
+            | Self::AggregatedIpv6Routes(q) => Some(q.to_string()),
+            Self::RouteSetMembersRecursive(q) => Some(q.to_string()),
+            Self::Ipv4Routes(q) | Self::Ipv6Routes(q) => Some(q.to_string()),
+            Self::Routes(q, _) => Some(q.to_string()),
+            Self::RpslObject(_, q) | Self::AsSetsContaining(q, _) => Some(q.clone()),
+        }
+    }
+
+    pub(crate) fn cmd(&self) -> String {
+        match self {
+            Self::SetClientId(id) => format!("!n{id}\n"),
+            Self::SetSources(sources) => format!("!s{}\n", sources.join(",")),
+            Self::AsSetMembers(q) => format!("!i{q}\n"),
+            Self::AsSetMembersRecursive(q) => format!("!i{q},1\n"),
+            Self::RouteSetMembersRecursive(q) => format!("!i{q},1\n"),
+            Self::Ipv4Routes(q) => format!("!g{q}\n"),
+            Self::Ipv6Routes(q) => format!("!6{q}\n"),
+            Self::RpslObject(class, q) => format!("!m{class},{q}\n"),
+            Self::AggregatedIpv4Routes(q) => format!("!a4{q}\n"),
+            Self::AggregatedIpv6Routes(q) => format!("!a6{q}\n"),
+            Self::Version => "!v\n".to_owned(),
+            Self::DatabaseSerials(sources) if sources.is_empty() => "!j-*\n".to_owned(),
+            Self::DatabaseSerials(sources) => format!("!j{}\n", sources.join(",")),
+            Self::Routes(q, RouteMatch::Exact) => format!("!r{q}\n"),
+            Self::Routes(q, RouteMatch::LessSpecific) => format!("!r{q},L\n"),
+            Self::Routes(q, RouteMatch::MoreSpecific) => format!("!r{q},M\n"),
+            Self::AsSetsContaining(q, sources) if sources.is_empty() => {
+                format!("-r -T as-set -i members {q}\n")
+            }
+            Self::AsSetsContaining(q, sources) => {
+                format!("-r -s {} -T as-set -i members {q}\n", sources.join(","))
+            }
+        }
+    }
+}
+
+/// The prefixes matched by a [`Query::Routes`] query, relative to the prefix queried for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RouteMatch {
+    /// Only the prefix itself.
+    Exact,
+    /// The prefix itself, and every less-specific prefix covering it.
+    LessSpecific,
+    /// Every more-specific prefix covered by the prefix, excluding the prefix itself.
+    MoreSpecific,
+}
+
+/// RPSL object classes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RpslObjectClass {
+    /// `aut-num` object class.
+    AutNum,
+    /// `route` object class.
+    Route,
+    /// `route6` object class.
+    Route6,
+    /// `as-set` object class.
+    AsSet,
+    /// `route-set` object class.
+    RouteSet,
+    /// `filter-set` object class.
+    FilterSet,
+}
+
+impl fmt::Display for RpslObjectClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AutNum => write!(f, "aut-num"),
+            Self::Route => write!(f, "route"),
+            Self::Route6 => write!(f, "route6"),
+            Self::AsSet => write!(f, "as-set"),
+            Self::RouteSet => write!(f, "route-set"),
+            Self::FilterSet => write!(f, "filter-set"),
+        }
+    }
+}
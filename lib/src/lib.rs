@@ -47,10 +47,107 @@
 mod error;
 pub use self::error::Error;
 
+/// IRRd query protocol client.
+mod irrd;
+pub use self::irrd::{
+    Error as IrrdError, Query as IrrdQuery, ResponseError as IrrdResponseError, RouteMatch,
+    RpslObjectClass,
+};
+
+/// Name resolution over IRRd connections.
+mod client;
+
+/// Tables of resolved RPSL names.
+mod table;
+
+/// Batch evaluation of many expressions.
+mod batch;
+pub use self::batch::BatchResults;
+
 /// Query pipelining and response handling.
 mod query;
 pub use self::query::RpslEvaluator;
 
+/// In-memory caching of resolved RPSL names.
+mod cache;
+pub use self::cache::Cache;
+
+/// Persistent caching of resolved RPSL names.
+mod disk_cache;
+pub use self::disk_cache::DiskCache;
+
+/// Evaluator configuration.
+mod builder;
+pub use self::builder::EvaluatorBuilder;
+
+/// Asynchronous expression evaluation.
+mod async_query;
+pub use self::async_query::AsyncRpslEvaluator;
+
+/// RPKI route origin validation.
+mod rov;
+pub use self::rov::{Rov, RovAction, Validity};
+
+/// Offline expression evaluation using RPSL database dumps.
+mod offline;
+pub use self::offline::OfflineRpslEvaluator;
+
+/// Local mirrors of IRR databases.
+mod mirror;
+pub use self::mirror::Mirror;
+
+/// Compilation of `aut-num` routing policies.
+mod policy;
+pub use self::policy::Direction;
+
+/// AS path constraints derived from evaluations.
+mod aspath;
+pub use self::aspath::AsPathFilter;
+
+/// Differences between evaluated prefix sets.
+mod diff;
+pub use self::diff::Difference;
+
+/// Serialisable snapshots of evaluation results.
+mod snapshot;
+pub use self::snapshot::Snapshot;
+
+/// Recursive expansion of `as-set`s.
+mod expand;
+pub use self::expand::{AsSetExpansion, MemberTree};
+
+/// Inverse `as-set` membership lookups.
+mod member_of;
+pub use self::member_of::MemberOf;
+
+/// Origin lookups for prefixes.
+mod origins;
+pub use self::origins::{RouteObject, Specificity};
+
+/// Hygiene checks for `as-set`s.
+mod lint;
+pub use self::lint::{LintReport, Linter, Problem};
+
+/// Limits on evaluation resources.
+mod limits;
+pub use self::limits::Limits;
+
+/// Serial numbers of IRR databases.
+mod serial;
+pub use self::serial::Serials;
+
+/// Reports of non-fatal evaluation errors.
+mod report;
+pub use self::report::Report;
+
+/// Provenance of evaluation output.
+mod explain;
+pub use self::explain::{Derivation, Explanation, Step};
+
+/// Sanitisation of evaluated prefix sets.
+mod sanitise;
+pub use self::sanitise::{SanitiseReport, Sanitiser};
+
 // silence unused dev-dependency warnings
 #[cfg(test)]
 mod deps {
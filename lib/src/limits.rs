@@ -0,0 +1,322 @@
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    future::Future,
+    mem,
+    time::{Duration, Instant},
+};
+
+use ip::{any, concrete::Afi, traits::PrefixSet as _, Any, PrefixSet};
+
+use rpsl::names::{AsSet, AutNum};
+
+use crate::{error::Error, expand::Expansion};
+
+/// Limits on the resources consumed by a single evaluation.
+///
+/// Limits contain the blast radius of a misconfigured object, such as a customer `as-set` that
+/// recursively includes a transit provider's customer cone. By default, no limits are applied.
+///
+/// Exceeding any limit is fatal, regardless of whether the evaluator is
+/// [strict][crate::EvaluatorBuilder::strict], and produces a distinct [`Error`] variant.
+///
+/// Enforcing [`Limits::max_depth`] or [`Limits::max_autnums`] requires each `as-set` to be walked
+/// one level at a time, which costs an additional round of queries per level when resolving
+/// names using the IRRd query protocol.
+///
+/// # Examples
+///
+/// ``` no_run
+/// use std::time::Duration;
+///
+/// use bgpfu::{Limits, RpslEvaluator};
+///
+/// let evaluator = RpslEvaluator::builder("whois.radb.net", 43)
+///     .limits(
+///         Limits::default()
+///             .max_depth(5)
+///             .max_autnums(1000)
+///             .max_prefixes(10_000)
+///             .timeout(Duration::from_secs(60)),
+///     )
+///     .build()?;
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    max_depth: Option<usize>,
+    max_autnums: Option<usize>,
+    max_prefixes: Option<usize>,
+    timeout: Option<Duration>,
+    batch_timeout: Option<Duration>,
+}
+
+impl Limits {
+    /// Set the maximum number of levels of `as-set` nesting below each `as-set` that is
+    /// evaluated.
+    ///
+    /// An `as-set` whose members are all `aut-num`s has a depth of `0`.
+    ///
+    /// Exceeding this limit produces an [`Error::DepthLimit`].
+    #[must_use]
+    pub const fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Set the maximum number of distinct autonomous systems, whether referenced directly or as
+    /// `as-set` members, that may be resolved during an evaluation.
+    ///
+    /// Exceeding this limit produces an [`Error::AutNumLimit`].
+    #[must_use]
+    pub const fn max_autnums(mut self, autnums: usize) -> Self {
+        self.max_autnums = Some(autnums);
+        self
+    }
+
+    /// Set the maximum number of distinct prefix ranges of each address family that may be
+    /// resolved from IRR objects during an evaluation.
+    ///
+    /// Prefix ranges appearing literally in the evaluated expression are not counted.
+    ///
+    /// Exceeding this limit produces an [`Error::PrefixLimit`].
+    #[must_use]
+    pub const fn max_prefixes(mut self, prefixes: usize) -> Self {
+        self.max_prefixes = Some(prefixes);
+        self
+    }
+
+    /// Set the maximum wall-clock time that an evaluation may take.
+    ///
+    /// This limit does not apply to the expressions in a batch evaluation, because the names
+    /// required by every expression are fetched together. Use [`Limits::batch_timeout`] instead.
+    ///
+    /// Exceeding this limit produces an [`Error::Timeout`].
+    #[must_use]
+    pub const fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the maximum wall-clock time that a batch evaluation may take, as a whole.
+    ///
+    /// See [`AsyncRpslEvaluator::evaluate_batch`][crate::AsyncRpslEvaluator::evaluate_batch].
+    ///
+    /// Each expression that is still incomplete when this limit is exceeded fails with an
+    /// [`Error::Timeout`]. Expressions that were already complete are unaffected.
+    #[must_use]
+    pub const fn batch_timeout(mut self, timeout: Duration) -> Self {
+        self.batch_timeout = Some(timeout);
+        self
+    }
+
+    /// Get the limits that apply to each expression in a batch evaluation.
+    pub(crate) const fn batch_expression(self) -> Self {
+        Self {
+            timeout: None,
+            batch_timeout: None,
+            ..self
+        }
+    }
+}
+
+/// The resources consumed so far by an evaluation, checked against a set of [`Limits`].
+#[derive(Debug, Default)]
+pub(crate) struct Budget {
+    limits: Limits,
+    deadline: Option<Instant>,
+    expanded: HashSet<AsSet>,
+    autnums: HashSet<AutNum>,
+    counted: HashSet<String>,
+    prefixes: PrefixSet<Any>,
+}
+
+impl Budget {
+    /// Start a new evaluation, subject to `limits`.
+    pub(crate) fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+            ..Self::default()
+        }
+    }
+
+    /// Start a batch evaluation, subject to the [`Limits::batch_timeout`] of `limits`.
+    ///
+    /// The other limits apply to each expression in the batch separately, and are not enforced
+    /// by the returned [`Budget`].
+    pub(crate) fn batch(limits: Limits) -> Self {
+        Self::new(Limits {
+            timeout: limits.batch_timeout,
+            ..Limits::default()
+        })
+    }
+
+    /// Get the time remaining before the evaluation must be complete, if limited.
+    pub(crate) fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Get the [`Error`] produced when the evaluation runs out of time.
+    pub(crate) fn timeout_err(&self) -> Error {
+        Error::Timeout(self.limits.timeout.unwrap_or_default())
+    }
+
+    /// Drive `future` to completion, failing if the evaluation runs out of time.
+    pub(crate) async fn run<F, T>(&self, future: F) -> Result<T, Error>
+    where
+        F: Future<Output = Result<T, Error>>,
+    {
+        match self.remaining() {
+            Some(remaining) => tokio::time::timeout(remaining, future)
+                .await
+                .unwrap_or_else(|_| Err(self.timeout_err())),
+            None => future.await,
+        }
+    }
+
+    /// Drive `future` to completion, returning `None` if the evaluation runs out of time first.
+    pub(crate) async fn within<F: Future>(&self, future: F) -> Option<F::Output> {
+        match self.remaining() {
+            Some(remaining) => tokio::time::timeout(remaining, future).await.ok(),
+            None => Some(future.await),
+        }
+    }
+
+    /// Check that the evaluation has not run out of time.
+    pub(crate) fn check_deadline(&self) -> Result<(), Error> {
+        match self.remaining() {
+            Some(remaining) if remaining.is_zero() => Err(self.timeout_err()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Begin walking the members of `as_set`, if that is required to enforce the limits and it
+    /// has not already been walked during this evaluation.
+    pub(crate) fn expansion(&mut self, as_set: &AsSet) -> Option<Expansion> {
+        let walk = self.limits.max_depth.is_some() || self.limits.max_autnums.is_some();
+        (walk && self.expanded.insert(as_set.clone()))
+            .then(|| Expansion::new(as_set.clone(), self.limits.max_depth))
+    }
+
+    /// Begin walking the members of `as_set`, regardless of whether that is required to enforce
+    /// the limits.
+    pub(crate) fn walk(&self, as_set: &AsSet) -> Expansion {
+        Expansion::new(as_set.clone(), self.limits.max_depth)
+    }
+
+    /// Record `autnums` as having been resolved.
+    pub(crate) fn add_autnums<I>(&mut self, autnums: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = AutNum>,
+    {
+        self.autnums.extend(autnums);
+        match self.limits.max_autnums {
+            Some(max) if self.autnums.len() > max => Err(Error::AutNumLimit(max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Record the prefixes in `set` as having been resolved from the RPSL object `name`.
+    pub(crate) fn add_prefixes<N: Display>(
+        &mut self,
+        name: &N,
+        set: &PrefixSet<Any>,
+    ) -> Result<(), Error> {
+        let Some(max) = self.limits.max_prefixes else {
+            return Ok(());
+        };
+        if !self.counted.insert(name.to_string()) {
+            return Ok(());
+        }
+        self.prefixes = mem::take(&mut self.prefixes) | set.clone();
+        let (ipv4, ipv6) = self
+            .prefixes
+            .ranges()
+            .fold((0, 0), |(ipv4, ipv6), range| match range {
+                any::PrefixRange::Ipv4(_) => (ipv4 + 1, ipv6),
+                any::PrefixRange::Ipv6(_) => (ipv4, ipv6 + 1),
+            });
+        if ipv4 > max {
+            Err(Error::PrefixLimit(Afi::Ipv4, max))
+        } else if ipv6 > max {
+            Err(Error::PrefixLimit(Afi::Ipv6, max))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rpsl::expr::AsSetMember;
+
+    use super::*;
+
+    fn members(members: &[&str]) -> Vec<AsSetMember> {
+        members
+            .iter()
+            .map(|member| member.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn depth_limit() {
+        let mut budget = Budget::new(Limits::default().max_depth(1));
+        let mut expansion = budget.expansion(&"AS-FOO".parse().unwrap()).unwrap();
+        expansion
+            .advance([Some(members(&["AS65000", "AS-BAR"]))])
+            .unwrap();
+        assert_eq!(expansion.pending(), ["AS-BAR".parse().unwrap()]);
+        // AS-FOO has already been visited, but AS-BAZ is nested too deeply
+        assert!(matches!(
+            expansion.advance([Some(members(&["AS-FOO", "AS-BAZ"]))]),
+            Err(Error::DepthLimit(_, 1))
+        ));
+        assert!(budget.expansion(&"AS-FOO".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn autnum_and_prefix_limits() {
+        let mut budget = Budget::new(Limits::default().max_autnums(1).max_prefixes(1));
+        assert!(budget.expansion(&"AS-FOO".parse().unwrap()).is_some());
+        let autnum: AutNum = "AS65000".parse().unwrap();
+        budget.add_autnums([autnum, autnum]).unwrap();
+        assert!(matches!(
+            budget.add_autnums(["AS65001".parse().unwrap()]),
+            Err(Error::AutNumLimit(1))
+        ));
+        let set = |prefixes: &[&str]| -> PrefixSet<Any> {
+            prefixes
+                .iter()
+                .map(|prefix| prefix.parse::<ip::Prefix<Any>>().unwrap())
+                .collect()
+        };
+        budget
+            .add_prefixes(&autnum, &set(&["192.0.2.0/24", "2001:db8::/32"]))
+            .unwrap();
+        // each name is only counted once
+        budget
+            .add_prefixes(&autnum, &set(&["198.51.100.0/24"]))
+            .unwrap();
+        assert!(matches!(
+            budget.add_prefixes(&"AS65001", &set(&["198.51.100.0/24"])),
+            Err(Error::PrefixLimit(Afi::Ipv4, 1))
+        ));
+    }
+
+    #[test]
+    fn batch_timeout() {
+        let limits = Limits::default()
+            .max_depth(1)
+            .timeout(Duration::from_secs(1))
+            .batch_timeout(Duration::from_secs(60));
+        let budget = Budget::batch(limits);
+        assert!(budget.remaining().unwrap() > Duration::from_secs(1));
+        assert!(matches!(budget.timeout_err(), Error::Timeout(timeout) if timeout.as_secs() == 60));
+        let expression = limits.batch_expression();
+        assert_eq!(expression, Limits::default().max_depth(1));
+        assert!(Budget::new(expression).remaining().is_none());
+    }
+}
@@ -0,0 +1,435 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use rpsl::{
+    expr::AsSetMember,
+    names::{AsSet, AutNum},
+};
+
+use crate::{
+    client::{list_values, object_source, Client},
+    error::Error,
+    irrd::RpslObjectClass,
+    offline::attributes,
+};
+
+/// The IRR databases operated by the Regional Internet Registries, which are authoritative for
+/// the `aut-num` objects of the autonomous systems that they assign.
+const RIR_SOURCES: &[&str] = &["AFRINIC", "APNIC", "ARIN", "LACNIC", "RIPE"];
+
+/// Hygiene checks for `as-set`s.
+///
+/// A [`Linter`] walks an `as-set` and every `as-set` nested within it, and reports the
+/// [`Problem`]s found. These are problems that commonly cause the filters generated from an
+/// `as-set` to be incomplete, or unexpectedly large.
+///
+/// By default, `aut-num` objects are expected to be found in the databases of the Regional
+/// Internet Registries, and an `as-set` is considered unusually large if it has more than `500`
+/// direct members, or contains more than `5000` autonomous systems.
+///
+/// # Examples
+///
+/// ``` no_run
+/// use bgpfu::{Linter, RpslEvaluator};
+///
+/// let evaluator = RpslEvaluator::new("whois.radb.net", 43)?;
+/// let report = evaluator.lint(&"AS-FOO".parse()?, &Linter::default().max_members(100))?;
+/// for problem in report.problems() {
+///     println!("{problem}");
+/// }
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Linter {
+    authoritative_sources: Vec<String>,
+    max_members: usize,
+    max_autnums: usize,
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Self {
+            authoritative_sources: RIR_SOURCES.iter().map(ToString::to_string).collect(),
+            max_members: 500,
+            max_autnums: 5000,
+        }
+    }
+}
+
+impl Linter {
+    /// Set the IRR databases that are authoritative for `aut-num` objects.
+    ///
+    /// An `aut-num` member whose object is found only in other databases produces a
+    /// [`Problem::NonAuthoritative`]. If none of the authoritative databases are known to the
+    /// server, this check is skipped.
+    #[must_use]
+    pub fn authoritative_sources<I, S>(mut self, sources: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.authoritative_sources = sources
+            .into_iter()
+            .map(|source| source.as_ref().to_ascii_uppercase())
+            .collect();
+        self
+    }
+
+    /// Set the number of direct members above which an `as-set` produces a
+    /// [`Problem::TooManyMembers`].
+    #[must_use]
+    pub const fn max_members(mut self, max: usize) -> Self {
+        self.max_members = max;
+        self
+    }
+
+    /// Set the number of autonomous systems above which the linted `as-set` produces a
+    /// [`Problem::TooManyAutNums`].
+    #[must_use]
+    pub const fn max_autnums(mut self, max: usize) -> Self {
+        self.max_autnums = max;
+        self
+    }
+
+    /// Walk `as_set` using `client`, and report the problems found.
+    pub(crate) async fn lint(&self, client: &Client, as_set: &AsSet) -> Result<LintReport, Error> {
+        let walk = Walk::new(client, as_set).await?;
+        let mut problems = Vec::new();
+        problems.extend(walk.loops().into_iter().map(Problem::Loop));
+
+        // every reference to a missing object is reported, so that each can be removed
+        let mut autnums = Vec::new();
+        let mut seen = HashSet::new();
+        for (parent, members) in walk.found() {
+            for member in members {
+                match member {
+                    AsSetMember::AsSet(nested) if walk.is_missing(nested) => {
+                        problems.push(Problem::MissingAsSet(nested.clone(), parent.clone()));
+                    }
+                    AsSetMember::AsSet(_) => {}
+                    AsSetMember::AutNum(autnum) => {
+                        if seen.insert(*autnum) {
+                            autnums.push((*autnum, parent.clone()));
+                        }
+                    }
+                }
+            }
+            if members.len() > self.max_members {
+                problems.push(Problem::TooManyMembers(parent.clone(), members.len()));
+            }
+        }
+        tracing::debug!("checking {} aut-num members", autnums.len());
+
+        let keys: Vec<_> = autnums
+            .iter()
+            .map(|(autnum, _)| autnum.to_string())
+            .collect();
+        let objects = client.objects(RpslObjectClass::AutNum, &keys).await?;
+        let mut existing = Vec::new();
+        let mut unauthoritative = Vec::new();
+        for ((autnum, parent), object) in autnums.iter().zip(objects) {
+            let Some(object) = object else {
+                problems.push(Problem::MissingAutNum(*autnum, parent.clone()));
+                continue;
+            };
+            existing.push(*autnum);
+            match object_source(&object) {
+                Some(source) if !self.is_authoritative(&source) => {
+                    unauthoritative.push((*autnum, source));
+                }
+                _ => {}
+            }
+        }
+        problems.extend(self.check_authoritative(client, unauthoritative).await?);
+
+        let originates = client.originates_routes(&existing).await?;
+        problems.extend(
+            existing
+                .into_iter()
+                .zip(originates)
+                .filter(|(_, originates)| !originates)
+                .map(|(autnum, _)| Problem::NoRoutes(autnum)),
+        );
+
+        if seen.len() > self.max_autnums {
+            problems.push(Problem::TooManyAutNums(as_set.clone(), seen.len()));
+        }
+        Ok(LintReport {
+            as_set: as_set.clone(),
+            problems,
+        })
+    }
+
+    fn is_authoritative(&self, source: &str) -> bool {
+        self.authoritative_sources
+            .iter()
+            .any(|authoritative| authoritative.eq_ignore_ascii_case(source))
+    }
+
+    /// Check whether each of the `aut-num`s whose object was found in a non-authoritative
+    /// database also has an object in an authoritative database.
+    ///
+    /// The first object found is the one from the most preferred source, so an `aut-num` may
+    /// have an authoritative object that is shadowed by a non-authoritative one.
+    async fn check_authoritative(
+        &self,
+        client: &Client,
+        candidates: Vec<(AutNum, String)>,
+    ) -> Result<Vec<Problem>, Error> {
+        if candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+        let known = client.known_sources().await?;
+        let sources: Vec<_> = self
+            .authoritative_sources
+            .iter()
+            .filter(|source| known.iter().any(|known| known.eq_ignore_ascii_case(source)))
+            .cloned()
+            .collect();
+        if sources.is_empty() {
+            tracing::warn!(
+                "none of the authoritative sources {} are known to the server, skipping check",
+                self.authoritative_sources.join(",")
+            );
+            return Ok(Vec::new());
+        }
+        let keys: Vec<_> = candidates
+            .iter()
+            .map(|(autnum, _)| autnum.to_string())
+            .collect();
+        let objects = client
+            .objects_in(&sources, RpslObjectClass::AutNum, &keys)
+            .await?;
+        Ok(candidates
+            .into_iter()
+            .zip(objects)
+            .filter(|(_, object)| object.is_none())
+            .map(|((autnum, source), _)| Problem::NonAuthoritative(autnum, source))
+            .collect())
+    }
+}
+
+/// The direct members of each `as-set` reached from a root `as-set`, in the order in which they
+/// were reached.
+#[derive(Debug)]
+struct Walk {
+    root: AsSet,
+    order: Vec<AsSet>,
+    members: HashMap<AsSet, Option<Vec<AsSetMember>>>,
+}
+
+impl Walk {
+    /// Walk `root` one level of nesting at a time.
+    async fn new(client: &Client, root: &AsSet) -> Result<Self, Error> {
+        let mut walk = Self {
+            root: root.clone(),
+            order: Vec::new(),
+            members: HashMap::new(),
+        };
+        let mut pending = vec![root.clone()];
+        while !pending.is_empty() {
+            let keys: Vec<_> = pending.iter().map(ToString::to_string).collect();
+            let objects = client.objects(RpslObjectClass::AsSet, &keys).await?;
+            let mut next = Vec::new();
+            for (as_set, object) in pending.into_iter().zip(objects) {
+                let members = object.map(|object| {
+                    list_values(&attributes(&object), "members")
+                        .into_iter()
+                        .filter_map(|member| member.parse::<AsSetMember>().ok())
+                        .collect::<Vec<_>>()
+                });
+                for member in members.iter().flatten() {
+                    if let AsSetMember::AsSet(nested) = member {
+                        if !walk.members.contains_key(nested)
+                            && nested != &as_set
+                            && !next.contains(nested)
+                        {
+                            next.push(nested.clone());
+                        }
+                    }
+                }
+                walk.order.push(as_set.clone());
+                _ = walk.members.insert(as_set, members);
+            }
+            next.retain(|as_set| !walk.members.contains_key(as_set));
+            pending = next;
+        }
+        if walk.is_missing(root) {
+            return Err(Error::AsSetNotFound(root.clone()));
+        }
+        Ok(walk)
+    }
+
+    /// Returns `true` if `as_set` was reached, but could not be found.
+    fn is_missing(&self, as_set: &AsSet) -> bool {
+        matches!(self.members.get(as_set), Some(None))
+    }
+
+    /// Iterate over each `as-set` that was found, along with its direct members.
+    fn found(&self) -> impl Iterator<Item = (&AsSet, &Vec<AsSetMember>)> {
+        self.order.iter().filter_map(|as_set| {
+            self.members
+                .get(as_set)
+                .and_then(Option::as_ref)
+                .map(|members| (as_set, members))
+        })
+    }
+
+    /// Find the loops among the `as-set`s reached, each starting and ending with the same
+    /// `as-set`.
+    fn loops(&self) -> Vec<Vec<AsSet>> {
+        let mut loops = Vec::new();
+        let mut done = HashSet::new();
+        let mut path = Vec::new();
+        self.find_loops(&self.root, &mut path, &mut done, &mut loops);
+        loops
+    }
+
+    /// Search for loops depth first, using `path` to track the `as-set`s currently being
+    /// searched.
+    fn find_loops(
+        &self,
+        as_set: &AsSet,
+        path: &mut Vec<AsSet>,
+        done: &mut HashSet<AsSet>,
+        loops: &mut Vec<Vec<AsSet>>,
+    ) {
+        if let Some(start) = path.iter().position(|visiting| visiting == as_set) {
+            let mut found = path[start..].to_vec();
+            found.push(as_set.clone());
+            loops.push(found);
+            return;
+        }
+        if done.contains(as_set) {
+            return;
+        }
+        path.push(as_set.clone());
+        for member in self.members.get(as_set).into_iter().flatten().flatten() {
+            if let AsSetMember::AsSet(nested) = member {
+                self.find_loops(nested, path, done, loops);
+            }
+        }
+        _ = path.pop();
+        _ = done.insert(as_set.clone());
+    }
+}
+
+/// A problem found by a [`Linter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// An `as-set` contains itself, via the listed chain of `as-set`s, which starts and ends
+    /// with the same `as-set`.
+    Loop(Vec<AsSet>),
+    /// An `as-set` member (the first field) of another `as-set` (the second field) does not
+    /// exist.
+    MissingAsSet(AsSet, AsSet),
+    /// An `aut-num` member of an `as-set` has no `aut-num` object.
+    MissingAutNum(AutNum, AsSet),
+    /// An `aut-num` member has an `aut-num` object only in a non-authoritative database, the
+    /// source of which is given.
+    NonAuthoritative(AutNum, String),
+    /// An `aut-num` member has no `route` or `route6` objects.
+    NoRoutes(AutNum),
+    /// An `as-set` has an unusually large number of direct members.
+    TooManyMembers(AsSet, usize),
+    /// The linted `as-set` contains an unusually large number of autonomous systems.
+    TooManyAutNums(AsSet, usize),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Loop(chain) => {
+                let chain: Vec<_> = chain.iter().map(ToString::to_string).collect();
+                write!(f, "as-set loop: {}", chain.join(" > "))
+            }
+            Self::MissingAsSet(as_set, parent) => {
+                write!(f, "{parent} contains {as_set}, which does not exist")
+            }
+            Self::MissingAutNum(autnum, parent) => {
+                write!(f, "{parent} contains {autnum}, which has no aut-num object")
+            }
+            Self::NonAuthoritative(autnum, source) => write!(
+                f,
+                "{autnum} has an aut-num object only in the non-authoritative source {source}"
+            ),
+            Self::NoRoutes(autnum) => write!(f, "{autnum} has no route or route6 objects"),
+            Self::TooManyMembers(as_set, count) => {
+                write!(
+                    f,
+                    "{as_set} has an unusually large number of members ({count})"
+                )
+            }
+            Self::TooManyAutNums(as_set, count) => write!(
+                f,
+                "{as_set} contains an unusually large number of autonomous systems ({count})"
+            ),
+        }
+    }
+}
+
+/// The problems found by linting an `as-set`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintReport {
+    as_set: AsSet,
+    problems: Vec<Problem>,
+}
+
+impl LintReport {
+    /// Get the `as-set` that was linted.
+    #[must_use]
+    pub const fn as_set(&self) -> &AsSet {
+        &self.as_set
+    }
+
+    /// Get the problems found.
+    #[must_use]
+    pub fn problems(&self) -> &[Problem] {
+        &self.problems
+    }
+
+    /// Returns `true` if no problems were found.
+    #[must_use]
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn members(members: &[&str]) -> Option<Vec<AsSetMember>> {
+        members.iter().map(|member| member.parse().ok()).collect()
+    }
+
+    #[test]
+    fn find_loops() {
+        let walk = Walk {
+            root: "AS-FOO".parse().unwrap(),
+            order: Vec::new(),
+            members: [
+                ("AS-FOO", members(&["AS65001", "AS-BAR", "AS-BAZ"])),
+                ("AS-BAR", members(&["AS-BAZ", "AS-FOO"])),
+                ("AS-BAZ", members(&["AS-BAZ"])),
+            ]
+            .into_iter()
+            .map(|(as_set, members)| (as_set.parse().unwrap(), members))
+            .collect(),
+        };
+        let loops: Vec<_> = walk
+            .loops()
+            .into_iter()
+            .map(|chain| Problem::Loop(chain).to_string())
+            .collect();
+        assert_eq!(
+            loops,
+            [
+                "as-set loop: AS-BAZ > AS-BAZ",
+                "as-set loop: AS-FOO > AS-BAR > AS-FOO"
+            ]
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use std::{collections::HashSet, fmt};
+
+use rpsl::{expr::AsSetMember, names::AsSet};
+
+use crate::{client::Client, error::Error};
+
+/// An `as-set` that contains a given `aut-num` or `as-set`, as found by an inverse membership
+/// lookup.
+///
+/// # Examples
+///
+/// ``` no_run
+/// use bgpfu::RpslEvaluator;
+///
+/// let evaluator = RpslEvaluator::new("whois.radb.net", 43)?;
+/// for member_of in evaluator.member_of(&"AS65000".parse()?, true)? {
+///     println!("{member_of}");
+/// }
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberOf {
+    as_set: AsSet,
+    source: Option<String>,
+    member: AsSetMember,
+    depth: usize,
+    by_reference: bool,
+}
+
+impl MemberOf {
+    /// Get the containing `as-set`.
+    #[must_use]
+    pub const fn as_set(&self) -> &AsSet {
+        &self.as_set
+    }
+
+    /// Get the IRR database in which the `as-set` is defined, if known.
+    #[must_use]
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    /// Get the member of the `as-set` through which it contains the member looked up.
+    ///
+    /// This is the member looked up itself for an `as-set` that contains it directly, and
+    /// otherwise the nested `as-set` one level down.
+    #[must_use]
+    pub const fn member(&self) -> &AsSetMember {
+        &self.member
+    }
+
+    /// Get the level of nesting at which the `as-set` contains the member looked up, starting
+    /// from `1` for an `as-set` that contains it directly.
+    #[must_use]
+    pub const fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns `true` if the `as-set` contains [`MemberOf::member`] by reference, via the
+    /// `member-of` attribute of its `aut-num` object, rather than listing it in its `members`
+    /// attribute.
+    #[must_use]
+    pub const fn by_reference(&self) -> bool {
+        self.by_reference
+    }
+}
+
+impl fmt::Display for MemberOf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_set)?;
+        if let Some(source) = &self.source {
+            write!(f, " ({source})")?;
+        }
+        write!(f, " contains {}", self.member)?;
+        if self.by_reference {
+            write!(f, " by reference")?;
+        }
+        Ok(())
+    }
+}
+
+/// Find the `as-set`s that contain `member`, using `client`.
+///
+/// If `recursive` is `true`, the `as-set`s that contain each of those found are looked up in
+/// turn, one level of nesting at a time, until no more are found. Each `as-set` is reported
+/// once, at the shallowest depth at which it was found.
+pub(crate) async fn lookup(
+    client: &Client,
+    member: &AsSetMember,
+    recursive: bool,
+) -> Result<Vec<MemberOf>, Error> {
+    let mut found = Vec::new();
+    let mut seen = HashSet::new();
+    if let AsSetMember::AsSet(as_set) = member {
+        _ = seen.insert(as_set.clone());
+    }
+    let mut level = vec![member.clone()];
+    let mut depth = 1;
+    while !level.is_empty() {
+        let mut next = Vec::new();
+        for member in level {
+            let mut as_sets: Vec<_> = client
+                .as_sets_containing(&member.to_string())
+                .await?
+                .into_iter()
+                .map(|(as_set, source)| (as_set, source, false))
+                .collect();
+            if let AsSetMember::AutNum(autnum) = member {
+                as_sets.extend(
+                    client
+                        .as_sets_by_reference(autnum)
+                        .await?
+                        .into_iter()
+                        .map(|(as_set, source)| (as_set, source, true)),
+                );
+            }
+            for (as_set, source, by_reference) in as_sets {
+                if !seen.insert(as_set.clone()) {
+                    continue;
+                }
+                next.push(AsSetMember::AsSet(as_set.clone()));
+                found.push(MemberOf {
+                    as_set,
+                    source,
+                    member: member.clone(),
+                    depth,
+                    by_reference,
+                });
+            }
+        }
+        tracing::debug!(depth, found = next.len(), "found containing as-sets");
+        if !recursive {
+            break;
+        }
+        level = next;
+        depth += 1;
+    }
+    Ok(found)
+}
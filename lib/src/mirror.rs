@@ -0,0 +1,528 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    net::TcpStream,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    error::Error,
+    offline::{attributes, open_dump, read_paragraphs},
+};
+
+/// Object classes that are kept in a mirror, because they are needed to resolve names.
+const MIRRORED_CLASSES: [&str; 6] = [
+    "aut-num",
+    "as-set",
+    "route-set",
+    "filter-set",
+    "route",
+    "route6",
+];
+
+/// The first line of a persisted mirror, followed by the source and serial.
+const HEADER: &str = "%BGPFU-MIRROR";
+
+/// Time to wait for data from an NRTM server before giving up.
+const NRTM_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A local mirror of a single IRR database, kept up to date using version 3 of the Near Real
+/// Time Mirroring (NRTM) protocol.
+///
+/// A mirror is seeded from an RPSL database dump published by the IRR operator (e.g.
+/// `radb.db.gz`), along with the serial at which the dump was taken, which is usually published
+/// alongside it (e.g. `RADB.CURRENTSERIAL`). The changes made since are then retrieved from an
+/// NRTM server using [`Mirror::update`], and applied in order.
+///
+/// The mirror is persisted to an RPSL flat file after each change, so that only the updates made
+/// since the last run need to be retrieved when it is next opened. Only objects of the classes
+/// needed to resolve names are kept.
+///
+/// The contents of one or more mirrors are evaluated using
+/// [`OfflineRpslEvaluator::from_mirrors`][crate::OfflineRpslEvaluator::from_mirrors].
+///
+/// # Examples
+///
+/// ``` no_run
+/// use bgpfu::{Mirror, OfflineRpslEvaluator};
+/// use rpsl::expr::MpFilterExpr;
+///
+/// let mut mirror = Mirror::open("RADB", "/var/lib/bgpfu/radb.db")?;
+/// if mirror.serial().is_none() {
+///     mirror.load_dump("radb.db.gz", 1_234_567)?;
+/// }
+/// mirror.update("nrtm.radb.net:43")?;
+///
+/// let filter: MpFilterExpr = "AS-FOO".parse()?;
+/// let set = OfflineRpslEvaluator::from_mirrors([&mirror]).evaluate(filter)?;
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Mirror {
+    source: String,
+    path: PathBuf,
+    serial: Option<u64>,
+    objects: BTreeMap<(String, String), String>,
+}
+
+impl Mirror {
+    /// Open the mirror of the IRR database `source` persisted at `path`.
+    ///
+    /// If nothing has been persisted at `path` yet, the mirror is empty, and must be seeded using
+    /// [`Mirror::load_dump`] before it can be updated.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::Mirror`] is returned if the file at `path` cannot be read, or is not a mirror
+    /// of `source`.
+    pub fn open<S, P>(source: S, path: P) -> Result<Self, Error>
+    where
+        S: AsRef<str>,
+        P: Into<PathBuf>,
+    {
+        let mut mirror = Self {
+            source: source.as_ref().to_ascii_uppercase(),
+            path: path.into(),
+            serial: None,
+            objects: BTreeMap::new(),
+        };
+        match File::open(&mirror.path) {
+            Ok(file) => {
+                tracing::info!("opening IRR database mirror {}", mirror.path.display());
+                mirror
+                    .read(BufReader::new(file))
+                    .map_err(|err| Error::Mirror(mirror.path.clone(), err))?;
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(Error::Mirror(mirror.path, err)),
+        }
+        Ok(mirror)
+    }
+
+    /// Get the name of the mirrored IRR database.
+    #[must_use]
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Get the serial of the last change applied to the mirror, or `None` if it has not yet been
+    /// seeded.
+    #[must_use]
+    pub const fn serial(&self) -> Option<u64> {
+        self.serial
+    }
+
+    /// Get the number of objects in the mirror.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Returns `true` if the mirror contains no objects.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Replace the contents of the mirror with the RPSL database dump at `path`, taken at
+    /// `serial`.
+    ///
+    /// Compressed dumps are detected automatically.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::ReadDump`] is returned if the dump cannot be read, or an [`Error::Mirror`] if
+    /// the mirror cannot be persisted.
+    pub fn load_dump<P: AsRef<Path>>(&mut self, path: P, serial: u64) -> Result<(), Error> {
+        let path = path.as_ref();
+        tracing::info!(
+            "seeding {} mirror from RPSL database dump {} at serial {serial}",
+            self.source,
+            path.display()
+        );
+        let mut objects = BTreeMap::new();
+        open_dump(path)
+            .and_then(|reader| {
+                read_paragraphs(reader, |paragraph| {
+                    if let Some(key) = object_key(paragraph) {
+                        _ = objects.insert(key, paragraph.to_owned());
+                    }
+                })
+            })
+            .map_err(|err| Error::ReadDump(path.to_path_buf(), err))?;
+        self.objects = objects;
+        self.serial = Some(serial);
+        self.save()
+    }
+
+    /// Retrieve the changes made since the last update from the NRTM server at `addr`, and
+    /// apply them to the mirror, returning the number of changes applied.
+    ///
+    /// Changes are applied in order, so if the update fails part way through, the mirror remains
+    /// consistent with the serial of the last change applied.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::Nrtm`] is returned if the mirror has not been seeded, if the connection to the
+    /// NRTM server fails, or if the server reports an error or sends a malformed response. An
+    /// [`Error::Mirror`] is returned if the mirror cannot be persisted.
+    pub fn update(&mut self, addr: &str) -> Result<usize, Error> {
+        let serial = self.serial;
+        let applied = match self.retrieve(addr) {
+            Ok(0) => return Ok(0),
+            Ok(applied) => applied,
+            Err(err) => {
+                // persist any changes applied before the failure
+                if self.serial != serial {
+                    self.save()?;
+                }
+                return Err(Error::Nrtm(addr.to_owned(), err));
+            }
+        };
+        tracing::info!(
+            "applied {applied} NRTM updates to {} mirror, now at serial {}",
+            self.source,
+            self.serial.unwrap_or_default()
+        );
+        self.save()?;
+        Ok(applied)
+    }
+
+    /// Iterate over the text of each object in the mirror.
+    pub(crate) fn objects(&self) -> impl Iterator<Item = &str> {
+        self.objects.values().map(String::as_str)
+    }
+
+    fn retrieve(&mut self, addr: &str) -> io::Result<usize> {
+        let Some(serial) = self.serial else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the mirror must be seeded from a dump before it can be updated",
+            ));
+        };
+        tracing::debug!("requesting NRTM updates to {} after {serial}", self.source);
+        let mut stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(NRTM_TIMEOUT))?;
+        stream.write_all(format!("-g {}:3:{}-LAST\n", self.source, serial + 1).as_bytes())?;
+        self.apply(BufReader::new(stream))
+    }
+
+    /// Read a persisted mirror.
+    fn read<R: BufRead>(&mut self, mut reader: R) -> io::Result<()> {
+        let mut header = String::new();
+        _ = reader.read_line(&mut header)?;
+        let mut fields = header.split_whitespace();
+        if fields.next() != Some(HEADER) {
+            return Err(invalid_data("missing mirror header".to_owned()));
+        }
+        if fields.next() != Some(self.source.as_str()) {
+            return Err(invalid_data(format!("not a mirror of {}", self.source)));
+        }
+        self.serial = fields.next().and_then(|serial| serial.parse().ok());
+        read_paragraphs(reader, |paragraph| {
+            if let Some(key) = object_key(paragraph) {
+                _ = self.objects.insert(key, paragraph.to_owned());
+            }
+        })
+    }
+
+    /// Persist the mirror, replacing the previous contents of the file atomically.
+    fn save(&self) -> Result<(), Error> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        self.write(&tmp)
+            .and_then(|()| fs::rename(&tmp, &self.path))
+            .map_err(|err| Error::Mirror(self.path.clone(), err))
+    }
+
+    fn write(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        write!(writer, "{HEADER} {}", self.source)?;
+        if let Some(serial) = self.serial {
+            write!(writer, " {serial}")?;
+        }
+        writeln!(writer)?;
+        for text in self.objects.values() {
+            write!(writer, "\n{text}")?;
+        }
+        writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?
+            .sync_all()
+    }
+
+    /// Apply the operations in an NRTM version 3 response.
+    ///
+    /// The response begins with a `%START Version: 3 SOURCE FIRST-LAST` line, followed by `ADD`
+    /// and `DEL` operations, each tagged with its serial and followed by the object concerned,
+    /// and ends with a `%END SOURCE` line. A response containing only comments indicates that
+    /// there are no updates.
+    fn apply<R: BufRead>(&mut self, reader: R) -> io::Result<usize> {
+        let mut response = Response {
+            reader,
+            buf: Vec::new(),
+        };
+        loop {
+            let Some(line) = response.line()? else {
+                return Ok(0);
+            };
+            if line.starts_with("%ERROR") {
+                return Err(invalid_data(line));
+            }
+            if let Some(start) = line.strip_prefix("%START") {
+                self.check_start(start)?;
+                break;
+            }
+        }
+        let mut applied = 0;
+        loop {
+            let line = response
+                .line()?
+                .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with("%ERROR") {
+                return Err(invalid_data(line));
+            }
+            if let Some(source) = line.strip_prefix("%END") {
+                if !source.trim().eq_ignore_ascii_case(&self.source) {
+                    return Err(invalid_data(line));
+                }
+                break Ok(applied);
+            }
+            if line.starts_with('%') {
+                continue;
+            }
+            let (op, serial) = line
+                .split_once(' ')
+                .and_then(|(op, serial)| Some((op, serial.trim().parse::<u64>().ok()?)))
+                .ok_or_else(|| invalid_data(format!("unexpected NRTM operation '{line}'")))?;
+            if self.serial.is_some_and(|current| serial <= current) {
+                return Err(invalid_data(format!(
+                    "NRTM serial {serial} is not newer than {}",
+                    self.serial.unwrap_or_default()
+                )));
+            }
+            let paragraph = response.paragraph()?;
+            let key = object_key(&paragraph);
+            match (op, key) {
+                ("ADD", Some(key)) => _ = self.objects.insert(key, paragraph),
+                ("DEL", Some(key)) => {
+                    if self.objects.remove(&key).is_none() {
+                        tracing::warn!("NRTM serial {serial} deletes unknown object {}", key.1);
+                    }
+                }
+                ("ADD" | "DEL", None) => {}
+                _ => return Err(invalid_data(format!("unexpected NRTM operation '{line}'"))),
+            }
+            self.serial = Some(serial);
+            applied += 1;
+        }
+    }
+
+    /// Check the `%START` line of an NRTM version 3 response.
+    fn check_start(&self, start: &str) -> io::Result<()> {
+        let mut fields = start.split_whitespace();
+        let valid = fields.next() == Some("Version:")
+            && fields.next() == Some("3")
+            && fields
+                .next()
+                .is_some_and(|source| source.eq_ignore_ascii_case(&self.source))
+            && fields
+                .next()
+                .and_then(|range| range.split_once('-'))
+                .and_then(|(first, _)| first.parse::<u64>().ok())
+                .is_some_and(|first| Some(first) == self.serial.map(|serial| serial + 1));
+        if valid {
+            Ok(())
+        } else {
+            Err(invalid_data(format!(
+                "unexpected NRTM response '%START{start}'"
+            )))
+        }
+    }
+}
+
+/// A streamed NRTM response.
+struct Response<R> {
+    reader: R,
+    buf: Vec<u8>,
+}
+
+impl<R: BufRead> Response<R> {
+    /// Read the next line, without its line ending.
+    fn line(&mut self) -> io::Result<Option<String>> {
+        self.buf.clear();
+        if self.reader.read_until(b'\n', &mut self.buf)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(
+            String::from_utf8_lossy(&self.buf).trim_end().to_owned(),
+        ))
+    }
+
+    /// Read the next blank line terminated paragraph, skipping any leading blank lines.
+    fn paragraph(&mut self) -> io::Result<String> {
+        let mut paragraph = String::new();
+        while let Some(line) = self.line()? {
+            if line.trim().is_empty() {
+                if paragraph.is_empty() {
+                    continue;
+                }
+                break;
+            }
+            paragraph.push_str(&line);
+            paragraph.push('\n');
+        }
+        if paragraph.is_empty() {
+            Err(io::Error::from(io::ErrorKind::UnexpectedEof))
+        } else {
+            Ok(paragraph)
+        }
+    }
+}
+
+/// Get the `(class, key)` under which an object is kept, or `None` if it is not of a mirrored
+/// class.
+///
+/// `route` and `route6` objects are keyed by both prefix and origin.
+fn object_key(paragraph: &str) -> Option<(String, String)> {
+    let attrs = attributes(paragraph);
+    let (class, name) = attrs
+        .first()
+        .filter(|(class, _)| MIRRORED_CLASSES.contains(&class.as_str()))?;
+    let key = if class.starts_with("route") && class != "route-set" {
+        let (_, origin) = attrs.iter().find(|(attr, _)| attr == "origin")?;
+        format!("{name}{origin}")
+    } else {
+        name.clone()
+    };
+    Some((class.clone(), key.to_ascii_uppercase()))
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, net::TcpListener, thread};
+
+    use ip::traits::PrefixSet as _;
+
+    use crate::OfflineRpslEvaluator;
+
+    use super::*;
+
+    const DUMP: &str = "\
+route:          192.0.2.0/24
+origin:         AS65001
+mnt-by:         MAINT-EX
+source:         TEST
+
+as-set:         AS-FOO
+members:        AS65001
+mnt-by:         MAINT-EX
+source:         TEST
+
+person:         Nobody
+mnt-by:         MAINT-EX
+source:         TEST
+";
+
+    const NRTM: &str = "\
+%START Version: 3 TEST 11-13
+
+ADD 11
+
+route6:         2001:db8::/32
+origin:         AS65002
+mnt-by:         MAINT-EX
+source:         TEST
+
+ADD 12
+
+as-set:         AS-FOO
+members:        AS65001, AS65002
+mnt-by:         MAINT-EX
+source:         TEST
+
+DEL 13
+
+route:          192.0.2.0/24
+origin:         AS65001
+mnt-by:         MAINT-EX
+source:         TEST
+
+%END TEST
+";
+
+    /// Serve a single NRTM request, returning the query received.
+    fn serve(response: &'static str) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut query = String::new();
+            _ = BufReader::new(stream.try_clone().unwrap())
+                .read_line(&mut query)
+                .unwrap();
+            stream.write_all(response.as_bytes()).unwrap();
+            query
+        });
+        (addr, handle)
+    }
+
+    #[test]
+    fn seed_update_and_evaluate() {
+        let dir = std::env::temp_dir().join(format!("bgpfu-mirror-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let dump = dir.join("test.db");
+        fs::write(&dump, DUMP).unwrap();
+        let path = dir.join("mirror").join("test.db");
+
+        let mut mirror = Mirror::open("test", &path).unwrap();
+        assert!(mirror.update("127.0.0.1:1").is_err());
+        mirror.load_dump(&dump, 10).unwrap();
+        assert_eq!(mirror.len(), 2);
+
+        let (addr, server) = serve(NRTM);
+        assert_eq!(mirror.update(&addr).unwrap(), 3);
+        assert_eq!(server.join().unwrap(), "-g TEST:3:11-LAST\n");
+        assert_eq!(mirror.serial(), Some(13));
+
+        let (addr, server) = serve("% Warning: there are no newer updates available\n");
+        assert_eq!(mirror.update(&addr).unwrap(), 0);
+        assert_eq!(server.join().unwrap(), "-g TEST:3:14-LAST\n");
+
+        let (addr, server) = serve("%ERROR:401: invalid range\n");
+        assert!(mirror.update(&addr).is_err());
+        _ = server.join().unwrap();
+
+        let mirror = Mirror::open("TEST", &path).unwrap();
+        assert_eq!(mirror.serial(), Some(13));
+        assert_eq!(mirror.len(), 2);
+        assert!(Mirror::open("OTHER", &path).is_err());
+
+        let set = OfflineRpslEvaluator::from_mirrors([&mirror])
+            .evaluate("AS-FOO".parse::<rpsl::expr::MpFilterExpr>().unwrap())
+            .unwrap();
+        assert_eq!(set.prefixes().count(), 1);
+
+        let mut contents = String::new();
+        _ = File::open(&path)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert!(contents.starts_with("%BGPFU-MIRROR TEST 13\n"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
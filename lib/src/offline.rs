@@ -0,0 +1,1006 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    mem,
+    path::Path,
+};
+
+use flate2::bufread::MultiGzDecoder;
+
+use ip::{Any, Prefix, PrefixSet};
+
+use rpsl::{
+    attr::RpslAttribute,
+    expr::{
+        eval::{Evaluate, Evaluator, Resolver},
+        AsSetMember, MpFilterExpr,
+    },
+    names::{AsSet, AutNum, FilterSet, RouteSet},
+    obj::{self, RpslObject, RpslObjectClass},
+    primitive::PeerAs,
+};
+
+use crate::{
+    error::Error,
+    expand::{AsSetExpansion, Expansion},
+    explain::{Explainer, Explanation, Lookup},
+    limits::{Budget, Limits},
+    mirror::Mirror,
+    origins::{Origins, RouteObject},
+    policy::{Direction, Policy},
+    query::log_sunk_error,
+    report::Report,
+    rov::Rov,
+};
+
+/// An implementation of [`rpsl::expr::eval::Evaluator`] that resolves RPSL names using local
+/// RPSL database dumps, without any network access.
+///
+/// Dumps are RPSL flat files, such as those published by the IRR operators (e.g. `radb.db` or
+/// `ripe.db.route`), and may be either plain text or `gzip` compressed. The `as-set`,
+/// `route-set`, `filter-set`, `route` and `route6` objects in each dump are indexed when it is
+/// loaded. Objects of other classes, and objects that cannot be parsed, are ignored.
+///
+/// If the same set is defined in more than one dump, the first definition loaded takes
+/// precedence. `route` and `route6` objects from all dumps are combined.
+///
+/// # Examples
+///
+/// ``` no_run
+/// use bgpfu::OfflineRpslEvaluator;
+/// use ip::traits::PrefixSet;
+/// use rpsl::expr::MpFilterExpr;
+///
+/// let filter: MpFilterExpr = "AS-FOO AND { 0.0.0.0/0^8-24, ::/0^16-48 }".parse()?;
+/// OfflineRpslEvaluator::load(["radb.db.gz", "ripe.db.route.gz"])?
+///     .evaluate(filter)?
+///     .ranges()
+///     .for_each(|range| println!("{range}"));
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct OfflineRpslEvaluator {
+    index: Index,
+    peer_as: Option<AutNum>,
+    resolving: HashSet<RouteSet>,
+    rov: Option<Rov>,
+    strict: bool,
+    report: Report,
+    limits: Limits,
+    budget: Budget,
+}
+
+/// Indexes of the RPSL objects needed to resolve each kind of name.
+#[derive(Debug, Default)]
+struct Index {
+    filter_sets: HashMap<FilterSet, MpFilterExpr>,
+    as_sets: HashMap<AsSet, Vec<AsSetMember>>,
+    route_sets: HashMap<RouteSet, MpFilterExpr>,
+    routes: HashMap<AutNum, Vec<Prefix<Any>>>,
+    sources: HashMap<(Prefix<Any>, AutNum), String>,
+    policies: HashMap<AutNum, Policy>,
+}
+
+impl OfflineRpslEvaluator {
+    /// Construct a new [`OfflineRpslEvaluator`] from the RPSL database dumps at `paths`.
+    ///
+    /// Compressed dumps are detected automatically.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::ReadDump`] is returned if any of the dumps cannot be read.
+    pub fn load<I, P>(paths: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let mut evaluator = Self::default();
+        paths.into_iter().try_for_each(|path| {
+            let path = path.as_ref();
+            tracing::info!("loading RPSL database dump {}", path.display());
+            evaluator
+                .read_file(path)
+                .map_err(|err| Error::ReadDump(path.to_path_buf(), err))
+        })?;
+        Ok(evaluator)
+    }
+
+    /// Construct a new [`OfflineRpslEvaluator`] from the contents of local IRR database
+    /// `mirrors`.
+    ///
+    /// As for dumps, if the same set is defined in more than one mirror, the definition in the
+    /// first mirror takes precedence.
+    pub fn from_mirrors<'a, I>(mirrors: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Mirror>,
+    {
+        let mut evaluator = Self::default();
+        for mirror in mirrors {
+            tracing::info!(
+                "loading {} mirror at serial {}",
+                mirror.source(),
+                mirror.serial().unwrap_or_default()
+            );
+            let (mut indexed, mut skipped) = (0usize, 0usize);
+            for text in mirror.objects() {
+                match evaluator.insert(text) {
+                    Some(true) => indexed += 1,
+                    Some(false) => skipped += 1,
+                    None => {}
+                }
+            }
+            tracing::info!("indexed {indexed} RPSL objects, skipped {skipped} unparseable objects");
+        }
+        evaluator
+    }
+
+    /// Validate resolved routes against a set of validated ROA payloads using `rov`.
+    ///
+    /// See [`Rov`] for details.
+    #[must_use]
+    pub fn rov(mut self, rov: Rov) -> Self {
+        self.rov = Some(rov);
+        self
+    }
+
+    /// Make every error encountered during evaluation fatal.
+    ///
+    /// See [`EvaluatorBuilder::strict`][crate::EvaluatorBuilder::strict].
+    #[must_use]
+    pub const fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Limit the resources consumed by each evaluation.
+    ///
+    /// See [`Limits`] for details.
+    #[must_use]
+    pub const fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Reset the per-evaluation state before starting a new evaluation.
+    fn start(&mut self) {
+        self.report = Report::default();
+        self.budget = Budget::new(self.limits);
+    }
+
+    fn read_file(&mut self, path: &Path) -> io::Result<()> {
+        self.read(open_dump(path)?)
+    }
+
+    /// Index the objects read from an RPSL flat file.
+    fn read<R: BufRead>(&mut self, reader: R) -> io::Result<()> {
+        let (mut indexed, mut skipped) = (0usize, 0usize);
+        read_paragraphs(reader, |paragraph| match self.insert(paragraph) {
+            Some(true) => indexed += 1,
+            Some(false) => skipped += 1,
+            None => {}
+        })?;
+        tracing::info!("indexed {indexed} RPSL objects, skipped {skipped} unparseable objects");
+        Ok(())
+    }
+
+    /// Index a single object, returning `None` if it is not of an indexed class, or
+    /// `Some(false)` if it could not be parsed.
+    fn insert(&mut self, paragraph: &str) -> Option<bool> {
+        let attrs = attributes(paragraph);
+        if let Some((_, name)) = attrs.first().filter(|(class, _)| class == "aut-num") {
+            // aut-num objects are not parsed as a whole, so that a single unparseable policy
+            // attribute does not prevent the rest of the policy from being compiled
+            let Ok(autnum) = name.parse() else {
+                return Some(false);
+            };
+            _ = self
+                .index
+                .policies
+                .entry(autnum)
+                .or_insert_with(|| Policy::from_attrs(attrs));
+            return Some(true);
+        }
+        let text = reduce(&attrs)?;
+        match text.parse() {
+            Ok(obj) => {
+                self.index.insert(obj);
+                Some(true)
+            }
+            Err(err) => {
+                tracing::debug!("skipping unparseable RPSL object: {err}\n{paragraph}");
+                Some(false)
+            }
+        }
+    }
+
+    /// Find the `route` and `route6` objects for `prefix`, along with their origins and
+    /// sources.
+    ///
+    /// See [`RpslEvaluator::origins`][crate::RpslEvaluator::origins]. Where the same object is
+    /// defined in more than one dump, only the source of the first is known.
+    #[must_use]
+    pub fn origins(
+        &self,
+        prefix: Prefix<Any>,
+        less_specific: bool,
+        more_specific: bool,
+    ) -> Vec<RouteObject> {
+        let mut origins = Origins::new(prefix, less_specific, more_specific);
+        for (origin, prefixes) in &self.index.routes {
+            for found in prefixes {
+                let source = self.index.sources.get(&(*found, *origin)).cloned();
+                origins.insert(*found, *origin, source);
+            }
+        }
+        origins.finish()
+    }
+
+    /// Evaluate an RPSL expression.
+    ///
+    /// This method wraps [`Evaluator::evaluate`], and is provided as a convenience so that the
+    /// underlying trait does not have to be brought into scope explicitly.
+    ///
+    /// # Errors
+    ///
+    /// See [`Evaluator`] for error handling details.
+    #[tracing::instrument(skip(self, expr), fields(%expr), level = "debug")]
+    pub fn evaluate<'a, T>(
+        &mut self,
+        expr: T,
+    ) -> Result<<Self as Evaluator<'a>>::Output<T>, <Self as Evaluator<'a>>::Error>
+    where
+        T: Evaluate<'a, Self> + Display,
+    {
+        tracing::info!("evaluating RPSL mp-filter expression '{expr}'");
+        self.start();
+        <Self as Evaluator>::evaluate(self, expr).map_err(Error::surface_limit)
+    }
+
+    /// Evaluate an RPSL expression in the context of a peering with `peer_as`.
+    ///
+    /// See [`RpslEvaluator::evaluate_for_peer`][crate::RpslEvaluator::evaluate_for_peer].
+    ///
+    /// # Errors
+    ///
+    /// See [`Evaluator`] for error handling details.
+    #[tracing::instrument(skip(self, expr), fields(%expr), level = "debug")]
+    pub fn evaluate_for_peer<'a, T>(
+        &mut self,
+        expr: T,
+        peer_as: AutNum,
+    ) -> Result<<Self as Evaluator<'a>>::Output<T>, <Self as Evaluator<'a>>::Error>
+    where
+        T: Evaluate<'a, Self> + Display,
+    {
+        tracing::info!("evaluating RPSL mp-filter expression '{expr}' for peer {peer_as}");
+        self.start();
+        let outer = self.peer_as.replace(peer_as);
+        let result = <Self as Evaluator>::evaluate(self, expr).map_err(Error::surface_limit);
+        self.peer_as = outer;
+        result
+    }
+
+    /// Evaluate an RPSL expression, returning a [`Report`] of the non-fatal errors encountered
+    /// alongside the output.
+    ///
+    /// # Errors
+    ///
+    /// See [`Evaluator`] for error handling details.
+    pub fn evaluate_with_report<'a, T>(
+        &mut self,
+        expr: T,
+    ) -> Result<(<Self as Evaluator<'a>>::Output<T>, Report), Error>
+    where
+        T: Evaluate<'a, Self> + Display,
+    {
+        let output = self.evaluate(expr)?;
+        Ok((output, mem::take(&mut self.report)))
+    }
+
+    /// Evaluate an RPSL expression in the context of a peering with `peer_as`, returning a
+    /// [`Report`] of the non-fatal errors encountered alongside the output.
+    ///
+    /// # Errors
+    ///
+    /// See [`Evaluator`] for error handling details.
+    pub fn evaluate_for_peer_with_report<'a, T>(
+        &mut self,
+        expr: T,
+        peer_as: AutNum,
+    ) -> Result<(<Self as Evaluator<'a>>::Output<T>, Report), Error>
+    where
+        T: Evaluate<'a, Self> + Display,
+    {
+        let output = self.evaluate_for_peer(expr, peer_as)?;
+        Ok((output, mem::take(&mut self.report)))
+    }
+
+    /// Evaluate an RPSL `mp-filter` expression, and explain how each of the routes and
+    /// `route-set` members that contributed to the output was reached.
+    ///
+    /// See [`RpslEvaluator::explain`][crate::RpslEvaluator::explain].
+    ///
+    /// # Errors
+    ///
+    /// See [`Evaluator`] for error handling details.
+    #[tracing::instrument(skip(self, expr), fields(%expr), level = "debug")]
+    pub fn explain(&mut self, expr: MpFilterExpr) -> Result<Explanation, Error> {
+        tracing::info!("explaining RPSL mp-filter expression '{expr}'");
+        self.start();
+        let peer_as = self.peer_as;
+        let explanation = Explainer::new(self, peer_as)
+            .explain(expr)
+            .map_err(Error::surface_limit)?;
+        Ok(explanation.with_report(mem::take(&mut self.report)))
+    }
+
+    /// Evaluate and explain an RPSL `mp-filter` expression in the context of a peering with
+    /// `peer_as`.
+    ///
+    /// See [`RpslEvaluator::explain_for_peer`][crate::RpslEvaluator::explain_for_peer].
+    ///
+    /// # Errors
+    ///
+    /// See [`Evaluator`] for error handling details.
+    pub fn explain_for_peer(
+        &mut self,
+        expr: MpFilterExpr,
+        peer_as: AutNum,
+    ) -> Result<Explanation, Error> {
+        let outer = self.peer_as.replace(peer_as);
+        let result = self.explain(expr);
+        self.peer_as = outer;
+        result
+    }
+
+    /// Expand `as_set` recursively into the autonomous systems that it contains.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::AsSetNotFound`] is returned if `as_set` is not defined in any of the loaded
+    /// dumps, and the corresponding [`Error`] variant is returned if any of the configured
+    /// [`Limits`] is exceeded.
+    #[tracing::instrument(skip(self), fields(%as_set), level = "debug")]
+    pub fn expand_as_set(&mut self, as_set: &AsSet) -> Result<AsSetExpansion, Error> {
+        self.expand(as_set, false)
+    }
+
+    /// Expand `as_set` recursively, along with the tree of its members.
+    ///
+    /// # Errors
+    ///
+    /// See [`OfflineRpslEvaluator::expand_as_set`].
+    #[tracing::instrument(skip(self), fields(%as_set), level = "debug")]
+    pub fn expand_as_set_with_tree(&mut self, as_set: &AsSet) -> Result<AsSetExpansion, Error> {
+        self.expand(as_set, true)
+    }
+
+    /// Compile the routing policy that `autnum` publishes for its peering with `peer_as` into an
+    /// `mp-filter` expression.
+    ///
+    /// See [`RpslEvaluator::peer_policy`][crate::RpslEvaluator::peer_policy].
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::AutNumNotFound`] is returned if `autnum` is not defined in any of the loaded
+    /// dumps, and an [`Error::Policy`] is returned if the policy cannot be compiled.
+    #[tracing::instrument(skip(self), fields(%autnum, %peer_as, %direction), level = "debug")]
+    pub fn peer_policy(
+        &mut self,
+        autnum: AutNum,
+        peer_as: AutNum,
+        direction: Direction,
+    ) -> Result<MpFilterExpr, Error> {
+        tracing::info!("compiling the {direction} policy of {autnum} for peer {peer_as}");
+        let policy = self
+            .index
+            .policies
+            .get(&autnum)
+            .cloned()
+            .ok_or(Error::AutNumNotFound(autnum))?;
+        policy.compile(direction, peer_as, |as_set| {
+            self.expand_as_set(as_set).map(AsSetExpansion::into_autnums)
+        })
+    }
+
+    fn expand(&mut self, as_set: &AsSet, tree: bool) -> Result<AsSetExpansion, Error> {
+        tracing::info!("expanding {as_set}");
+        self.start();
+        let expansion = self.walk(self.budget.walk(as_set))?.into_expansion(tree)?;
+        self.budget
+            .add_autnums(expansion.autnums().iter().copied())?;
+        Ok(expansion)
+    }
+
+    /// Walk the members of an `as-set` one level of nesting at a time.
+    fn walk(&mut self, mut expansion: Expansion) -> Result<Expansion, Error> {
+        while !expansion.pending().is_empty() {
+            self.budget.check_deadline()?;
+            let members = self.as_set_members(expansion.pending())?;
+            expansion.advance(members)?;
+        }
+        Ok(expansion)
+    }
+
+    /// Handle an object that was not found in any of the loaded dumps.
+    fn not_found<N: Display>(&mut self, class: &'static str, name: &N) -> Result<(), Error> {
+        self.collect_result::<(), _, Error>(Err(Error::ObjectNotFound(class, name.to_string())))
+            .map(|_| ())
+    }
+
+    fn routes<'b, I>(&self, autnums: I) -> PrefixSet<Any>
+    where
+        I: IntoIterator<Item = &'b AutNum>,
+    {
+        autnums
+            .into_iter()
+            .filter_map(|autnum| {
+                let routes = self.index.routes.get(autnum)?.iter().copied().collect();
+                Some(match &self.rov {
+                    Some(rov) => rov.apply(*autnum, &routes),
+                    None => routes,
+                })
+            })
+            .fold(PrefixSet::<Any>::default(), |acc, routes| acc | routes)
+    }
+}
+
+impl Index {
+    fn insert(&mut self, obj: RpslObject) {
+        match obj {
+            RpslObject::Route(route) => self.insert_route(route.name(), route.attrs()),
+            RpslObject::Route6(route) => self.insert_route(route.name(), route.attrs()),
+            RpslObject::AsSet(as_set) => {
+                let members = as_set
+                    .attrs()
+                    .into_iter()
+                    .filter_map(|attr| match attr {
+                        RpslAttribute::AsSetMembers(members) => Some(list_items(members)),
+                        _ => None,
+                    })
+                    .flatten()
+                    .filter_map(|member| member.parse().ok())
+                    .collect();
+                _ = self.as_sets.entry(as_set.name().clone()).or_insert(members);
+            }
+            RpslObject::RouteSet(route_set) => {
+                // the members of a route-set are resolved by evaluating an equivalent filter,
+                // so that any range operators are applied by the evaluator
+                let expr = route_set
+                    .attrs()
+                    .into_iter()
+                    .filter_map(|attr| match attr {
+                        RpslAttribute::RouteSetMembers(members) => Some(list_items(members)),
+                        RpslAttribute::RouteSetMpMembers(members) => Some(list_items(members)),
+                        _ => None,
+                    })
+                    .flatten()
+                    .map(|member| filter_term(&member))
+                    .collect::<Vec<_>>()
+                    .join(" OR ");
+                let expr = if expr.is_empty() { "NOT ANY" } else { &expr };
+                match expr.parse() {
+                    Ok(expr) => {
+                        _ = self
+                            .route_sets
+                            .entry(route_set.name().clone())
+                            .or_insert(expr);
+                    }
+                    Err(err) => tracing::warn!(
+                        "failed to construct filter for route-set {}: {err}",
+                        route_set.name()
+                    ),
+                }
+            }
+            RpslObject::FilterSet(filter_set) => {
+                let mut mp_filter = None;
+                let mut filter = None;
+                filter_set.attrs().into_iter().for_each(|attr| match attr {
+                    RpslAttribute::MpFilter(expr) => mp_filter = Some(expr.clone()),
+                    RpslAttribute::Filter(expr) => filter = Some(expr.to_string()),
+                    _ => {}
+                });
+                // a `filter` expression is also a valid `mp-filter` expression
+                if let Some(expr) = mp_filter.or_else(|| filter.and_then(|expr| expr.parse().ok()))
+                {
+                    _ = self
+                        .filter_sets
+                        .entry(filter_set.name().clone())
+                        .or_insert(expr);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn insert_route<N: Display>(&mut self, name: &N, attrs: &rpsl::attr::AttributeSeq) {
+        let (mut origin, mut source) = (None, None);
+        attrs.into_iter().for_each(|attr| match attr {
+            RpslAttribute::Origin(autnum) => origin = Some(*autnum),
+            RpslAttribute::Source(registry) => source = Some(registry.to_string()),
+            _ => {}
+        });
+        if let (Some(origin), Ok(prefix)) = (origin, name.to_string().parse()) {
+            self.routes.entry(origin).or_default().push(prefix);
+            if let Some(source) = source {
+                _ = self.sources.entry((prefix, origin)).or_insert(source);
+            }
+        }
+    }
+}
+
+/// Attributes of each indexed object class that are needed to resolve names.
+fn indexed_attrs(class: &str) -> Option<&'static [&'static str]> {
+    match class {
+        "route" | "route6" => Some(&["origin"]),
+        "as-set" => Some(&["members"]),
+        "route-set" => Some(&["members", "mp-members"]),
+        "filter-set" => Some(&["filter", "mp-filter"]),
+        _ => None,
+    }
+}
+
+/// Attributes that must be present for an object to pass validation.
+const MANDATORY_ATTRS: [&str; 3] = ["mnt-by", "changed", "source"];
+
+/// Reduce an object to the attributes that are needed for indexing, returning `None` if the
+/// object is not of an indexed class.
+///
+/// Objects in the dumps published by modern IRR servers frequently contain attributes that are
+/// not defined by the RPSL RFCs (e.g. `last-modified`), and omit the `changed` attribute, so the
+/// remaining attributes are removed before parsing. If necessary, a placeholder `changed`
+/// attribute is added.
+fn reduce(attrs: &[(String, String)]) -> Option<String> {
+    let (class, _) = attrs.first()?;
+    let indexed = indexed_attrs(class)?;
+    let mut text = String::new();
+    let mut changed = false;
+    for (i, (name, value)) in attrs.iter().enumerate() {
+        if value.is_empty()
+            || !(i == 0 || indexed.contains(&&**name) || MANDATORY_ATTRS.contains(&&**name))
+        {
+            continue;
+        }
+        changed |= name == "changed";
+        text.push_str(name);
+        text.push_str(": ");
+        text.push_str(value);
+        text.push('\n');
+    }
+    if !changed {
+        text.push_str("changed: unknown@bgpfu.invalid 19700101\n");
+    }
+    Some(text)
+}
+
+/// Split the text of an RPSL object into `(name, value)` pairs, in the order in which the
+/// attributes appear.
+///
+/// Attribute names are converted to lower case, continuation lines are joined and comments are
+/// stripped.
+pub(crate) fn attributes(paragraph: &str) -> Vec<(String, String)> {
+    let mut attrs: Vec<(String, String)> = Vec::new();
+    for line in paragraph.lines() {
+        let line = line.split_once('#').map_or(line, |(line, _)| line);
+        if let Some(cont) = line.strip_prefix(|c: char| c == '+' || c.is_whitespace()) {
+            match (attrs.last_mut(), cont.trim()) {
+                (Some((_, value)), cont) if !cont.is_empty() => {
+                    value.push(' ');
+                    value.push_str(cont);
+                }
+                _ => {}
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            attrs.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    attrs
+}
+
+/// Open the RPSL flat file at `path`, decompressing it if it is `gzip` compressed.
+pub(crate) fn open_dump(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    // check for the gzip magic number, rather than relying on the file extension
+    if reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Read the blank line separated paragraphs of an RPSL flat file, passing the text of each to
+/// `f`.
+///
+/// Comment lines beginning with `%` or `#` are skipped.
+pub(crate) fn read_paragraphs<R, F>(mut reader: R, mut f: F) -> io::Result<()>
+where
+    R: BufRead,
+    F: FnMut(&str),
+{
+    let mut buf = Vec::new();
+    let mut paragraph = String::new();
+    loop {
+        buf.clear();
+        let eof = reader.read_until(b'\n', &mut buf)? == 0;
+        // some dumps contain text in legacy encodings, which is only ever found in attributes
+        // that are not indexed
+        let line = String::from_utf8_lossy(&buf);
+        if eof || line.trim().is_empty() {
+            if !paragraph.is_empty() {
+                f(&paragraph);
+                paragraph.clear();
+            }
+            if eof {
+                break Ok(());
+            }
+        } else if !line.starts_with(['%', '#']) {
+            paragraph.push_str(&line);
+        }
+    }
+}
+
+/// Split the elements of an RPSL list attribute.
+///
+/// [`rpsl::containers::ListOf`] does not provide access to its elements, but displays them
+/// separated by `", "`.
+fn list_items<T: Display>(list: &T) -> Vec<String> {
+    list.to_string().split(", ").map(str::to_string).collect()
+}
+
+/// Convert a `route-set` member into an equivalent `mp-filter` term.
+fn filter_term(member: &str) -> String {
+    let base = member.split_once('^').map_or(member, |(base, _)| base);
+    if base.parse::<Prefix<Any>>().is_ok() {
+        format!("{{{member}}}")
+    } else if base == "RS-ANY" || base == "AS-ANY" {
+        "ANY".to_string()
+    } else {
+        member.to_string()
+    }
+}
+
+impl Lookup for OfflineRpslEvaluator {
+    fn as_set_members(
+        &mut self,
+        as_sets: &[AsSet],
+    ) -> Result<Vec<Option<Vec<AsSetMember>>>, Error> {
+        Ok(as_sets
+            .iter()
+            .map(|as_set| self.index.as_sets.get(as_set).cloned())
+            .collect())
+    }
+
+    fn originated_routes(&mut self, autnums: &[AutNum]) -> Result<Vec<PrefixSet<Any>>, Error> {
+        Ok(autnums.iter().map(|autnum| self.routes([autnum])).collect())
+    }
+
+    fn route_sources(
+        &mut self,
+        routes: &[(Prefix<Any>, AutNum)],
+    ) -> Result<Vec<Option<String>>, Error> {
+        Ok(routes
+            .iter()
+            .map(|route| self.index.sources.get(route).cloned())
+            .collect())
+    }
+}
+
+impl<'a> Evaluator<'a> for OfflineRpslEvaluator {
+    type Output<T> = <T as Evaluate<'a, Self>>::Output
+    where
+        T: Evaluate<'a, Self>;
+
+    type Error = Error;
+
+    fn finalise<T>(&mut self, output: T::Output) -> Result<Self::Output<T>, Self::Error>
+    where
+        T: Evaluate<'a, Self>,
+    {
+        Ok(output)
+    }
+
+    fn sink_error(&mut self, err: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+        if self.strict {
+            return false;
+        }
+        log_sunk_error(err);
+        self.report.record(None, err);
+        true
+    }
+}
+
+impl Resolver<'_, FilterSet, MpFilterExpr> for OfflineRpslEvaluator {
+    type IError = Error;
+
+    #[tracing::instrument(skip(self), level = "debug")]
+    fn resolve(&mut self, filter_set: &FilterSet) -> Result<MpFilterExpr, Self::IError> {
+        self.budget.check_deadline()?;
+        if let Some(expr) = self.index.filter_sets.get(filter_set) {
+            Ok(expr.clone())
+        } else {
+            self.not_found(obj::FilterSet::CLASS, filter_set)?;
+            Ok("NOT ANY".parse()?)
+        }
+    }
+}
+
+impl Resolver<'_, AsSet, PrefixSet<Any>> for OfflineRpslEvaluator {
+    type IError = Error;
+
+    #[tracing::instrument(skip(self), fields(%as_set), level = "debug")]
+    fn resolve(&mut self, as_set: &AsSet) -> Result<PrefixSet<Any>, Self::IError> {
+        self.budget.check_deadline()?;
+        if let Some(expansion) = self.budget.expansion(as_set) {
+            let expansion = self.walk(expansion)?;
+            self.budget.add_autnums(expansion.into_autnums())?;
+        }
+        let mut autnums = HashSet::new();
+        let mut visited = HashSet::new();
+        let mut missing = Vec::new();
+        let mut stack = vec![as_set];
+        while let Some(as_set) = stack.pop() {
+            if !visited.insert(as_set) {
+                continue;
+            }
+            if let Some(members) = self.index.as_sets.get(as_set) {
+                for member in members {
+                    match member {
+                        AsSetMember::AutNum(autnum) => _ = autnums.insert(autnum),
+                        AsSetMember::AsSet(as_set) => stack.push(as_set),
+                    }
+                }
+            } else {
+                missing.push(as_set.clone());
+            }
+        }
+        let routes = self.routes(autnums);
+        missing
+            .iter()
+            .try_for_each(|as_set| self.not_found(obj::AsSet::CLASS, as_set))?;
+        self.budget.add_prefixes(as_set, &routes)?;
+        Ok(routes)
+    }
+}
+
+impl Resolver<'_, RouteSet, PrefixSet<Any>> for OfflineRpslEvaluator {
+    type IError = Error;
+
+    #[tracing::instrument(skip(self), level = "debug")]
+    fn resolve(&mut self, route_set: &RouteSet) -> Result<PrefixSet<Any>, Self::IError> {
+        self.budget.check_deadline()?;
+        let Some(expr) = self.index.route_sets.get(route_set).cloned() else {
+            self.not_found(obj::RouteSet::CLASS, route_set)?;
+            return Ok(PrefixSet::<Any>::default());
+        };
+        // members of a route-set that is already being resolved contribute nothing new
+        if !self.resolving.insert(route_set.clone()) {
+            tracing::debug!("skipping recursive reference to {route_set}");
+            return Ok(PrefixSet::<Any>::default());
+        }
+        let result = <Self as Evaluator>::evaluate(self, expr);
+        _ = self.resolving.remove(route_set);
+        let set = result?;
+        self.budget.add_prefixes(route_set, &set)?;
+        Ok(set)
+    }
+}
+
+impl Resolver<'_, AutNum, PrefixSet<Any>> for OfflineRpslEvaluator {
+    type IError = Error;
+
+    #[tracing::instrument(skip(self), fields(%autnum), level = "debug")]
+    fn resolve(&mut self, autnum: &AutNum) -> Result<PrefixSet<Any>, Self::IError> {
+        self.budget.check_deadline()?;
+        self.budget.add_autnums([*autnum])?;
+        let routes = self.routes([autnum]);
+        self.budget.add_prefixes(autnum, &routes)?;
+        Ok(routes)
+    }
+}
+
+impl Resolver<'_, PeerAs, PrefixSet<Any>> for OfflineRpslEvaluator {
+    type IError = Error;
+
+    #[tracing::instrument(skip(self), level = "debug")]
+    fn resolve(&mut self, _: &PeerAs) -> Result<PrefixSet<Any>, Self::IError> {
+        let autnum = self.peer_as.ok_or(Error::PeerAs)?;
+        tracing::debug!("substituting {autnum} for 'PeerAS'");
+        <Self as Resolver<'_, AutNum, PrefixSet<Any>>>::resolve(self, &autnum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ip::traits::PrefixSet as _;
+
+    use super::*;
+
+    const DUMP: &str = "\
+% comment header
+
+route:          192.0.2.0/24
+descr:          example
+origin:         AS65001
+mnt-by:         MAINT-EX
+last-modified:  2024-01-01T00:00:00Z
+source:         RADB
+
+route6:         2001:db8::/32
+origin:         AS65002
+mnt-by:         MAINT-EX
+source:         RADB
+
+route:          198.51.100.0/24
+origin:         AS65003
+mnt-by:         MAINT-EX
+source:         RADB
+
+as-set:         AS-FOO
+members:        AS65001,
+                AS-BAR # nested
+mnt-by:         MAINT-EX
+source:         RADB
+
+as-set:         AS-BAR
+members:        AS65002, AS-FOO
+mnt-by:         MAINT-EX
+source:         RADB
+
+route-set:      RS-FOO
+members:        203.0.113.0/24^+, AS65003
+mnt-by:         MAINT-EX
+source:         RADB
+
+filter-set:     FLTR-FOO
+mp-filter:      AS-FOO OR RS-FOO
+mnt-by:         MAINT-EX
+source:         RADB
+
+aut-num:        AS65000
+as-name:        EXAMPLE
+mp-import:      afi any.unicast from AS-BAR accept PeerAS
+mp-import:      afi ipv6.unicast from AS65003
+                accept RS-FOO
+mp-export:      to ANY announce AS65000
+mnt-by:         MAINT-EX
+source:         RADB
+";
+
+    fn evaluator() -> OfflineRpslEvaluator {
+        let mut evaluator = OfflineRpslEvaluator::default();
+        evaluator.read(DUMP.as_bytes()).unwrap();
+        evaluator
+    }
+
+    fn prefixes(set: &PrefixSet<Any>) -> Vec<String> {
+        let mut prefixes: Vec<_> = set.prefixes().map(|prefix| prefix.to_string()).collect();
+        prefixes.sort();
+        prefixes
+    }
+
+    #[test]
+    fn resolve_recursive_as_set() {
+        let set = evaluator()
+            .evaluate("AS-FOO".parse::<MpFilterExpr>().unwrap())
+            .unwrap();
+        assert_eq!(prefixes(&set), ["192.0.2.0/24", "2001:db8::/32"]);
+    }
+
+    #[test]
+    fn resolve_filter_set() {
+        let set = evaluator()
+            .evaluate("FLTR-FOO".parse::<MpFilterExpr>().unwrap())
+            .unwrap();
+        assert!(set.contains("203.0.113.128/25".parse().unwrap()));
+        assert!(set.contains("198.51.100.0/24".parse().unwrap()));
+    }
+
+    #[test]
+    fn explain_nested_as_set() {
+        let explanation = evaluator()
+            .explain("AS-FOO OR RS-FOO".parse().unwrap())
+            .unwrap();
+        let mut derivations: Vec<_> = explanation
+            .derivations()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        derivations.sort();
+        assert_eq!(
+            derivations,
+            [
+                "AS-FOO -> AS-BAR -> AS65002 -> route6 2001:db8::/32 (RADB)",
+                "AS-FOO -> AS65001 -> route 192.0.2.0/24 (RADB)",
+                "RS-FOO -> 198.51.100.0/24^24-24",
+                "RS-FOO -> 203.0.113.0/24^24-32",
+            ]
+        );
+        let range = "192.0.2.0/24,24,24".parse().unwrap();
+        assert_eq!(explanation.contributors(&range).count(), 1);
+    }
+
+    #[test]
+    fn expand_as_set() {
+        let as_set = "AS-FOO".parse().unwrap();
+        let expansion = evaluator().expand_as_set_with_tree(&as_set).unwrap();
+        assert_eq!(
+            expansion.autnums(),
+            &["AS65001", "AS65002"]
+                .iter()
+                .map(|autnum| autnum.parse().unwrap())
+                .collect()
+        );
+        assert_eq!(
+            expansion.tree().unwrap().to_string(),
+            "AS-FOO\n  AS65001\n  AS-BAR\n    AS65002\n    AS-FOO (repeated)\n"
+        );
+        assert!(matches!(
+            evaluator().expand_as_set(&"AS-MISSING".parse().unwrap()),
+            Err(Error::AsSetNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn missing_as_set_is_empty() {
+        let (set, report) = evaluator()
+            .evaluate_with_report("AS-MISSING".parse::<MpFilterExpr>().unwrap())
+            .unwrap();
+        assert!(prefixes(&set).is_empty());
+        assert_eq!(report.names().collect::<Vec<_>>(), ["AS-MISSING"]);
+    }
+
+    #[test]
+    fn limits_are_fatal() {
+        let expr = || "AS-FOO".parse::<MpFilterExpr>().unwrap();
+        let result = evaluator()
+            .limits(Limits::default().max_depth(0))
+            .evaluate(expr());
+        assert!(matches!(result, Err(Error::DepthLimit(_, 0))));
+        let result = evaluator()
+            .limits(Limits::default().max_autnums(1))
+            .evaluate(expr());
+        assert!(matches!(result, Err(Error::AutNumLimit(1))));
+        let result = evaluator()
+            .limits(
+                Limits::default()
+                    .max_depth(1)
+                    .max_autnums(2)
+                    .max_prefixes(1),
+            )
+            .evaluate(expr());
+        assert_eq!(
+            prefixes(&result.unwrap()),
+            ["192.0.2.0/24", "2001:db8::/32"]
+        );
+    }
+
+    #[test]
+    fn missing_as_set_is_fatal_when_strict() {
+        let result = evaluator()
+            .strict(true)
+            .evaluate("AS-FOO OR AS-MISSING".parse::<MpFilterExpr>().unwrap());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn peer_policy() {
+        let mut evaluator = evaluator();
+        let autnum: AutNum = "AS65000".parse().unwrap();
+        let peer_as: AutNum = "AS65002".parse().unwrap();
+        let expr = evaluator
+            .peer_policy(autnum, peer_as, Direction::Import)
+            .unwrap();
+        let set = evaluator.evaluate_for_peer(expr, peer_as).unwrap();
+        assert_eq!(prefixes(&set), ["2001:db8::/32"]);
+        let expr = evaluator
+            .peer_policy(autnum, "AS65003".parse().unwrap(), Direction::Import)
+            .unwrap();
+        assert_eq!(expr.to_string(), "(RS-FOO) AND {::/0^+}");
+        assert!(matches!(
+            evaluator.peer_policy(peer_as, autnum, Direction::Export),
+            Err(Error::AutNumNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,211 @@
+use std::fmt;
+
+use ip::{traits::Prefix as _, Any, Prefix};
+
+use rpsl::names::AutNum;
+
+use crate::offline::{attributes, read_paragraphs};
+
+/// How the prefix of a [`RouteObject`] relates to the prefix looked up.
+///
+/// Variants are ordered from the least to the most specific.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Specificity {
+    /// The prefix of the object covers the prefix looked up.
+    LessSpecific,
+    /// The prefix of the object is the prefix looked up.
+    Exact,
+    /// The prefix of the object is covered by the prefix looked up.
+    MoreSpecific,
+}
+
+impl Specificity {
+    /// Determine how `found` relates to `prefix`, or `None` if they do not overlap.
+    fn of(prefix: &Prefix<Any>, found: &Prefix<Any>) -> Option<Self> {
+        // prefixes of different address families are not ordered consistently
+        if found.afi() != prefix.afi() {
+            None
+        } else if found == prefix {
+            Some(Self::Exact)
+        } else if found.contains(prefix) {
+            Some(Self::LessSpecific)
+        } else if prefix.contains(found) {
+            Some(Self::MoreSpecific)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Specificity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Self::LessSpecific => "less-specific",
+            Self::Exact => "exact",
+            Self::MoreSpecific => "more-specific",
+        })
+    }
+}
+
+/// A `route` or `route6` object found by an origin lookup for a prefix.
+///
+/// # Examples
+///
+/// ``` no_run
+/// use bgpfu::RpslEvaluator;
+///
+/// let evaluator = RpslEvaluator::new("whois.radb.net", 43)?;
+/// for route in evaluator.origins("192.0.2.0/24".parse()?, true, true)? {
+///     println!("{:<13} {route}", route.specificity());
+/// }
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteObject {
+    prefix: Prefix<Any>,
+    origin: AutNum,
+    source: Option<String>,
+    specificity: Specificity,
+}
+
+impl RouteObject {
+    /// Get the prefix of the object.
+    #[must_use]
+    pub const fn prefix(&self) -> Prefix<Any> {
+        self.prefix
+    }
+
+    /// Get the autonomous system that the object authorises to originate the prefix.
+    #[must_use]
+    pub const fn origin(&self) -> AutNum {
+        self.origin
+    }
+
+    /// Get the IRR database in which the object is defined, if known.
+    #[must_use]
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    /// Get how the prefix of the object relates to the prefix looked up.
+    #[must_use]
+    pub const fn specificity(&self) -> Specificity {
+        self.specificity
+    }
+}
+
+impl fmt::Display for RouteObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.prefix, self.origin)?;
+        if let Some(source) = &self.source {
+            write!(f, " ({source})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects the [`RouteObject`]s found by an origin lookup for `prefix`.
+#[derive(Debug)]
+pub(crate) struct Origins {
+    prefix: Prefix<Any>,
+    less_specific: bool,
+    more_specific: bool,
+    found: Vec<RouteObject>,
+}
+
+impl Origins {
+    /// Start an origin lookup for `prefix`, optionally including less-specific and
+    /// more-specific prefixes.
+    pub(crate) const fn new(prefix: Prefix<Any>, less_specific: bool, more_specific: bool) -> Self {
+        Self {
+            prefix,
+            less_specific,
+            more_specific,
+            found: Vec::new(),
+        }
+    }
+
+    /// Add the object for `found` originated by `origin`, if its prefix was looked up, and it
+    /// has not already been added.
+    pub(crate) fn insert(&mut self, found: Prefix<Any>, origin: AutNum, source: Option<String>) {
+        let Some(specificity) = Specificity::of(&self.prefix, &found) else {
+            return;
+        };
+        let wanted = match specificity {
+            Specificity::LessSpecific => self.less_specific,
+            Specificity::Exact => true,
+            Specificity::MoreSpecific => self.more_specific,
+        };
+        let object = RouteObject {
+            prefix: found,
+            origin,
+            source,
+            specificity,
+        };
+        if wanted && !self.found.contains(&object) {
+            self.found.push(object);
+        }
+    }
+
+    /// Add each `route` or `route6` object in the text of a response.
+    pub(crate) fn insert_objects(&mut self, data: &str) {
+        // reading from a byte slice cannot fail
+        _ = read_paragraphs(data.as_bytes(), |paragraph| {
+            let attrs = attributes(paragraph);
+            let Some((_, prefix)) = attrs
+                .first()
+                .filter(|(class, _)| class == "route" || class == "route6")
+            else {
+                return;
+            };
+            let origin = attrs
+                .iter()
+                .find(|(name, _)| name == "origin")
+                .and_then(|(_, origin)| origin.parse().ok());
+            let source = attrs
+                .iter()
+                .find(|(name, _)| name == "source")
+                .map(|(_, source)| source.clone());
+            if let (Ok(prefix), Some(origin)) = (prefix.parse(), origin) {
+                self.insert(prefix, origin, source);
+            } else {
+                tracing::debug!("skipping unparseable route object\n{paragraph}");
+            }
+        });
+    }
+
+    /// Get the objects found, from the least to the most specific, in a deterministic order.
+    pub(crate) fn finish(mut self) -> Vec<RouteObject> {
+        self.found.sort_by_cached_key(|object| {
+            (
+                object.specificity,
+                *object.prefix.prefix_len().as_ref(),
+                object.to_string(),
+            )
+        });
+        self.found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relate_and_order() {
+        let mut origins = Origins::new("192.0.2.0/24".parse().unwrap(), true, false);
+        origins.insert_objects(
+            "route: 192.0.2.0/25\norigin: AS65003\nsource: RADB\n\n\
+             route: 192.0.2.0/24\norigin: AS65001\nsource: RIPE\n\n\
+             route: 192.0.0.0/16\norigin: AS65002\nsource: RADB\n\n\
+             route: 192.0.2.0/24\norigin: AS65001\nsource: RIPE\n\n\
+             route6: 2001:db8::/32\norigin: AS65004\nsource: RADB\n",
+        );
+        let found = origins.finish();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].specificity(), Specificity::LessSpecific);
+        assert_eq!(found[0].to_string(), "192.0.0.0/16 AS65002 (RADB)");
+        assert_eq!(found[1].specificity(), Specificity::Exact);
+        assert_eq!(found[1].source(), Some("RIPE"));
+    }
+}
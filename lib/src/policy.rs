@@ -0,0 +1,550 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use rpsl::{
+    expr::{ExportExpr, ImportExpr, MpExportExpr, MpFilterExpr, MpImportExpr},
+    names::{AsSet, AutNum},
+};
+
+use crate::error::Error;
+
+/// The direction of a routing policy, relative to the `aut-num` that publishes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Routes accepted from the peer, described by the `mp-import` and `import` attributes.
+    Import,
+    /// Routes announced to the peer, described by the `mp-export` and `export` attributes.
+    Export,
+}
+
+impl Direction {
+    /// The keyword introducing each peering in a policy factor.
+    const fn peer_keyword(self) -> &'static str {
+        match self {
+            Self::Import => "from",
+            Self::Export => "to",
+        }
+    }
+
+    /// The keyword introducing the filter in a policy factor.
+    const fn filter_keyword(self) -> &'static str {
+        match self {
+            Self::Import => "accept",
+            Self::Export => "announce",
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Import => write!(f, "import"),
+            Self::Export => write!(f, "export"),
+        }
+    }
+}
+
+/// The names of the `aut-num` attributes describing routing policy.
+const POLICY_ATTRS: [&str; 4] = ["import", "mp-import", "export", "mp-export"];
+
+/// The routing policy attributes of an `aut-num` object.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Policy {
+    attrs: Vec<(String, String)>,
+}
+
+impl Policy {
+    /// Collect the policy attributes from the `(name, value)` pairs of an `aut-num` object.
+    pub(crate) fn from_attrs<I>(attrs: I) -> Self
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        Self {
+            attrs: attrs
+                .into_iter()
+                .filter(|(name, _)| POLICY_ATTRS.contains(&name.as_str()))
+                .collect(),
+        }
+    }
+
+    /// Compile the policy for the peering with `peer_as` in `direction` into a single `mp-filter`
+    /// expression, using `autnums` to expand the `as-set`s appearing in peering expressions.
+    ///
+    /// The filters of every policy factor whose peering matches `peer_as` are combined, so that
+    /// the expression matches each route that would be accepted (or announced) by some factor.
+    /// Actions are ignored, as are redistribution policies between protocols other than BGP.
+    ///
+    /// Policy attributes that cannot be parsed are skipped with a warning, and an `as-set` that
+    /// cannot be found matches no peer.
+    pub(crate) fn compile<F>(
+        &self,
+        direction: Direction,
+        peer_as: AutNum,
+        autnums: F,
+    ) -> Result<MpFilterExpr, Error>
+    where
+        F: FnMut(&AsSet) -> Result<HashSet<AutNum>, Error>,
+    {
+        let mut compiler = Compiler {
+            direction,
+            peer_as,
+            autnums,
+            expanded: HashMap::new(),
+        };
+        let mut filters = Vec::new();
+        for (name, value) in &self.attrs {
+            let Some((text, ipv4_only)) = statement(name, value, direction) else {
+                continue;
+            };
+            let mut tokens = Tokens::new(&text);
+            if let Some(filter) = compiler.statement(&mut tokens)? {
+                filters.push(if ipv4_only {
+                    restrict(&filter, true, false)
+                } else {
+                    filter
+                });
+            }
+        }
+        let expr = if filters.is_empty() {
+            "NOT ANY".to_owned()
+        } else {
+            union(&filters)
+        };
+        Ok(expr.parse()?)
+    }
+}
+
+/// Parse the value of the policy attribute `name` if it describes `direction`, returning its
+/// canonical text, and whether it applies only to IPv4.
+fn statement(name: &str, value: &str, direction: Direction) -> Option<(String, bool)> {
+    let parsed = match (name, direction) {
+        ("mp-import", Direction::Import) => value
+            .parse::<MpImportExpr>()
+            .map(|e| (e.to_string(), false)),
+        ("import", Direction::Import) => value.parse::<ImportExpr>().map(|e| (e.to_string(), true)),
+        ("mp-export", Direction::Export) => value
+            .parse::<MpExportExpr>()
+            .map(|e| (e.to_string(), false)),
+        ("export", Direction::Export) => value.parse::<ExportExpr>().map(|e| (e.to_string(), true)),
+        _ => return None,
+    };
+    parsed
+        .map_err(|err| tracing::warn!("skipping unparseable {name} attribute '{value}': {err}"))
+        .ok()
+}
+
+/// Combine `filters` so that a route matching any of them matches.
+fn union(filters: &[String]) -> String {
+    match filters {
+        [filter] => filter.clone(),
+        _ => filters
+            .iter()
+            .map(|filter| format!("({filter})"))
+            .collect::<Vec<_>>()
+            .join(" OR "),
+    }
+}
+
+/// Restrict `filter` to the address families for which `ipv4` and `ipv6` are `true`.
+fn restrict(filter: &str, ipv4: bool, ipv6: bool) -> String {
+    match (ipv4, ipv6) {
+        (true, false) => format!("({filter}) AND {{0.0.0.0/0^+}}"),
+        (false, true) => format!("({filter}) AND {{::/0^+}}"),
+        _ => filter.to_owned(),
+    }
+}
+
+/// Determine whether an `afi` list includes unicast routes of IPv4 and IPv6, respectively.
+fn families(afis: &[String]) -> (bool, bool) {
+    afis.iter().fold((false, false), |(ipv4, ipv6), afi| {
+        let (afi, safi) = afi.split_once('.').unwrap_or((afi, "unicast"));
+        if safi.eq_ignore_ascii_case("unicast") {
+            (
+                ipv4 || afi.eq_ignore_ascii_case("ipv4") || afi.eq_ignore_ascii_case("any"),
+                ipv6 || afi.eq_ignore_ascii_case("ipv6") || afi.eq_ignore_ascii_case("any"),
+            )
+        } else {
+            (ipv4, ipv6)
+        }
+    })
+}
+
+/// The whitespace-separated tokens of a policy statement in its canonical textual form.
+///
+/// The expression types in [`rpsl::expr`] do not provide access to their components, but their
+/// [`Display`][fmt::Display] implementations produce a canonical form, with keywords separated by
+/// single spaces. Bracketed text, such as a prefix set or AS path regular expression in a filter,
+/// is kept together as a single token.
+#[derive(Debug)]
+struct Tokens {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Tokens {
+    fn new(text: &str) -> Self {
+        let mut tokens: Vec<String> = Vec::new();
+        let mut depth = 0i32;
+        for word in text.split_whitespace() {
+            if depth > 0 {
+                if let Some(last) = tokens.last_mut() {
+                    last.push(' ');
+                    last.push_str(word);
+                }
+            } else {
+                tokens.push(word.to_owned());
+            }
+            // the braces delimiting a multi-factor policy term stand alone
+            if word != "{" && word != "}" {
+                depth += word
+                    .chars()
+                    .map(|c| match c {
+                        '{' | '(' | '<' => 1,
+                        '}' | ')' | '>' => -1,
+                        _ => 0,
+                    })
+                    .sum::<i32>();
+            }
+        }
+        Self { tokens, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Consume the next token if it is `keyword`.
+    fn eat(&mut self, keyword: &str) -> bool {
+        let found = self.peek() == Some(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    /// Consume tokens up to, but not including, the next token satisfying `stop`.
+    fn until<F: Fn(&str) -> bool>(&mut self, stop: F) -> Vec<String> {
+        let mut taken = Vec::new();
+        while let Some(token) = self.peek() {
+            if stop(token) {
+                break;
+            }
+            taken.extend(self.next());
+        }
+        taken
+    }
+
+    fn malformed(&self) -> Error {
+        Error::Policy(
+            self.tokens.join(" "),
+            "malformed policy expression".to_owned(),
+        )
+    }
+}
+
+/// Compiles policy statements for a single peering.
+struct Compiler<F> {
+    direction: Direction,
+    peer_as: AutNum,
+    autnums: F,
+    expanded: HashMap<AsSet, HashSet<AutNum>>,
+}
+
+impl<F> Compiler<F>
+where
+    F: FnMut(&AsSet) -> Result<HashSet<AutNum>, Error>,
+{
+    /// Compile a statement, returning `None` if no part of it applies to the peering.
+    fn statement(&mut self, tokens: &mut Tokens) -> Result<Option<String>, Error> {
+        let mut bgp = true;
+        for keyword in ["protocol", "into"] {
+            if tokens.eat(keyword) {
+                let protocol = tokens.next().ok_or_else(|| tokens.malformed())?;
+                bgp &= protocol.eq_ignore_ascii_case("BGP4");
+            }
+        }
+        if !bgp {
+            return Ok(None);
+        }
+        let filter = self.afi_expr(tokens)?;
+        if tokens.peek().is_some() {
+            return Err(tokens.malformed());
+        }
+        Ok(filter)
+    }
+
+    /// Compile an expression, optionally preceded by an `afi` list.
+    fn afi_expr(&mut self, tokens: &mut Tokens) -> Result<Option<String>, Error> {
+        let families = if tokens.eat("afi") {
+            let mut afis = Vec::new();
+            while let Some(afi) = tokens.next() {
+                let more = afi.ends_with(',');
+                afis.push(afi.trim_end_matches(',').to_owned());
+                if !more {
+                    break;
+                }
+            }
+            Some(families(&afis))
+        } else {
+            None
+        };
+        let term = self.term(tokens)?;
+        let filter = if tokens.eat("EXCEPT") {
+            // routes matching the exception are subject to its policy instead, so either
+            // policy may accept them
+            match (term, self.afi_expr(tokens)?) {
+                (Some(term), Some(except)) => Some(union(&[term, except])),
+                (term, except) => term.or(except),
+            }
+        } else if tokens.eat("REFINE") {
+            match (term, self.afi_expr(tokens)?) {
+                (Some(term), Some(refine)) => Some(format!("({term}) AND ({refine})")),
+                _ => None,
+            }
+        } else {
+            term
+        };
+        Ok(match families {
+            Some((false, false)) => None,
+            Some((ipv4, ipv6)) => filter.map(|filter| restrict(&filter, ipv4, ipv6)),
+            None => filter,
+        })
+    }
+
+    /// Compile a policy term, consisting of a single factor or a braced list of factors.
+    fn term(&mut self, tokens: &mut Tokens) -> Result<Option<String>, Error> {
+        let mut filters = Vec::new();
+        if tokens.eat("{") {
+            while !tokens.eat("}") {
+                filters.extend(self.factor(tokens, true)?);
+            }
+        } else {
+            filters.extend(self.factor(tokens, false)?);
+        }
+        Ok((!filters.is_empty()).then(|| union(&filters)))
+    }
+
+    /// Compile a policy factor, returning its filter if any of its peerings matches the peer.
+    fn factor(&mut self, tokens: &mut Tokens, braced: bool) -> Result<Option<String>, Error> {
+        let (peer, verb) = (
+            self.direction.peer_keyword(),
+            self.direction.filter_keyword(),
+        );
+        let mut matched = false;
+        while tokens.eat(peer) {
+            let peering = tokens.until(|token| token == "ACTION" || token == peer || token == verb);
+            matched |= self.peering(&peering)?;
+            if tokens.eat("ACTION") {
+                _ = tokens.until(|token| token == peer || token == verb);
+            }
+        }
+        if !tokens.eat(verb) {
+            return Err(tokens.malformed());
+        }
+        let mut filter = if braced {
+            let mut filter = tokens.until(|token| token.ends_with(';'));
+            let last = tokens.next().ok_or_else(|| tokens.malformed())?;
+            filter.push(last.trim_end_matches(';').to_owned());
+            filter
+        } else {
+            tokens.until(|token| token == "EXCEPT" || token == "REFINE")
+        };
+        filter.retain(|token| !token.is_empty());
+        if filter.is_empty() {
+            return Err(tokens.malformed());
+        }
+        Ok(matched.then(|| filter.join(" ")))
+    }
+
+    /// Determine whether a peering expression matches the peer.
+    ///
+    /// Router expressions following the AS expression are ignored.
+    fn peering(&mut self, peering: &[String]) -> Result<bool, Error> {
+        match peering.first() {
+            Some(name) if name.to_ascii_lowercase().starts_with("prng-") => Err(Error::Policy(
+                peering.join(" "),
+                format!("peering-set {name} is not supported"),
+            )),
+            Some(_) => self.as_expr(peering).map(|(matched, _)| matched),
+            None => Err(Error::Policy(
+                String::new(),
+                "empty peering expression".to_owned(),
+            )),
+        }
+    }
+
+    /// Evaluate the AS expression at the start of `tokens`, returning whether it matches the
+    /// peer, and the number of tokens consumed.
+    ///
+    /// Operators are applied with the precedence given by RFC 2622, where `AND` and `EXCEPT` bind
+    /// more tightly than `OR`, and operators of equal precedence associate to the left. The parser
+    /// in [`rpsl`] builds a right-associative tree without precedence, but displays it without
+    /// parentheses, so the canonical form still has the operators in their original order.
+    fn as_expr(&mut self, tokens: &[String]) -> Result<(bool, usize), Error> {
+        let (mut matched, mut pos) = self.as_conjunction(tokens, 0)?;
+        while let [op, next, ..] = &tokens[pos..] {
+            if op != "OR" || self.as_term(next)?.is_none() {
+                break;
+            }
+            let (rhs, end) = self.as_conjunction(tokens, pos + 1)?;
+            matched |= rhs;
+            pos = end;
+        }
+        Ok((matched, pos))
+    }
+
+    /// Evaluate the terms joined by `AND` and `EXCEPT` starting at `tokens[start]`, returning
+    /// whether they match the peer, and the position of the first token not consumed.
+    fn as_conjunction(&mut self, tokens: &[String], start: usize) -> Result<(bool, usize), Error> {
+        let Some(mut matched) = self.as_term(&tokens[start])? else {
+            return Err(Error::Policy(
+                tokens.join(" "),
+                format!("invalid AS expression term {}", tokens[start]),
+            ));
+        };
+        let mut pos = start + 1;
+        while let [op, next, ..] = &tokens[pos..] {
+            let Some(rhs) = self.as_term(next)? else {
+                break;
+            };
+            match op.as_str() {
+                "AND" => matched &= rhs,
+                "EXCEPT" => matched &= !rhs,
+                _ => break,
+            }
+            pos += 2;
+        }
+        Ok((matched, pos))
+    }
+
+    /// Evaluate a single AS expression term, returning `None` if `token` is not one.
+    fn as_term(&mut self, token: &str) -> Result<Option<bool>, Error> {
+        if token == "ANY" || token == "AS-ANY" {
+            return Ok(Some(true));
+        }
+        if let Some(inner) = token.strip_prefix('(').and_then(|t| t.strip_suffix(')')) {
+            let inner = Tokens::new(inner).tokens;
+            if inner.is_empty() {
+                return Ok(None);
+            }
+            return self.as_expr(&inner).map(|(matched, _)| Some(matched));
+        }
+        if let Ok(autnum) = token.parse::<AutNum>() {
+            return Ok(Some(autnum == self.peer_as));
+        }
+        let Ok(as_set) = token.parse::<AsSet>() else {
+            return Ok(None);
+        };
+        if !self.expanded.contains_key(&as_set) {
+            let autnums = match (self.autnums)(&as_set) {
+                Ok(autnums) => autnums,
+                Err(Error::AsSetNotFound(_)) => {
+                    tracing::warn!("{as_set} appears in a peering expression, but was not found");
+                    HashSet::new()
+                }
+                Err(err) => return Err(err),
+            };
+            _ = self.expanded.insert(as_set.clone(), autnums);
+        }
+        Ok(Some(
+            self.expanded
+                .get(&as_set)
+                .is_some_and(|autnums| autnums.contains(&self.peer_as)),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(attrs: &[(&str, &str)], direction: Direction, peer_as: &str) -> String {
+        let policy = Policy::from_attrs(
+            attrs
+                .iter()
+                .map(|(name, value)| ((*name).to_owned(), (*value).to_owned())),
+        );
+        policy
+            .compile(direction, peer_as.parse().unwrap(), |as_set| {
+                if as_set.to_string() == "AS-PEERS" {
+                    Ok(std::iter::once("AS65002".parse().unwrap()).collect())
+                } else {
+                    Err(Error::AsSetNotFound(as_set.clone()))
+                }
+            })
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn matching_factors() {
+        let attrs = [
+            ("import", "from AS65001 action pref=100; accept AS65001"),
+            (
+                "mp-import",
+                "afi ipv6.unicast { from AS65001 accept AS-FOO; from AS-PEERS accept ANY; }",
+            ),
+            ("mp-export", "to AS65001 announce AS65000"),
+        ];
+        assert_eq!(
+            compile(&attrs, Direction::Import, "AS65001"),
+            "((AS65001) AND {0.0.0.0/0^+}) OR ((AS-FOO) AND {::/0^+})"
+        );
+        assert_eq!(
+            compile(&attrs, Direction::Import, "AS65002"),
+            "(ANY) AND {::/0^+}"
+        );
+        assert_eq!(compile(&attrs, Direction::Export, "AS65001"), "AS65000");
+        assert_eq!(compile(&attrs, Direction::Export, "AS65002"), "NOT ANY");
+    }
+
+    #[test]
+    fn refine_and_except() {
+        let attrs = [(
+            "mp-import",
+            "from AS65001 OR AS-MISSING accept ANY REFINE from AS-ANY EXCEPT AS65002 accept PeerAS",
+        )];
+        assert_eq!(
+            compile(&attrs, Direction::Import, "AS65001"),
+            "(ANY) AND (PeerAS)"
+        );
+        assert_eq!(compile(&attrs, Direction::Import, "AS65002"), "NOT ANY");
+    }
+
+    #[test]
+    fn as_expr_precedence() {
+        let matches = |peering: &str, peer_as: &str| {
+            let value = format!("from {peering} accept ANY");
+            let attrs = [("mp-import", value.as_str())];
+            compile(&attrs, Direction::Import, peer_as) == "ANY"
+        };
+        // AND binds more tightly than OR
+        assert!(matches("AS65001 OR AS65002 AND AS65003", "AS65001"));
+        assert!(!matches("AS65001 OR AS65002 AND AS65003", "AS65002"));
+        assert!(matches("AS65002 AND AS65003 OR AS65001", "AS65001"));
+        // EXCEPT binds as tightly as AND, and more tightly than OR
+        assert!(matches("AS65001 OR AS-PEERS EXCEPT AS65002", "AS65001"));
+        assert!(!matches("AS65001 OR AS-PEERS EXCEPT AS65002", "AS65002"));
+        assert!(matches("AS-PEERS EXCEPT AS65001 OR AS65001", "AS65001"));
+        // operators of equal precedence associate to the left
+        assert!(!matches("AS-ANY EXCEPT AS65002 EXCEPT AS65001", "AS65001"));
+        assert!(matches("AS-ANY EXCEPT AS65002 EXCEPT AS65001", "AS65003"));
+        assert!(!matches("AS-ANY EXCEPT AS65002 AND AS65001", "AS65002"));
+        // parentheses override precedence
+        assert!(matches("(AS65001 OR AS65002) AND AS-PEERS", "AS65002"));
+        assert!(!matches("(AS65001 OR AS65002) AND AS-PEERS", "AS65001"));
+        // router expressions following the AS expression are ignored
+        assert!(matches(
+            "AS65001 OR AS65002 AND AS65003 AT 192.0.2.1",
+            "AS65001"
+        ));
+    }
+}
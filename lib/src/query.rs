@@ -1,21 +1,34 @@
-use std::fmt::Display;
+use std::{fmt::Display, future::Future, hash::Hash, iter::once, mem, panic, thread};
 
 use ip::{Any, Prefix, PrefixSet};
 
-use irrc::{Connection, IrrClient, Query, ResponseItem};
-
 use rpsl::{
-    attr::{AttributeType, RpslAttribute},
     expr::{
         eval::{Evaluate, Evaluator, Resolver},
-        MpFilterExpr,
+        AsSetMember, MpFilterExpr,
     },
     names::{AsSet, AutNum, FilterSet, RouteSet},
-    obj::{RpslObject, RpslObjectClass},
     primitive::PeerAs,
 };
 
-use crate::error::Error;
+use tokio::runtime::{Builder, Handle, Runtime};
+
+use crate::{
+    batch::{self, BatchResults},
+    builder::EvaluatorBuilder,
+    client::Client,
+    error::Error,
+    expand::AsSetExpansion,
+    explain::{Explainer, Explanation, Lookup},
+    limits::{Budget, Limits},
+    lint::{LintReport, Linter},
+    member_of::{self, MemberOf},
+    origins::RouteObject,
+    policy::Direction,
+    report::Report,
+    serial::Serials,
+    table::{Entry, Names, Table},
+};
 
 /// An implementation of [`rpsl::expr::eval::Evaluator`] that resolves RPSL names using the IRRd
 /// query protocol.
@@ -34,33 +47,92 @@ use crate::error::Error;
 ///     .for_each(|range| println!("{range}"));
 /// # Ok::<_, Box<dyn std::error::Error>>(())
 /// ```
+///
+/// Queries are executed on a private, single-threaded `tokio` runtime. If [`RpslEvaluator`] is
+/// used from within an asynchronous context, that runtime is driven on a separate thread, and the
+/// calling thread is blocked until the queries are complete. Asynchronous code should use
+/// [`AsyncRpslEvaluator`] instead.
+///
+/// [`AsyncRpslEvaluator`]: crate::AsyncRpslEvaluator
 #[derive(Debug)]
 pub struct RpslEvaluator {
-    conn: Option<Connection>,
+    runtime: QueryRuntime,
+    client: Client,
+    peer_as: Option<AutNum>,
+    strict: bool,
+    report: Report,
+    context: Option<String>,
+    limits: Limits,
+    budget: Budget,
 }
 
 impl RpslEvaluator {
-    /// Construct a new [`Evaluator`].
+    /// Construct a new [`RpslEvaluator`], with a single connection to the IRRd server.
+    ///
+    /// Use [`RpslEvaluator::builder`] for more control over the evaluator configuration.
     ///
     /// # Errors
     ///
     /// An [`Error::Irr`] is returned if the connection to the IRRd server cannot be established.
-    #[tracing::instrument(level = "debug")]
+    ///
+    /// An [`Error::Runtime`] is returned if the underlying `tokio` runtime cannot be constructed.
     pub fn new(host: &str, port: u16) -> Result<Self, Error> {
-        let addr = format!("{host}:{port}");
-        let conn = IrrClient::new(addr).connect()?;
-        Ok(Self { conn: Some(conn) })
+        Self::builder(host, port).build()
+    }
+
+    /// Construct an [`EvaluatorBuilder`] for the IRRd server at `host:port`.
+    #[must_use]
+    pub fn builder(host: &str, port: u16) -> EvaluatorBuilder {
+        EvaluatorBuilder::new(host, port)
+    }
+
+    #[tracing::instrument(level = "debug")]
+    pub(crate) fn from_builder(builder: &EvaluatorBuilder) -> Result<Self, Error> {
+        let runtime = QueryRuntime::new()?;
+        let client = runtime.block_on(builder.client())?;
+        Ok(Self {
+            runtime,
+            client,
+            peer_as: None,
+            strict: builder.is_strict(),
+            report: Report::default(),
+            context: None,
+            limits: builder.evaluation_limits(),
+            budget: Budget::default(),
+        })
+    }
+
+    /// Reset the per-evaluation state before starting a new evaluation.
+    fn start(&mut self) {
+        self.report = Report::default();
+        self.budget = Budget::new(self.limits);
     }
 
-    fn with_connection<F, T, E>(&mut self, f: F) -> Result<T, Error>
+    /// Drive `future` to completion on the private runtime, subject to the evaluation deadline.
+    fn block_on<F, T>(&self, future: F) -> Result<T, Error>
     where
-        F: Fn(&mut Self, &mut Connection) -> Result<T, E>,
-        E: Into<Error>,
+        F: Future<Output = Result<T, Error>> + Send,
+        T: Send,
     {
-        let mut conn = self.conn.take().ok_or(Error::AcquireConnection)?;
-        let result = f(self, &mut conn).map_err(Into::into);
-        self.conn = Some(conn);
-        result
+        self.runtime.block_on(self.budget.run(future))
+    }
+
+    /// Fetch the contents of a single RPSL name, and collect any non-fatal errors encountered.
+    fn resolve_with<T, F, N>(&mut self, names: Names, name: &N, select: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Table) -> Option<Entry<T>>,
+        T: Default,
+        N: Display,
+    {
+        self.budget.check_deadline()?;
+        let mut table = self.block_on(self.client.resolve(names))?;
+        let Entry { output, errors } = select(&mut table).unwrap_or_default();
+        self.context = Some(name.to_string());
+        let result = errors
+            .into_iter()
+            .try_for_each(|err| self.collect_result::<(), _, Error>(Err(err)).map(|_| ()));
+        self.context = None;
+        result.map(|()| output)
     }
 
     /// Evaluate an RPSL expression.
@@ -80,7 +152,432 @@ impl RpslEvaluator {
         T: Evaluate<'a, Self> + Display,
     {
         tracing::info!("evaluating RPSL mp-filter expression '{expr}'");
-        <Self as Evaluator>::evaluate(self, expr)
+        self.start();
+        <Self as Evaluator>::evaluate(self, expr).map_err(Error::surface_limit)
+    }
+
+    /// Evaluate an RPSL expression in the context of a peering with `peer_as`.
+    ///
+    /// Any `PeerAS` token appearing in `expr` is substituted with `peer_as`, and resolved in the
+    /// same way as an `aut-num`. This allows a single generic expression to be re-used across
+    /// many peerings.
+    ///
+    /// # Errors
+    ///
+    /// See [`Evaluator`] for error handling details.
+    ///
+    /// # Examples
+    ///
+    /// ``` no_run
+    /// use bgpfu::RpslEvaluator;
+    /// use rpsl::expr::MpFilterExpr;
+    ///
+    /// let filter: MpFilterExpr = "PeerAS OR AS-FOO".parse()?;
+    /// let mut evaluator = RpslEvaluator::new("whois.radb.net", 43)?;
+    /// for peer_as in ["AS65000", "AS65001"] {
+    ///     let set = evaluator.evaluate_for_peer(filter.clone(), peer_as.parse()?)?;
+    ///     println!("{peer_as}: {set:?}");
+    /// }
+    /// # Ok::<_, Box<dyn std::error::Error>>(())
+    /// ```
+    #[tracing::instrument(skip(self, expr), fields(%expr), level = "debug")]
+    pub fn evaluate_for_peer<'a, T>(
+        &mut self,
+        expr: T,
+        peer_as: AutNum,
+    ) -> Result<<Self as Evaluator<'a>>::Output<T>, <Self as Evaluator<'a>>::Error>
+    where
+        T: Evaluate<'a, Self> + Display,
+    {
+        tracing::info!("evaluating RPSL mp-filter expression '{expr}' for peer {peer_as}");
+        self.start();
+        let outer = self.peer_as.replace(peer_as);
+        let result = <Self as Evaluator>::evaluate(self, expr).map_err(Error::surface_limit);
+        self.peer_as = outer;
+        result
+    }
+
+    /// Evaluate an RPSL expression, returning a [`Report`] of the non-fatal errors encountered
+    /// alongside the output.
+    ///
+    /// # Errors
+    ///
+    /// See [`Evaluator`] for error handling details.
+    pub fn evaluate_with_report<'a, T>(
+        &mut self,
+        expr: T,
+    ) -> Result<(<Self as Evaluator<'a>>::Output<T>, Report), Error>
+    where
+        T: Evaluate<'a, Self> + Display,
+    {
+        let output = self.evaluate(expr)?;
+        Ok((output, mem::take(&mut self.report)))
+    }
+
+    /// Evaluate an RPSL expression in the context of a peering with `peer_as`, returning a
+    /// [`Report`] of the non-fatal errors encountered alongside the output.
+    ///
+    /// See [`RpslEvaluator::evaluate_for_peer`].
+    ///
+    /// # Errors
+    ///
+    /// See [`Evaluator`] for error handling details.
+    pub fn evaluate_for_peer_with_report<'a, T>(
+        &mut self,
+        expr: T,
+        peer_as: AutNum,
+    ) -> Result<(<Self as Evaluator<'a>>::Output<T>, Report), Error>
+    where
+        T: Evaluate<'a, Self> + Display,
+    {
+        let output = self.evaluate_for_peer(expr, peer_as)?;
+        Ok((output, mem::take(&mut self.report)))
+    }
+
+    /// Get the current serials of the IRR databases being queried.
+    ///
+    /// These are the sources configured using
+    /// [`EvaluatorBuilder::sources`][crate::EvaluatorBuilder::sources], or every source known to
+    /// the server if none were configured. See [`Serials`] for details.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::Irr`] is returned if the connection to the IRRd server fails, or if the
+    /// server returns an error response.
+    #[tracing::instrument(skip(self), level = "debug")]
+    pub fn serials(&self) -> Result<Serials, Error> {
+        self.runtime.block_on(self.client.serials())
+    }
+
+    /// Find the `as-set`s that contain `member`, which may be either an `aut-num` or an
+    /// `as-set`.
+    ///
+    /// An `as-set` contains `member` if it lists it in its `members` attribute, as found using
+    /// an inverse query, or if `member` is an `aut-num` whose `member-of` attribute names the
+    /// `as-set`, and the `as-set` admits it via its `mbrs-by-ref` attribute.
+    ///
+    /// If `recursive` is `true`, the `as-set`s that contain those found are looked up in turn,
+    /// until no more are found. See [`MemberOf`] for details.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::Irr`] is returned if the connection to the IRRd server fails, or if the
+    /// server rejects the inverse query.
+    #[tracing::instrument(skip(self), fields(%member), level = "debug")]
+    pub fn member_of(&self, member: &AsSetMember, recursive: bool) -> Result<Vec<MemberOf>, Error> {
+        tracing::info!("looking up the as-sets containing {member}");
+        self.runtime
+            .block_on(member_of::lookup(&self.client, member, recursive))
+    }
+
+    /// Find the `route` and `route6` objects for `prefix`, along with their origins and
+    /// sources.
+    ///
+    /// If `less_specific` is `true`, the objects for every prefix covering `prefix` are also
+    /// found, and if `more_specific` is `true`, those for every prefix covered by `prefix`. Each
+    /// configured source is searched. See [`RouteObject`] for details.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::Irr`] is returned if the connection to the IRRd server fails, or if the
+    /// server returns an error response.
+    #[tracing::instrument(skip(self), fields(%prefix), level = "debug")]
+    pub fn origins(
+        &self,
+        prefix: Prefix<Any>,
+        less_specific: bool,
+        more_specific: bool,
+    ) -> Result<Vec<RouteObject>, Error> {
+        tracing::info!("looking up the route objects for {prefix}");
+        self.runtime.block_on(
+            self.client
+                .route_objects(prefix, less_specific, more_specific),
+        )
+    }
+
+    /// Check `as_set` and the `as-set`s nested within it for common problems, such as loops,
+    /// missing members, and `aut-num`s without `route` objects.
+    ///
+    /// See [`Linter`] for the checks made, and how to configure them.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::AsSetNotFound`] is returned if `as_set` does not exist, and an [`Error::Irr`]
+    /// if the connection to the IRRd server fails.
+    #[tracing::instrument(skip(self, linter), fields(%as_set), level = "debug")]
+    pub fn lint(&self, as_set: &AsSet, linter: &Linter) -> Result<LintReport, Error> {
+        tracing::info!("linting {as_set}");
+        self.runtime.block_on(linter.lint(&self.client, as_set))
+    }
+
+    /// Evaluate many RPSL `mp-filter` expressions together, returning the output and [`Report`]
+    /// of each under the key with which it was given.
+    ///
+    /// See [`AsyncRpslEvaluator::evaluate_batch`][crate::AsyncRpslEvaluator::evaluate_batch].
+    ///
+    /// # Errors
+    ///
+    /// See [`AsyncRpslEvaluator::evaluate_batch`][crate::AsyncRpslEvaluator::evaluate_batch].
+    pub fn evaluate_batch<K, I>(&mut self, exprs: I) -> Result<BatchResults<K>, Error>
+    where
+        I: IntoIterator<Item = (K, MpFilterExpr)>,
+        K: Eq + Hash + Send,
+    {
+        let exprs: Vec<_> = exprs.into_iter().collect();
+        self.runtime.block_on(batch::evaluate(
+            &self.client,
+            exprs,
+            None,
+            self.strict,
+            self.limits,
+        ))
+    }
+
+    /// Evaluate many RPSL `mp-filter` expressions together, in the context of a peering with
+    /// `peer_as`.
+    ///
+    /// See [`AsyncRpslEvaluator::evaluate_batch`][crate::AsyncRpslEvaluator::evaluate_batch].
+    ///
+    /// # Errors
+    ///
+    /// See [`AsyncRpslEvaluator::evaluate_batch`][crate::AsyncRpslEvaluator::evaluate_batch].
+    pub fn evaluate_batch_for_peer<K, I>(
+        &mut self,
+        exprs: I,
+        peer_as: AutNum,
+    ) -> Result<BatchResults<K>, Error>
+    where
+        I: IntoIterator<Item = (K, MpFilterExpr)>,
+        K: Eq + Hash + Send,
+    {
+        let exprs: Vec<_> = exprs.into_iter().collect();
+        self.runtime.block_on(batch::evaluate(
+            &self.client,
+            exprs,
+            Some(peer_as),
+            self.strict,
+            self.limits,
+        ))
+    }
+
+    /// Evaluate an RPSL `mp-filter` expression, and explain how each of the routes and
+    /// `route-set` members that contributed to the output was reached.
+    ///
+    /// Explaining an evaluation requires additional queries for the direct members of each
+    /// `as-set`, and for the `route` or `route6` object of each contributing route.
+    ///
+    /// # Errors
+    ///
+    /// See [`Evaluator`] for error handling details.
+    #[tracing::instrument(skip(self, expr), fields(%expr), level = "debug")]
+    pub fn explain(&mut self, expr: MpFilterExpr) -> Result<Explanation, Error> {
+        tracing::info!("explaining RPSL mp-filter expression '{expr}'");
+        self.start();
+        let peer_as = self.peer_as;
+        let explanation = Explainer::new(self, peer_as)
+            .explain(expr)
+            .map_err(Error::surface_limit)?;
+        Ok(explanation.with_report(mem::take(&mut self.report)))
+    }
+
+    /// Evaluate and explain an RPSL `mp-filter` expression in the context of a peering with
+    /// `peer_as`.
+    ///
+    /// See [`RpslEvaluator::explain`] and [`RpslEvaluator::evaluate_for_peer`].
+    ///
+    /// # Errors
+    ///
+    /// See [`Evaluator`] for error handling details.
+    pub fn explain_for_peer(
+        &mut self,
+        expr: MpFilterExpr,
+        peer_as: AutNum,
+    ) -> Result<Explanation, Error> {
+        let outer = self.peer_as.replace(peer_as);
+        let result = self.explain(expr);
+        self.peer_as = outer;
+        result
+    }
+}
+
+impl RpslEvaluator {
+    /// Expand `as_set` recursively into the autonomous systems that it contains.
+    ///
+    /// Nested `as-set`s are expanded by the IRRd server, unless [`Limits::max_depth`] or
+    /// [`Limits::max_autnums`] is configured, in which case `as_set` is walked one level of
+    /// nesting at a time.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::AsSetNotFound`] is returned if `as_set` does not exist, and the corresponding
+    /// [`Error`] variant is returned if any of the configured [`Limits`] is exceeded.
+    #[tracing::instrument(skip(self), fields(%as_set), level = "debug")]
+    pub fn expand_as_set(&mut self, as_set: &AsSet) -> Result<AsSetExpansion, Error> {
+        tracing::info!("expanding {as_set}");
+        self.start();
+        self.expand_as_set_inner(as_set)
+    }
+
+    /// Expand `as_set` recursively, within the budget of the evaluation in progress.
+    fn expand_as_set_inner(&mut self, as_set: &AsSet) -> Result<AsSetExpansion, Error> {
+        let autnums = match self.budget.expansion(as_set) {
+            Some(expansion) => self
+                .block_on(self.client.walk(expansion))?
+                .into_expansion(false)?
+                .into_autnums(),
+            None => self.block_on(self.client.as_set_autnums(as_set))?,
+        };
+        self.budget.add_autnums(autnums.iter().copied())?;
+        Ok(AsSetExpansion::new(as_set.clone(), autnums, None))
+    }
+
+    /// Expand `as_set` recursively, along with the tree of its members.
+    ///
+    /// Building the tree requires `as_set` to be walked one level of nesting at a time, using a
+    /// round of queries for the direct members of the `as-set`s at each level.
+    ///
+    /// # Errors
+    ///
+    /// See [`RpslEvaluator::expand_as_set`].
+    #[tracing::instrument(skip(self), fields(%as_set), level = "debug")]
+    pub fn expand_as_set_with_tree(&mut self, as_set: &AsSet) -> Result<AsSetExpansion, Error> {
+        tracing::info!("expanding {as_set}");
+        self.start();
+        let expansion = self
+            .block_on(self.client.walk(self.budget.walk(as_set)))?
+            .into_expansion(true)?;
+        self.budget
+            .add_autnums(expansion.autnums().iter().copied())?;
+        Ok(expansion)
+    }
+}
+
+impl RpslEvaluator {
+    /// Compile the routing policy that `autnum` publishes for its peering with `peer_as` into an
+    /// `mp-filter` expression.
+    ///
+    /// The `mp-import` and `import` attributes of the `aut-num` object are used for
+    /// [`Direction::Import`], and the `mp-export` and `export` attributes for
+    /// [`Direction::Export`]. The filters of every policy factor whose peering expression matches
+    /// `peer_as` are combined, and restricted to the address families to which they apply.
+    /// Actions are ignored.
+    ///
+    /// The resulting expression should be evaluated in the context of the peering, using
+    /// [`RpslEvaluator::evaluate_for_peer`], so that any `PeerAS` token is substituted. If no
+    /// policy factor matches `peer_as`, the expression matches nothing.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::AutNumNotFound`] is returned if `autnum` does not exist, and an
+    /// [`Error::Policy`] is returned if the policy cannot be compiled, such as when a peering is
+    /// described using a `peering-set`.
+    ///
+    /// # Examples
+    ///
+    /// ``` no_run
+    /// use bgpfu::{Direction, RpslEvaluator};
+    ///
+    /// let mut evaluator = RpslEvaluator::new("whois.radb.net", 43)?;
+    /// let peer_as = "AS65001".parse()?;
+    /// let expr = evaluator.peer_policy("AS65000".parse()?, peer_as, Direction::Import)?;
+    /// let set = evaluator.evaluate_for_peer(expr, peer_as)?;
+    /// # Ok::<_, Box<dyn std::error::Error>>(())
+    /// ```
+    #[tracing::instrument(skip(self), fields(%autnum, %peer_as, %direction), level = "debug")]
+    pub fn peer_policy(
+        &mut self,
+        autnum: AutNum,
+        peer_as: AutNum,
+        direction: Direction,
+    ) -> Result<MpFilterExpr, Error> {
+        tracing::info!("compiling the {direction} policy of {autnum} for peer {peer_as}");
+        self.start();
+        let policy = self.block_on(self.client.autnum_policy(autnum))?;
+        policy.compile(direction, peer_as, |as_set| {
+            self.expand_as_set_inner(as_set)
+                .map(AsSetExpansion::into_autnums)
+        })
+    }
+}
+
+impl Lookup for RpslEvaluator {
+    fn as_set_members(
+        &mut self,
+        as_sets: &[AsSet],
+    ) -> Result<Vec<Option<Vec<AsSetMember>>>, Error> {
+        self.block_on(self.client.as_set_members(as_sets))
+    }
+
+    fn originated_routes(&mut self, autnums: &[AutNum]) -> Result<Vec<PrefixSet<Any>>, Error> {
+        let names = Names {
+            autnums: autnums.iter().copied().collect(),
+            ..Names::default()
+        };
+        let mut table = self.block_on(self.client.resolve(names))?;
+        Ok(autnums
+            .iter()
+            .map(|autnum| {
+                table
+                    .autnums
+                    .remove(autnum)
+                    .map(|entry| entry.output)
+                    .unwrap_or_default()
+            })
+            .collect())
+    }
+
+    fn route_sources(
+        &mut self,
+        routes: &[(Prefix<Any>, AutNum)],
+    ) -> Result<Vec<Option<String>>, Error> {
+        self.block_on(self.client.route_sources(routes))
+    }
+}
+
+/// The private `tokio` runtime on which an [`RpslEvaluator`] executes its queries.
+///
+/// A runtime cannot be driven, or dropped, from within another asynchronous context. In that
+/// case, futures are instead driven on a scoped thread, and the runtime is shut down in the
+/// background.
+#[derive(Debug)]
+struct QueryRuntime(Option<Runtime>);
+
+impl QueryRuntime {
+    fn new() -> Result<Self, Error> {
+        Builder::new_current_thread()
+            .enable_io()
+            .enable_time()
+            .build()
+            .map(|runtime| Self(Some(runtime)))
+            .map_err(Error::Runtime)
+    }
+
+    fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: Future + Send,
+        F::Output: Send,
+    {
+        let runtime = self
+            .0
+            .as_ref()
+            .expect("the runtime is only taken when being dropped");
+        if Handle::try_current().is_ok() {
+            tracing::debug!("driving queries on a separate thread from within an async context");
+            thread::scope(|scope| scope.spawn(|| runtime.block_on(future)).join())
+                .unwrap_or_else(|payload| panic::resume_unwind(payload))
+        } else {
+            runtime.block_on(future)
+        }
+    }
+}
+
+impl Drop for QueryRuntime {
+    fn drop(&mut self) {
+        if Handle::try_current().is_ok() {
+            if let Some(runtime) = self.0.take() {
+                runtime.shutdown_background();
+            }
+        }
     }
 }
 
@@ -99,63 +596,33 @@ impl<'a> Evaluator<'a> for RpslEvaluator {
     }
 
     fn sink_error(&mut self, err: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
-        if let Some(irrc::Error::ResponseErr(
-            Query::Ipv4Routes(_) | Query::Ipv6Routes(_),
-            irrc::error::Response::KeyNotFound,
-        )) = err.downcast_ref()
-        {
-            tracing::debug!("{err:#}");
-        } else {
-            tracing::warn!("{err:#}");
+        if self.strict {
+            return false;
         }
+        log_sunk_error(err);
+        self.report.record(self.context.as_deref(), err);
         true
     }
 }
 
+/// Log an error that has been sunk during evaluation.
+pub(crate) fn log_sunk_error(err: &(dyn std::error::Error + Send + Sync + 'static)) {
+    tracing::warn!("{err:#}");
+}
+
 impl Resolver<'_, FilterSet, MpFilterExpr> for RpslEvaluator {
     type IError = Error;
 
     #[tracing::instrument(skip(self), level = "debug")]
     fn resolve(&mut self, filter_set: &FilterSet) -> Result<MpFilterExpr, Self::IError> {
-        self.with_connection(|this, conn| {
-            conn.pipeline()
-                // TODO: this is a bad API - we should be able to determine the required object
-                // class from the type of `filter_set`.
-                .push(Query::RpslObject(
-                    irrc::RpslObjectClass::FilterSet,
-                    filter_set.to_string(),
-                ))
-                .map_err(Error::from)
-                .and_then(|pipeline| {
-                    pipeline
-                        .responses()
-                        .find_map(|resp| {
-                            this.collect_result(resp.map_err(Error::from).and_then(|item| {
-                                let obj = item.into_content();
-                                if let RpslObject::FilterSet(ref filter_set_obj) = obj {
-                                    filter_set_obj
-                                        .attrs()
-                                        .into_iter()
-                                        .find_map(|attr| {
-                                            if let RpslAttribute::MpFilter(expr) = attr {
-                                                // TODO: shouldn't need to clone here either!
-                                                Some(expr.clone())
-                                            } else {
-                                                None
-                                            }
-                                        })
-                                        .ok_or_else(|| {
-                                            Error::FindAttribute(AttributeType::MpFilter, obj)
-                                        })
-                                } else {
-                                    Err(Error::RpslObjectClass(obj))
-                                }
-                            }))
-                            .transpose()
-                        })
-                        .unwrap_or_else(|| Ok("NOT ANY".parse()?))
-                })
-        })
+        let names = Names {
+            filter_sets: once(filter_set.clone()).collect(),
+            ..Names::default()
+        };
+        self.resolve_with(names, filter_set, |table| {
+            table.filter_sets.remove(filter_set)
+        })?
+        .map_or_else(|| Ok("NOT ANY".parse()?), Ok)
     }
 }
 
@@ -164,27 +631,17 @@ impl Resolver<'_, AsSet, PrefixSet<Any>> for RpslEvaluator {
 
     #[tracing::instrument(skip(self), fields(%as_set), level = "debug")]
     fn resolve(&mut self, as_set: &AsSet) -> Result<PrefixSet<Any>, Self::IError> {
-        self.with_connection(|this, conn| {
-            // TODO: shouldn't need to clone here
-            conn.pipeline_from_initial(Query::AsSetMembersRecursive(as_set.clone()), |resp| {
-                this.collect_result::<_, _, Error>(resp.map(|item| {
-                    let autnum = item.into_content();
-                    [Query::Ipv4Routes(autnum), Query::Ipv6Routes(autnum)]
-                }))
-                // TODO: we want a way of providing our own error handling closure
-                .unwrap_or_else(|err| {
-                    _ = this.sink_error(&err);
-                    None
-                })
-            })
-            .and_then(|mut pipeline| {
-                this.collect_results(
-                    pipeline
-                        .responses::<'_, Prefix<Any>>()
-                        .map(|resp| resp.map(ResponseItem::into_content)),
-                )
-            })
-        })
+        if let Some(expansion) = self.budget.expansion(as_set) {
+            let expansion = self.block_on(self.client.walk(expansion))?;
+            self.budget.add_autnums(expansion.into_autnums())?;
+        }
+        let names = Names {
+            as_sets: once(as_set.clone()).collect(),
+            ..Names::default()
+        };
+        let set = self.resolve_with(names, as_set, |table| table.as_sets.remove(as_set))?;
+        self.budget.add_prefixes(as_set, &set)?;
+        Ok(set)
     }
 }
 
@@ -193,19 +650,14 @@ impl Resolver<'_, RouteSet, PrefixSet<Any>> for RpslEvaluator {
 
     #[tracing::instrument(skip(self), level = "debug")]
     fn resolve(&mut self, route_set: &RouteSet) -> Result<PrefixSet<Any>, Self::IError> {
-        self.with_connection(|this, conn| {
-            conn.pipeline()
-                // TODO: shouldn't need to clone here
-                .push(Query::RouteSetMembersRecursive(route_set.clone()))
-                .map_err(Error::from)
-                .and_then(|pipeline| {
-                    this.collect_results(
-                        pipeline
-                            .responses::<'_, Prefix<Any>>()
-                            .map(|response| response.map(ResponseItem::into_content)),
-                    )
-                })
-        })
+        let names = Names {
+            route_sets: once(route_set.clone()).collect(),
+            ..Names::default()
+        };
+        let set =
+            self.resolve_with(names, route_set, |table| table.route_sets.remove(route_set))?;
+        self.budget.add_prefixes(route_set, &set)?;
+        Ok(set)
     }
 }
 
@@ -214,19 +666,14 @@ impl Resolver<'_, AutNum, PrefixSet<Any>> for RpslEvaluator {
 
     #[tracing::instrument(skip(self), fields(%autnum), level = "debug")]
     fn resolve(&mut self, autnum: &AutNum) -> Result<PrefixSet<Any>, Self::IError> {
-        self.with_connection(|this, conn| {
-            conn.pipeline()
-                .push(Query::Ipv4Routes(*autnum))?
-                .push(Query::Ipv6Routes(*autnum))
-                .map_err(Error::from)
-                .and_then(|pipeline| {
-                    this.collect_results(
-                        pipeline
-                            .responses::<'_, Prefix<Any>>()
-                            .map(|response| response.map(ResponseItem::into_content)),
-                    )
-                })
-        })
+        self.budget.add_autnums(once(*autnum))?;
+        let names = Names {
+            autnums: once(*autnum).collect(),
+            ..Names::default()
+        };
+        let set = self.resolve_with(names, autnum, |table| table.autnums.remove(autnum))?;
+        self.budget.add_prefixes(autnum, &set)?;
+        Ok(set)
     }
 }
 
@@ -235,6 +682,71 @@ impl Resolver<'_, PeerAs, PrefixSet<Any>> for RpslEvaluator {
 
     #[tracing::instrument(skip(self), level = "debug")]
     fn resolve(&mut self, _: &PeerAs) -> Result<PrefixSet<Any>, Self::IError> {
-        unimplemented!()
+        let autnum = self.peer_as.ok_or(Error::PeerAs)?;
+        tracing::debug!("substituting {autnum} for 'PeerAS'");
+        <Self as Resolver<'_, AutNum, PrefixSet<Any>>>::resolve(self, &autnum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use tokio::net::TcpListener;
+
+    use super::QueryRuntime;
+    use crate::{client::tests::serve, Direction, Error, EvaluatorBuilder, Limits};
+
+    #[test]
+    fn block_on_outside_async_context() {
+        let runtime = QueryRuntime::new().unwrap();
+        assert_eq!(runtime.block_on(async { 42 }), 42);
+    }
+
+    #[tokio::test]
+    async fn block_on_within_async_context() {
+        // the runtime is also dropped within the async context
+        let output = QueryRuntime::new().unwrap().block_on(async {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            42
+        });
+        assert_eq!(output, 42);
+    }
+
+    #[test]
+    fn peer_policy_budget() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let responses = [
+            (
+                "!maut-num,AS65000",
+                "aut-num: AS65000\n\
+                 mp-import: from AS-FOO accept ANY\n\
+                 mp-import: from AS-BAR accept ANY",
+            ),
+            ("!iAS-FOO", "AS65001 AS65002"),
+            ("!iAS-BAR", "AS65003"),
+        ];
+        drop(thread::spawn(move || {
+            QueryRuntime::new().unwrap().block_on(async {
+                let listener = TcpListener::from_std(listener).unwrap();
+                serve(listener, responses.into_iter().collect()).await;
+            });
+        }));
+        let mut evaluator = EvaluatorBuilder::new("127.0.0.1", port)
+            .limits(Limits::default().max_autnums(2))
+            .build()
+            .unwrap();
+        // each `as-set` is within the limit, but not both together
+        for as_set in ["AS-FOO", "AS-BAR"] {
+            assert!(evaluator.expand_as_set(&as_set.parse().unwrap()).is_ok());
+        }
+        let policy = evaluator.peer_policy(
+            "AS65000".parse().unwrap(),
+            "AS65003".parse().unwrap(),
+            Direction::Import,
+        );
+        assert!(matches!(policy, Err(Error::AutNumLimit(2))));
     }
 }
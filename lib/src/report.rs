@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+
+use crate::{error::Error, irrd};
+
+/// The non-fatal errors encountered during an evaluation, keyed by the RPSL name involved.
+///
+/// Errors that cannot be attributed to any particular name, such as a failure to apply a range
+/// operator, are recorded without a name.
+///
+/// # Examples
+///
+/// ``` no_run
+/// use bgpfu::RpslEvaluator;
+/// use rpsl::expr::MpFilterExpr;
+///
+/// let filter: MpFilterExpr = "AS-FOO OR AS-BAR".parse()?;
+/// let (set, report) = RpslEvaluator::new("whois.radb.net", 43)?.evaluate_with_report(filter)?;
+/// for (name, error) in report.errors() {
+///     eprintln!("{}: {error}", name.unwrap_or("<expression>"));
+/// }
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    errors: BTreeMap<Option<String>, Vec<String>>,
+}
+
+impl Report {
+    /// Record `err`, attributing it to the RPSL name involved if it can be determined, or to
+    /// `context` otherwise.
+    pub(crate) fn record(
+        &mut self,
+        context: Option<&str>,
+        err: &(dyn std::error::Error + Send + Sync + 'static),
+    ) {
+        let name = name_of(err).or_else(|| context.map(str::to_owned));
+        let message = format!("{err:#}");
+        let messages = self.errors.entry(name).or_default();
+        // the same error may be encountered during several evaluation attempts
+        if !messages.contains(&message) {
+            messages.push(message);
+        }
+    }
+
+    /// Returns `true` if no errors were encountered.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Get the total number of errors encountered.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.errors.values().map(Vec::len).sum()
+    }
+
+    /// Iterate over the RPSL names for which errors were encountered.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.errors.keys().filter_map(Option::as_deref)
+    }
+
+    /// Get the errors encountered for the RPSL name `name`.
+    #[must_use]
+    pub fn get(&self, name: &str) -> &[String] {
+        self.errors
+            .get(&Some(name.to_owned()))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Iterate over all errors, along with the RPSL name involved, if any.
+    pub fn errors(&self) -> impl Iterator<Item = (Option<&str>, &str)> {
+        self.errors.iter().flat_map(|(name, messages)| {
+            messages
+                .iter()
+                .map(move |message| (name.as_deref(), message.as_str()))
+        })
+    }
+}
+
+/// Determine the RPSL name involved in `err`, if possible.
+fn name_of(err: &(dyn std::error::Error + Send + Sync + 'static)) -> Option<String> {
+    let irr_err = err.downcast_ref::<irrd::Error>().or_else(|| {
+        if let Some(Error::Irr(irr_err)) = err.downcast_ref() {
+            Some(irr_err)
+        } else {
+            None
+        }
+    });
+    match irr_err {
+        Some(
+            irrd::Error::ResponseErr(query, _)
+            | irrd::Error::Malformed(query, _)
+            | irrd::Error::ParseItem { query, .. },
+        ) => query.name(),
+        Some(irrd::Error::Io(_)) => None,
+        None => match err.downcast_ref::<Error>()? {
+            Error::ObjectNotFound(_, name) => Some(name.clone()),
+            Error::AsSetNotFound(as_set) => Some(as_set.to_string()),
+            Error::AutNumNotFound(autnum) => Some(autnum.to_string()),
+            Error::PeerAs => Some("PeerAS".to_owned()),
+            Error::Shared(err) => name_of(err.as_ref()),
+            _ => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::irrd::{Query, ResponseError};
+
+    #[test]
+    fn keyed_by_name() {
+        let mut report = Report::default();
+        let not_found = Error::Irr(irrd::Error::ResponseErr(
+            Query::AsSetMembersRecursive("AS-FOO".parse().unwrap()),
+            ResponseError::KeyNotFound,
+        ));
+        report.record(None, &not_found);
+        report.record(Some("AS-BAR"), &not_found);
+        report.record(Some("FLTR-FOO"), &Error::AcquireConnection);
+        report.record(None, &Error::AcquireConnection);
+        report.record(None, &Error::Shared(Arc::new(Error::PeerAs)));
+        assert_eq!(report.len(), 4);
+        assert_eq!(
+            report.names().collect::<Vec<_>>(),
+            ["AS-FOO", "FLTR-FOO", "PeerAS"]
+        );
+        assert_eq!(report.get("AS-FOO").len(), 1);
+        assert!(report.get("AS-BAR").is_empty());
+        assert_eq!(
+            report.errors().filter(|(name, _)| name.is_none()).count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Read},
+    iter::successors,
+    path::Path,
+    sync::Arc,
+};
+
+use ip::{
+    traits::{Prefix as _, PrefixSet as _},
+    Any, Prefix, PrefixSet,
+};
+
+use rpsl::names::AutNum;
+
+use serde::Deserialize;
+
+use crate::error::Error;
+
+/// RPKI route origin validation (ROV) of resolved routes, using a set of validated ROA payloads
+/// (VRPs).
+///
+/// VRPs are loaded from the JSON export formats of `rpki-client` (`-j`) and Routinator
+/// (`--format json` or `jsonext`). Each route originated by an `aut-num` is validated against
+/// the VRPs as described in [RFC6811], and routes that are RPKI-invalid are handled according
+/// to the configured [`RovAction`].
+///
+/// Because validation requires the origin of each route, only the routes resolved from `aut-num`
+/// and `as-set` names are validated. Prefixes that are members of a `route-set` are not.
+///
+/// [`Rov`] is cheap to clone, and clones share the same underlying VRPs.
+///
+/// [RFC6811]: https://datatracker.ietf.org/doc/html/rfc6811
+///
+/// # Examples
+///
+/// ``` no_run
+/// use bgpfu::{Rov, RovAction, RpslEvaluator};
+///
+/// let rov = Rov::load("/var/lib/rpki-client/json")?.action(RovAction::Drop);
+/// let evaluator = RpslEvaluator::builder("whois.radb.net", 43)
+///     .rov(rov)
+///     .build()?;
+/// # Ok::<_, bgpfu::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Rov {
+    vrps: Arc<HashMap<Prefix<Any>, Vec<Vrp>>>,
+    action: RovAction,
+}
+
+/// The action to take for routes that are RPKI-invalid.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum RovAction {
+    /// Remove RPKI-invalid routes from the evaluation output.
+    #[default]
+    Drop,
+    /// Retain RPKI-invalid routes, but log a warning for each.
+    Flag,
+}
+
+/// The RPKI validation state of a route. See [RFC6811].
+///
+/// [RFC6811]: https://datatracker.ietf.org/doc/html/rfc6811#section-2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Validity {
+    /// At least one VRP matches the route.
+    Valid,
+    /// At least one VRP covers the route, but none match it.
+    Invalid,
+    /// No VRP covers the route.
+    NotFound,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Vrp {
+    /// The authorized origin, or `None` for an AS0 VRP, which never matches any route.
+    origin: Option<AutNum>,
+    max_length: u8,
+}
+
+#[derive(Debug, Deserialize)]
+struct Export {
+    roas: Vec<Roa>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Roa {
+    asn: Asn,
+    prefix: String,
+    #[serde(rename = "maxLength")]
+    max_length: u8,
+}
+
+/// `rpki-client` exports origin ASNs as integers, and Routinator as `AS`-prefixed strings.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Asn {
+    Integer(u32),
+    String(String),
+}
+
+impl Rov {
+    /// Identify the VRPs and the configured action, so that routes validated using different
+    /// configurations are cached separately.
+    ///
+    /// Clones share the same identity.
+    pub(crate) fn id(&self) -> (usize, RovAction) {
+        (Arc::as_ptr(&self.vrps) as usize, self.action)
+    }
+
+    /// Load the VRPs exported to the JSON file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::ReadVrps`] is returned if the file cannot be read. See also [`Rov::from_json`].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        tracing::info!("loading validated ROA payloads from {}", path.display());
+        let file = File::open(path).map_err(|err| Error::ReadVrps(path.to_path_buf(), err))?;
+        Self::from_json(BufReader::new(file))
+    }
+
+    /// Read the VRPs from a JSON export.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::ParseVrps`] is returned if the JSON is malformed, or an [`Error::InvalidVrp`]
+    /// if any VRP has an invalid prefix or origin ASN.
+    pub fn from_json<R: Read>(reader: R) -> Result<Self, Error> {
+        let export: Export = serde_json::from_reader(reader).map_err(Error::ParseVrps)?;
+        let as0: AutNum = "AS0".parse()?;
+        let mut vrps: HashMap<_, Vec<_>> = HashMap::new();
+        for roa in export.roas {
+            let origin: AutNum = match roa.asn {
+                Asn::Integer(asn) => format!("AS{asn}").parse(),
+                Asn::String(asn) => asn.parse(),
+            }
+            .map_err(|_| Error::InvalidVrp(roa.prefix.clone()))?;
+            let prefix = roa
+                .prefix
+                .parse()
+                .map_err(|_| Error::InvalidVrp(roa.prefix.clone()))?;
+            vrps.entry(prefix).or_default().push(Vrp {
+                origin: (origin != as0).then_some(origin),
+                max_length: roa.max_length,
+            });
+        }
+        tracing::info!("loaded {} validated ROA payload prefixes", vrps.len());
+        Ok(Self {
+            vrps: Arc::new(vrps),
+            action: RovAction::default(),
+        })
+    }
+
+    /// Set the action to take for routes that are RPKI-invalid. Defaults to
+    /// [`RovAction::Drop`].
+    #[must_use]
+    pub const fn action(mut self, action: RovAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    /// Get the RPKI validation state of the route for `prefix` originated by `origin`.
+    #[must_use]
+    pub fn validate(&self, prefix: Prefix<Any>, origin: AutNum) -> Validity {
+        let length = *prefix.prefix_len().as_ref();
+        let mut covered = false;
+        for covering in successors(Some(prefix), Prefix::<Any>::supernet) {
+            if let Some(vrps) = self.vrps.get(&covering) {
+                covered = true;
+                if vrps
+                    .iter()
+                    .any(|vrp| vrp.origin == Some(origin) && length <= vrp.max_length)
+                {
+                    return Validity::Valid;
+                }
+            }
+        }
+        if covered {
+            Validity::Invalid
+        } else {
+            Validity::NotFound
+        }
+    }
+
+    /// Apply the configured [`RovAction`] to the routes in `routes` originated by `origin`.
+    pub(crate) fn apply(&self, origin: AutNum, routes: &PrefixSet<Any>) -> PrefixSet<Any> {
+        routes
+            .prefixes()
+            .filter(|prefix| {
+                if self.validate(*prefix, origin) != Validity::Invalid {
+                    return true;
+                }
+                match self.action {
+                    RovAction::Drop => {
+                        tracing::info!(
+                            "dropping RPKI-invalid route {prefix} originated by {origin}"
+                        );
+                        false
+                    }
+                    RovAction::Flag => {
+                        tracing::warn!("route {prefix} originated by {origin} is RPKI-invalid");
+                        true
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RPKI_CLIENT: &str = r#"{
+        "metadata": {"buildtime": "2024-01-01T00:00:00Z", "vrps": 3},
+        "roas": [
+            {"asn": 65000, "prefix": "192.0.2.0/24", "maxLength": 24, "ta": "test", "expires": 0},
+            {"asn": 65001, "prefix": "2001:db8::/32", "maxLength": 48, "ta": "test", "expires": 0},
+            {"asn": 0, "prefix": "198.51.100.0/24", "maxLength": 32, "ta": "test", "expires": 0}
+        ]
+    }"#;
+
+    const ROUTINATOR: &str = r#"{
+        "metadata": {"generated": 0, "generatedTime": "2024-01-01T00:00:00Z"},
+        "roas": [
+            {"asn": "AS65000", "prefix": "192.0.2.0/24", "maxLength": 24, "ta": "test"}
+        ]
+    }"#;
+
+    fn validate(rov: &Rov, prefix: &str, origin: &str) -> Validity {
+        rov.validate(prefix.parse().unwrap(), origin.parse().unwrap())
+    }
+
+    #[test]
+    fn rpki_client_export() {
+        let rov = Rov::from_json(RPKI_CLIENT.as_bytes()).unwrap();
+        assert_eq!(validate(&rov, "192.0.2.0/24", "AS65000"), Validity::Valid);
+        assert_eq!(validate(&rov, "192.0.2.0/25", "AS65000"), Validity::Invalid);
+        assert_eq!(validate(&rov, "192.0.2.0/24", "AS65001"), Validity::Invalid);
+        assert_eq!(
+            validate(&rov, "2001:db8:1::/48", "AS65001"),
+            Validity::Valid
+        );
+        assert_eq!(validate(&rov, "198.51.100.0/24", "AS0"), Validity::Invalid);
+        assert_eq!(
+            validate(&rov, "203.0.113.0/24", "AS65000"),
+            Validity::NotFound
+        );
+    }
+
+    #[test]
+    fn routinator_export() {
+        let rov = Rov::from_json(ROUTINATOR.as_bytes()).unwrap();
+        assert_eq!(validate(&rov, "192.0.2.0/24", "AS65000"), Validity::Valid);
+        assert_eq!(validate(&rov, "192.0.2.0/24", "AS65001"), Validity::Invalid);
+    }
+}
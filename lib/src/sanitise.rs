@@ -0,0 +1,240 @@
+use ip::{
+    any::PrefixRange,
+    traits::{PrefixRange as _, PrefixSet as _},
+    Any, Prefix, PrefixSet,
+};
+
+/// IPv4 prefixes that should never appear in the global routing table. Each is removed along with
+/// all of its more-specific prefixes.
+const IPV4_BOGONS: &[&str] = &[
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.0.0.0/24",
+    "192.0.2.0/24",
+    "192.168.0.0/16",
+    "198.18.0.0/15",
+    "198.51.100.0/24",
+    "203.0.113.0/24",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+];
+
+/// IPv6 prefixes that should never appear in the global routing table. Each is removed along with
+/// all of its more-specific prefixes.
+const IPV6_BOGONS: &[&str] = &[
+    "::/8",
+    "100::/64",
+    "2001:2::/48",
+    "2001:10::/28",
+    "2001:db8::/32",
+    "2002::/16",
+    "3ffe::/16",
+    "fc00::/7",
+    "fe80::/10",
+    "fec0::/10",
+    "ff00::/8",
+];
+
+/// Default routes, which are removed without their more-specific prefixes.
+const DEFAULT_ROUTES: &[&str] = &["0.0.0.0/0", "::/0"];
+
+/// Sanitisation of evaluated prefix sets.
+///
+/// A [`Sanitiser`] removes bogon address space and prefixes that are more specific than the
+/// configured per-address-family maximum length from the output of an evaluation, and reports
+/// what was removed.
+///
+/// By default, the bogons are the IPv4 and IPv6 special-purpose, documentation and multicast
+/// ranges (including RFC1918 space) along with all of their more-specific prefixes, and both
+/// default routes. The default maximum lengths are `/24` for IPv4 and `/48` for IPv6.
+///
+/// # Examples
+///
+/// ``` no_run
+/// use bgpfu::{RpslEvaluator, Sanitiser};
+/// use rpsl::expr::MpFilterExpr;
+///
+/// let filter: MpFilterExpr = "AS-FOO".parse()?;
+/// let set = RpslEvaluator::new("whois.radb.net", 43)?.evaluate(filter)?;
+/// let (set, report) = Sanitiser::default().ipv6_max_length(40).sanitise(&set);
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Sanitiser {
+    bogons: PrefixSet<Any>,
+    ipv4_max_length: u8,
+    ipv6_max_length: u8,
+}
+
+/// The prefixes removed by a [`Sanitiser`].
+#[derive(Debug, Clone, Default)]
+pub struct SanitiseReport {
+    bogons: PrefixSet<Any>,
+    too_specific: PrefixSet<Any>,
+}
+
+impl Default for Sanitiser {
+    fn default() -> Self {
+        let bogons = IPV4_BOGONS
+            .iter()
+            .chain(IPV6_BOGONS)
+            .map(|bogon| parse_prefix(bogon).into())
+            .map(PrefixRange::or_longer)
+            .chain(
+                DEFAULT_ROUTES
+                    .iter()
+                    .map(|default| parse_prefix(default).into()),
+            )
+            .collect();
+        Self {
+            bogons,
+            ipv4_max_length: 24,
+            ipv6_max_length: 48,
+        }
+    }
+}
+
+/// Parse one of the built-in prefixes.
+fn parse_prefix(prefix: &str) -> Prefix<Any> {
+    prefix.parse().expect("built-in prefixes should be valid")
+}
+
+impl Sanitiser {
+    /// Replace the bogon address space with `bogons`.
+    ///
+    /// Each [`PrefixRange`] is removed exactly as given, so that, for example, `192.0.2.0/24`
+    /// alone does not cover `192.0.2.0/25`.
+    #[must_use]
+    pub fn bogons<I>(mut self, bogons: I) -> Self
+    where
+        I: IntoIterator<Item = PrefixRange>,
+    {
+        self.bogons = bogons.into_iter().collect();
+        self
+    }
+
+    /// Set the maximum length of IPv4 prefixes. Defaults to `24`.
+    ///
+    /// Lengths of `32` or greater retain all IPv4 prefixes.
+    #[must_use]
+    pub const fn ipv4_max_length(mut self, length: u8) -> Self {
+        self.ipv4_max_length = length;
+        self
+    }
+
+    /// Set the maximum length of IPv6 prefixes. Defaults to `48`.
+    ///
+    /// Lengths of `128` or greater retain all IPv6 prefixes.
+    #[must_use]
+    pub const fn ipv6_max_length(mut self, length: u8) -> Self {
+        self.ipv6_max_length = length;
+        self
+    }
+
+    /// Remove bogons and over-specific prefixes from `set`, returning the remaining prefixes and
+    /// a [`SanitiseReport`] of those removed.
+    #[must_use]
+    pub fn sanitise(&self, set: &PrefixSet<Any>) -> (PrefixSet<Any>, SanitiseReport) {
+        let bogons = set.clone() & self.bogons.clone();
+        let remaining = set.clone() - bogons.clone();
+        let sanitised = remaining.clone() & self.permitted_lengths();
+        let too_specific = remaining - sanitised.clone();
+        for range in bogons.ranges() {
+            tracing::info!("removing bogon prefixes {range}");
+        }
+        for range in too_specific.ranges() {
+            tracing::info!("removing over-specific prefixes {range}");
+        }
+        (
+            sanitised,
+            SanitiseReport {
+                bogons,
+                too_specific,
+            },
+        )
+    }
+
+    /// Construct the set of all prefixes no longer than the configured maximum lengths.
+    fn permitted_lengths(&self) -> PrefixSet<Any> {
+        DEFAULT_ROUTES
+            .iter()
+            .zip([self.ipv4_max_length, self.ipv6_max_length])
+            .map(|(default, max_length)| {
+                let range = PrefixRange::from(parse_prefix(default)).or_longer();
+                range
+                    .new_prefix_length(max_length)
+                    .ok()
+                    .and_then(|upper| range.clone().with_intersection(range.lower()..=upper))
+                    .unwrap_or(range)
+            })
+            .collect()
+    }
+}
+
+impl SanitiseReport {
+    /// Get the bogon prefixes that were removed.
+    #[must_use]
+    pub const fn bogons(&self) -> &PrefixSet<Any> {
+        &self.bogons
+    }
+
+    /// Get the prefixes that were removed for exceeding the maximum length.
+    #[must_use]
+    pub const fn too_specific(&self) -> &PrefixSet<Any> {
+        &self.too_specific
+    }
+
+    /// Returns `true` if no prefixes were removed.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.bogons.prefixes().next().is_none() && self.too_specific.prefixes().next().is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(ranges: &[&str]) -> PrefixSet<Any> {
+        ranges
+            .iter()
+            .map(|range| parse_prefix(range).into())
+            .map(PrefixRange::or_longer)
+            .collect()
+    }
+
+    fn prefixes(set: &PrefixSet<Any>) -> Vec<String> {
+        let mut prefixes: Vec<_> = set.ranges().map(|range| range.to_string()).collect();
+        prefixes.sort();
+        prefixes
+    }
+
+    #[test]
+    fn removes_bogons_and_clamps_lengths() {
+        let input = set(&["10.0.0.0/8", "193.0.0.0/22", "2001:db8::/32", "2a00::/44"])
+            | PrefixSet::<Any>::from_iter([parse_prefix("0.0.0.0/0")]);
+        let (output, report) = Sanitiser::default().sanitise(&input);
+        assert_eq!(prefixes(&output), ["193.0.0.0/22^22-24", "2a00::/44^44-48"]);
+        assert!(report.bogons().contains(parse_prefix("0.0.0.0/0")));
+        assert!(report.bogons().contains(parse_prefix("10.1.0.0/16")));
+        assert!(report.bogons().contains(parse_prefix("2001:db8:1::/48")));
+        assert!(!report.too_specific().contains(parse_prefix("193.0.0.0/24")));
+        assert!(report.too_specific().contains(parse_prefix("193.0.0.0/25")));
+        assert!(!report.is_empty());
+    }
+
+    #[test]
+    fn custom_configuration() {
+        let input = set(&["10.0.0.0/8"]);
+        let (output, report) = Sanitiser::default()
+            .bogons([])
+            .ipv4_max_length(32)
+            .sanitise(&input);
+        assert_eq!(prefixes(&output), ["10.0.0.0/8^8-32"]);
+        assert!(report.is_empty());
+    }
+}
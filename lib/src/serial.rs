@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+
+/// The current serial numbers of the IRR databases queried by an evaluator, as reported by the
+/// IRRd `!j` query.
+///
+/// The serial of a database increases whenever any of its objects change. Comparing the
+/// [`Serials`] taken before two evaluations therefore reveals whether their inputs may differ.
+///
+/// # Examples
+///
+/// ``` no_run
+/// use bgpfu::RpslEvaluator;
+///
+/// let evaluator = RpslEvaluator::new("whois.radb.net", 43)?;
+/// let before = evaluator.serials()?;
+/// // ...
+/// if evaluator.serials()?.unchanged_since(&before) {
+///     println!("no IRR database has changed");
+/// }
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Serials {
+    sources: BTreeMap<String, Option<u64>>,
+}
+
+impl Serials {
+    /// Parse the response to an IRRd `!j` query.
+    ///
+    /// Each line of the response has the form `SOURCE:MIRRORABLE:OLDEST-NEWEST`, optionally
+    /// followed by `:LAST-EXPORT`. The serial range is `-` for sources without serials.
+    pub(crate) fn parse(data: &str) -> Self {
+        let sources = data
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.trim().split(':');
+                let source = fields.next().filter(|source| !source.is_empty())?;
+                let serial = fields
+                    .nth(1)
+                    .and_then(|range| range.split_once('-'))
+                    .and_then(|(_, newest)| newest.parse().ok());
+                Some((source.to_ascii_uppercase(), serial))
+            })
+            .collect();
+        Self { sources }
+    }
+
+    /// Construct a [`Serials`] from each source and its serial, if known.
+    pub(crate) fn from_sources<I>(sources: I) -> Self
+    where
+        I: IntoIterator<Item = (String, Option<u64>)>,
+    {
+        Self {
+            sources: sources
+                .into_iter()
+                .map(|(source, serial)| (source.to_ascii_uppercase(), serial))
+                .collect(),
+        }
+    }
+
+    /// Get the current serial of `source`, or `None` if the source was not reported, or has no
+    /// serial.
+    #[must_use]
+    pub fn get(&self, source: &str) -> Option<u64> {
+        self.sources
+            .get(&source.to_ascii_uppercase())
+            .copied()
+            .flatten()
+    }
+
+    /// Iterate over the reported sources and their current serials, in alphabetical order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Option<u64>)> {
+        self.sources
+            .iter()
+            .map(|(source, serial)| (source.as_str(), *serial))
+    }
+
+    /// Returns `true` if at least one source was reported, and every reported source has a
+    /// serial.
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        !self.sources.is_empty() && self.sources.values().all(Option::is_some)
+    }
+
+    /// Returns `true` if no source can have changed since `previous` was taken.
+    ///
+    /// This is only the case if the same sources were reported, with the same serials, and
+    /// every source has a serial.
+    #[must_use]
+    pub fn unchanged_since(&self, previous: &Self) -> bool {
+        self.is_complete() && self == previous
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_response() {
+        let serials = Serials::parse("RADB:Y:1000-2000:1900\nripe:N:0-52\nALTDB:N:-\n");
+        assert_eq!(serials.get("RADB"), Some(2000));
+        assert_eq!(serials.get("RIPE"), Some(52));
+        assert_eq!(serials.get("ALTDB"), None);
+        assert_eq!(serials.iter().count(), 3);
+        assert!(!serials.is_complete());
+        assert!(!serials.unchanged_since(&serials));
+
+        let complete = Serials::parse("RADB:Y:1000-2000\n");
+        assert!(complete.unchanged_since(&complete.clone()));
+        assert!(!complete.unchanged_since(&Serials::parse("RADB:Y:1000-2001\n")));
+    }
+}
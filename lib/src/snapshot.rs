@@ -0,0 +1,277 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use ip::{
+    any,
+    traits::{Prefix as _, PrefixSet as _},
+    Any, PrefixSet,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{diff::Difference, error::Error, serial::Serials};
+
+/// The version of the snapshot format written by this version of the library.
+///
+/// This must be incremented whenever a change is made to [`Format`] that older versions cannot
+/// read.
+const VERSION: u64 = 1;
+
+/// A record of the result of evaluating an expression, that can be saved to disk and compared
+/// with later evaluations of the same expression.
+///
+/// A snapshot holds the expression evaluated, the time at which it was taken, the [`Serials`]
+/// of the IRR databases queried, and the evaluated prefix ranges of each address family.
+///
+/// Snapshots are stored as JSON, in a versioned format that future versions of this library will
+/// continue to read. Prefix ranges are written in address order, so that snapshots of identical
+/// results are identical apart from their timestamps.
+///
+/// # Examples
+///
+/// ``` no_run
+/// use bgpfu::{RpslEvaluator, Snapshot};
+/// use ip::traits::PrefixSet as _;
+/// use rpsl::expr::MpFilterExpr;
+///
+/// let mut evaluator = RpslEvaluator::new("whois.radb.net", 43)?;
+/// let filter: MpFilterExpr = "AS-FOO".parse()?;
+/// let serials = evaluator.serials()?;
+/// let set = evaluator.evaluate(filter.clone())?;
+/// let snapshot = Snapshot::new(&filter, set, serials);
+///
+/// let previous = Snapshot::load("AS-FOO.json")?;
+/// let diff = previous.diff(&snapshot);
+/// for range in diff.added().ranges() {
+///     println!("+ {range}");
+/// }
+/// for range in diff.removed().ranges() {
+///     println!("- {range}");
+/// }
+/// snapshot.save("AS-FOO.json")?;
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    expression: String,
+    timestamp: SystemTime,
+    serials: Serials,
+    prefixes: PrefixSet<Any>,
+}
+
+/// The on-disk representation of a [`Snapshot`].
+#[derive(Debug, Serialize, Deserialize)]
+struct Format {
+    version: u64,
+    expression: String,
+    /// Seconds since the Unix epoch.
+    timestamp: u64,
+    serials: BTreeMap<String, Option<u64>>,
+    ipv4: Vec<String>,
+    ipv6: Vec<String>,
+}
+
+impl Snapshot {
+    /// Construct a new [`Snapshot`] of `prefixes`, the result of evaluating `expression` while
+    /// the IRR databases had `serials`, taken at the current time.
+    pub fn new<E: ToString>(expression: &E, prefixes: PrefixSet<Any>, serials: Serials) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        Self {
+            expression: expression.to_string(),
+            timestamp: UNIX_EPOCH + Duration::from_secs(now),
+            serials,
+            prefixes,
+        }
+    }
+
+    /// Get the expression that was evaluated.
+    #[must_use]
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    /// Get the time at which the snapshot was taken, to the nearest second.
+    #[must_use]
+    pub const fn timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    /// Get the serials of the IRR databases queried by the evaluation.
+    ///
+    /// These are empty if the evaluation did not query an IRRd server.
+    #[must_use]
+    pub const fn serials(&self) -> &Serials {
+        &self.serials
+    }
+
+    /// Get the evaluated prefixes.
+    #[must_use]
+    pub const fn prefixes(&self) -> &PrefixSet<Any> {
+        &self.prefixes
+    }
+
+    /// Find the prefixes that were added and removed between this snapshot and `newer`.
+    #[must_use]
+    pub fn diff(&self, newer: &Self) -> Difference {
+        Difference::between(&self.prefixes, &newer.prefixes)
+    }
+
+    /// Load the snapshot saved to the file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::Snapshot`] is returned if the file cannot be read. See also
+    /// [`Snapshot::from_json`].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        tracing::info!("loading evaluation snapshot from {}", path.display());
+        let file = File::open(path).map_err(|err| Error::Snapshot(path.to_path_buf(), err))?;
+        Self::from_json(BufReader::new(file))
+    }
+
+    /// Read a snapshot from its JSON representation.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::ParseSnapshot`] is returned if the JSON is malformed, an
+    /// [`Error::SnapshotVersion`] if it was written in a newer version of the format, or an
+    /// [`Error::InvalidSnapshot`] if any prefix range is invalid.
+    pub fn from_json<R: Read>(reader: R) -> Result<Self, Error> {
+        let value: serde_json::Value =
+            serde_json::from_reader(reader).map_err(Error::ParseSnapshot)?;
+        // check the version first, so that newer formats are not reported as malformed
+        match value.get("version").and_then(serde_json::Value::as_u64) {
+            Some(version) if version > VERSION => return Err(Error::SnapshotVersion(version)),
+            _ => {}
+        }
+        let format: Format = serde_json::from_value(value).map_err(Error::ParseSnapshot)?;
+        let prefixes = format
+            .ipv4
+            .iter()
+            .chain(&format.ipv6)
+            .map(|range| parse_range(range).ok_or_else(|| Error::InvalidSnapshot(range.clone())))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            expression: format.expression,
+            timestamp: UNIX_EPOCH + Duration::from_secs(format.timestamp),
+            serials: Serials::from_sources(format.serials),
+            prefixes,
+        })
+    }
+
+    /// Save the snapshot to the file at `path`, replacing any existing file.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::Snapshot`] is returned if the file cannot be written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        tracing::info!("saving evaluation snapshot to {}", path.display());
+        let to_err = |err| Error::Snapshot(path.to_path_buf(), err);
+        let mut writer = BufWriter::new(File::create(path).map_err(to_err)?);
+        self.to_json(&mut writer)?;
+        writer.flush().map_err(to_err)
+    }
+
+    /// Write the JSON representation of the snapshot to `writer`.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::ParseSnapshot`] is returned if the snapshot cannot be written.
+    pub fn to_json<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let (mut ipv4, mut ipv6) = (Vec::new(), Vec::new());
+        for range in self.prefixes.ranges() {
+            match range {
+                any::PrefixRange::Ipv4(range) => ipv4.push(range),
+                any::PrefixRange::Ipv6(range) => ipv6.push(range),
+            }
+        }
+        // order ranges by address, so that snapshots are also readable with a text diff
+        ipv4.sort_by_key(|range| (range.prefix().network(), range.lower(), range.upper()));
+        ipv6.sort_by_key(|range| (range.prefix().network(), range.lower(), range.upper()));
+        let format = Format {
+            version: VERSION,
+            expression: self.expression.clone(),
+            timestamp: self
+                .timestamp
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            serials: self
+                .serials
+                .iter()
+                .map(|(source, serial)| (source.to_owned(), serial))
+                .collect(),
+            ipv4: ipv4.iter().map(ToString::to_string).collect(),
+            ipv6: ipv6.iter().map(ToString::to_string).collect(),
+        };
+        serde_json::to_writer_pretty(&mut writer, &format).map_err(Error::ParseSnapshot)?;
+        writeln!(writer).map_err(|err| Error::ParseSnapshot(serde_json::Error::io(err)))
+    }
+}
+
+/// Parse a prefix range written in the `prefix/length^lower-upper` form in which it is
+/// displayed.
+fn parse_range(range: &str) -> Option<any::PrefixRange> {
+    // the prefix range parser instead expects the form `prefix/length,lower,upper`
+    let (prefix, lengths) = range.split_once('^')?;
+    let (lower, upper) = lengths.split_once('-')?;
+    format!("{prefix},{lower},{upper}").parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use ip::{concrete::Afi, Prefix};
+
+    use super::*;
+
+    fn snapshot(prefixes: &[&str]) -> Snapshot {
+        Snapshot {
+            expression: "AS-FOO".to_owned(),
+            timestamp: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            serials: Serials::parse("RADB:Y:1-10:10\nRIPE:Y:-"),
+            prefixes: prefixes
+                .iter()
+                .map(|prefix| prefix.parse::<Prefix<Any>>().unwrap())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn round_trip_and_diff() {
+        let old = snapshot(&["192.0.2.0/24", "2001:db8::/32"]);
+        let mut json = Vec::new();
+        old.to_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains("\"version\": 1"));
+        assert!(json.contains("\"timestamp\": 1700000000"));
+        assert!(json.contains("\"192.0.2.0/24^24-24\""));
+        assert_eq!(Snapshot::from_json(json.as_bytes()).unwrap(), old);
+
+        let new = snapshot(&["198.51.100.0/24", "192.0.2.0/24", "192.0.2.0/25"]);
+        let mut json = Vec::new();
+        new.to_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        let order: Vec<_> = ["192.0.2.0/24^", "192.0.2.0/25^", "198.51.100.0/24^"]
+            .iter()
+            .map(|range| json.find(range).unwrap())
+            .collect();
+        assert!(order.windows(2).all(|pair| pair[0] < pair[1]));
+        let diff = old.diff(&new);
+        assert!(diff.afi(Afi::Ipv4).removed().ranges().next().is_none());
+        assert_eq!(diff.afi(Afi::Ipv4).added().ranges().count(), 2);
+        assert_eq!(diff.afi(Afi::Ipv6).removed().ranges().count(), 1);
+
+        let newer = json.replace("\"version\": 1", "\"version\": 2");
+        assert!(matches!(
+            Snapshot::from_json(newer.as_bytes()),
+            Err(Error::SnapshotVersion(2))
+        ));
+    }
+}
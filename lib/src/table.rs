@@ -0,0 +1,335 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    hash::Hash,
+    iter::once,
+    mem,
+    sync::Arc,
+};
+
+use ip::{Any, PrefixSet};
+
+use rpsl::{
+    expr::{
+        eval::{Evaluate, Evaluator, Resolver},
+        MpFilterExpr,
+    },
+    names::{AsSet, AutNum, FilterSet, RouteSet},
+    primitive::PeerAs,
+};
+
+use crate::{
+    error::Error,
+    limits::{Budget, Limits},
+    query::log_sunk_error,
+    report::Report,
+};
+
+/// A collection of RPSL names to be resolved.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Names {
+    pub(crate) filter_sets: HashSet<FilterSet>,
+    pub(crate) as_sets: HashSet<AsSet>,
+    pub(crate) route_sets: HashSet<RouteSet>,
+    pub(crate) autnums: HashSet<AutNum>,
+}
+
+impl Names {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.filter_sets.is_empty()
+            && self.as_sets.is_empty()
+            && self.route_sets.is_empty()
+            && self.autnums.is_empty()
+    }
+
+    /// Add each of the names in `other` to the collection.
+    pub(crate) fn extend(&mut self, other: &Self) {
+        self.filter_sets.extend(other.filter_sets.iter().cloned());
+        self.as_sets.extend(other.as_sets.iter().cloned());
+        self.route_sets.extend(other.route_sets.iter().cloned());
+        self.autnums.extend(other.autnums.iter().copied());
+    }
+}
+
+/// The output of resolving an RPSL name, along with any non-fatal errors encountered in the
+/// process.
+///
+/// The errors are shared, so that they can be copied to every table that uses the entry.
+#[derive(Debug, Default)]
+pub(crate) struct Entry<T> {
+    pub(crate) output: T,
+    pub(crate) errors: Vec<Arc<Error>>,
+}
+
+/// A table of resolved RPSL names.
+///
+/// [`Table`] implements [`Evaluator`] by looking up each name in the table. Names that are not
+/// yet present are recorded, and resolve to an empty set in the meantime, so that they can be
+/// fetched before the next evaluation attempt.
+///
+/// Because names that are discovered during one attempt (e.g. via a `filter-set`) may lead to
+/// the discovery of more names in the next, evaluation is complete only once an attempt records
+/// no missing names.
+#[derive(Debug, Default)]
+pub(crate) struct Table {
+    pub(crate) filter_sets: HashMap<FilterSet, Entry<Option<MpFilterExpr>>>,
+    pub(crate) as_sets: HashMap<AsSet, Entry<PrefixSet<Any>>>,
+    pub(crate) route_sets: HashMap<RouteSet, Entry<PrefixSet<Any>>>,
+    pub(crate) autnums: HashMap<AutNum, Entry<PrefixSet<Any>>>,
+    peer_as: Option<AutNum>,
+    strict: bool,
+    misses: Names,
+    logged: HashSet<String>,
+    report: Report,
+    context: Option<String>,
+    pub(crate) budget: Budget,
+}
+
+impl Table {
+    pub(crate) fn new(peer_as: Option<AutNum>, strict: bool, limits: Limits) -> Self {
+        Self {
+            peer_as,
+            strict,
+            budget: Budget::new(limits),
+            ..Self::default()
+        }
+    }
+
+    /// Take the [`Report`] of the non-fatal errors encountered during evaluation.
+    pub(crate) fn take_report(&mut self) -> Report {
+        mem::take(&mut self.report)
+    }
+
+    /// Take the names that were not found in the table during previous evaluation attempts.
+    pub(crate) fn take_misses(&mut self) -> Names {
+        mem::take(&mut self.misses)
+    }
+
+    /// Get those of `names` that are not present in the table.
+    pub(crate) fn absent(&self, names: Names) -> Names {
+        Names {
+            filter_sets: absent(&self.filter_sets, names.filter_sets),
+            as_sets: absent(&self.as_sets, names.as_sets),
+            route_sets: absent(&self.route_sets, names.route_sets),
+            autnums: absent(&self.autnums, names.autnums),
+        }
+    }
+
+    /// Copy the entries for those of `names` that are present in `source` into the table.
+    ///
+    /// The errors in each entry are copied along with its output, so that every table into which
+    /// the entry is copied handles them.
+    pub(crate) fn copy_from(&mut self, source: &Self, names: &Names) {
+        copy(
+            &mut self.filter_sets,
+            &source.filter_sets,
+            &names.filter_sets,
+        );
+        copy(&mut self.as_sets, &source.as_sets, &names.as_sets);
+        copy(&mut self.route_sets, &source.route_sets, &names.route_sets);
+        copy(&mut self.autnums, &source.autnums, &names.autnums);
+    }
+
+    fn collect_entry<T, N: Display>(
+        &mut self,
+        name: &N,
+        entry: Option<(T, Vec<Arc<Error>>)>,
+    ) -> Result<Option<T>, Error> {
+        self.context = Some(name.to_string());
+        let result = entry
+            .map(|(output, errors)| {
+                errors
+                    .into_iter()
+                    .try_for_each(|err| self.collect_result::<(), _, Error>(Err(err)).map(|_| ()))
+                    .map(|()| output)
+            })
+            .transpose();
+        self.context = None;
+        result
+    }
+
+    /// Count the prefixes resolved from `name` against the evaluation limits, if it was found.
+    fn add_prefixes<N: Display>(
+        &mut self,
+        name: &N,
+        set: Option<PrefixSet<Any>>,
+    ) -> Result<PrefixSet<Any>, Error> {
+        set.map_or_else(
+            || Ok(PrefixSet::<Any>::default()),
+            |set| self.budget.add_prefixes(name, &set).map(|()| set),
+        )
+    }
+}
+
+/// Look up `key` in `entries`, recording it in `misses` if not found.
+///
+/// Any errors in a found entry are taken, so that they are only handled once.
+fn lookup<K, T>(
+    entries: &mut HashMap<K, Entry<T>>,
+    misses: &mut HashSet<K>,
+    key: &K,
+) -> Option<(T, Vec<Arc<Error>>)>
+where
+    K: Clone + Eq + Hash,
+    T: Clone,
+{
+    if let Some(entry) = entries.get_mut(key) {
+        Some((entry.output.clone(), mem::take(&mut entry.errors)))
+    } else {
+        _ = misses.insert(key.clone());
+        None
+    }
+}
+
+/// Filter out those of `keys` that are present in `entries`.
+fn absent<K, T>(entries: &HashMap<K, Entry<T>>, keys: HashSet<K>) -> HashSet<K>
+where
+    K: Eq + Hash,
+{
+    keys.into_iter()
+        .filter(|key| !entries.contains_key(key))
+        .collect()
+}
+
+/// Copy the entries for those of `keys` that are present in `source` into `entries`.
+fn copy<K, T>(entries: &mut HashMap<K, Entry<T>>, source: &HashMap<K, Entry<T>>, keys: &HashSet<K>)
+where
+    K: Clone + Eq + Hash,
+    T: Clone,
+{
+    for key in keys {
+        if let Some(entry) = source.get(key) {
+            _ = entries.insert(
+                key.clone(),
+                Entry {
+                    output: entry.output.clone(),
+                    errors: entry.errors.clone(),
+                },
+            );
+        }
+    }
+}
+
+impl<'a> Evaluator<'a> for Table {
+    type Output<T> = <T as Evaluate<'a, Self>>::Output
+    where
+        T: Evaluate<'a, Self>;
+
+    type Error = Error;
+
+    fn finalise<T>(&mut self, output: T::Output) -> Result<Self::Output<T>, Self::Error>
+    where
+        T: Evaluate<'a, Self>,
+    {
+        Ok(output)
+    }
+
+    fn sink_error(&mut self, err: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+        if self.strict {
+            return false;
+        }
+        // the same error may be encountered during several evaluation attempts
+        if self.logged.insert(format!("{err:#}")) {
+            log_sunk_error(err);
+        }
+        self.report.record(self.context.as_deref(), err);
+        true
+    }
+}
+
+impl Resolver<'_, FilterSet, MpFilterExpr> for Table {
+    type IError = Error;
+
+    fn resolve(&mut self, filter_set: &FilterSet) -> Result<MpFilterExpr, Self::IError> {
+        let entry = lookup(
+            &mut self.filter_sets,
+            &mut self.misses.filter_sets,
+            filter_set,
+        );
+        self.collect_entry(filter_set, entry)?
+            .flatten()
+            .map_or_else(|| Ok("NOT ANY".parse()?), Ok)
+    }
+}
+
+impl Resolver<'_, AsSet, PrefixSet<Any>> for Table {
+    type IError = Error;
+
+    fn resolve(&mut self, as_set: &AsSet) -> Result<PrefixSet<Any>, Self::IError> {
+        let entry = lookup(&mut self.as_sets, &mut self.misses.as_sets, as_set);
+        let set = self.collect_entry(as_set, entry)?;
+        self.add_prefixes(as_set, set)
+    }
+}
+
+impl Resolver<'_, RouteSet, PrefixSet<Any>> for Table {
+    type IError = Error;
+
+    fn resolve(&mut self, route_set: &RouteSet) -> Result<PrefixSet<Any>, Self::IError> {
+        let entry = lookup(&mut self.route_sets, &mut self.misses.route_sets, route_set);
+        let set = self.collect_entry(route_set, entry)?;
+        self.add_prefixes(route_set, set)
+    }
+}
+
+impl Resolver<'_, AutNum, PrefixSet<Any>> for Table {
+    type IError = Error;
+
+    fn resolve(&mut self, autnum: &AutNum) -> Result<PrefixSet<Any>, Self::IError> {
+        self.budget.add_autnums(once(*autnum))?;
+        let entry = lookup(&mut self.autnums, &mut self.misses.autnums, autnum);
+        let set = self.collect_entry(autnum, entry)?;
+        self.add_prefixes(autnum, set)
+    }
+}
+
+impl Resolver<'_, PeerAs, PrefixSet<Any>> for Table {
+    type IError = Error;
+
+    fn resolve(&mut self, _: &PeerAs) -> Result<PrefixSet<Any>, Self::IError> {
+        let autnum = self.peer_as.ok_or(Error::PeerAs)?;
+        <Self as Resolver<'_, AutNum, PrefixSet<Any>>>::resolve(self, &autnum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_shares_errors() {
+        let as_set: AsSet = "AS-FOO".parse().unwrap();
+        let mut source = Table::default();
+        _ = source.as_sets.insert(
+            as_set.clone(),
+            Entry {
+                output: PrefixSet::<Any>::default(),
+                errors: vec![Arc::new(Error::PeerAs)],
+            },
+        );
+        let names = Names {
+            as_sets: once(as_set.clone()).collect(),
+            route_sets: once("RS-FOO".parse().unwrap()).collect(),
+            ..Names::default()
+        };
+        let absent = source.absent(names.clone());
+        assert!(absent.as_sets.is_empty());
+        assert_eq!(absent.route_sets.len(), 1);
+
+        let mut first = Table::default();
+        let mut second = Table::default();
+        first.copy_from(&source, &names);
+        second.copy_from(&source, &names);
+        assert_eq!(first.as_sets[&as_set].errors.len(), 1);
+        assert_eq!(second.as_sets[&as_set].errors.len(), 1);
+        assert!(second.route_sets.is_empty());
+
+        // in strict mode, every consumer of the entry fails
+        for mut table in [first, second] {
+            table.strict = true;
+            let err = <Table as Resolver<'_, AsSet, PrefixSet<Any>>>::resolve(&mut table, &as_set)
+                .unwrap_err();
+            assert!(matches!(err, Error::Shared(ref err) if matches!(**err, Error::PeerAs)));
+        }
+    }
+}